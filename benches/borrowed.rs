@@ -0,0 +1,81 @@
+//! the allocations and time of the names-only borrowed parse against the owned one,
+//! run with `cargo bench --bench borrowed`
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use pmx_parser::borrowed::PmxRef;
+use pmx_parser::header::Encoding;
+use pmx_parser::material::Material;
+use pmx_parser::morph::{ControlPanel, Morph, MorphData};
+use pmx_parser::pmx::Pmx;
+use pmx_parser::{pmx_read, pmx_write_with_encoding};
+
+const ELEMENTS: usize = 20_000;
+
+/// the system allocator counting its allocations
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// the allocations `f` makes
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    drop(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// a model of [`ELEMENTS`] textures, materials and morphs, all of them named
+fn model() -> Pmx {
+    let mut pmx = Pmx::default();
+    for i in 0..ELEMENTS {
+        pmx.textures.textures.push(format!("tex/{i}.png"));
+        pmx.materials
+            .materials
+            .push(Material::new(format!("material {i}")));
+        pmx.morphs.morphs.push(Morph {
+            name: format!("morph {i}"),
+            name_en: format!("morph {i}"),
+            control_panel: ControlPanel::BottomRight,
+            morph_data: MorphData::Group(Vec::new()),
+        });
+    }
+    pmx
+}
+
+fn parse(c: &mut Criterion) {
+    let mut bytes = Vec::new();
+    pmx_write_with_encoding(&mut bytes, &model(), 2.1, Encoding::Utf8).unwrap();
+    println!(
+        "allocations: pmx_read {}, PmxRef::parse {}",
+        allocations(|| pmx_read(&mut bytes.as_slice()).unwrap()),
+        allocations(|| PmxRef::parse(&bytes).unwrap()),
+    );
+
+    let mut group = c.benchmark_group("20k named elements");
+    group.bench_function("pmx_read", |b| {
+        b.iter(|| pmx_read(&mut bytes.as_slice()).unwrap())
+    });
+    group.bench_function("PmxRef::parse", |b| {
+        b.iter(|| PmxRef::parse(&bytes).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
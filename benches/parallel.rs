@@ -0,0 +1,100 @@
+//! the parallel readers against the sequential ones on a large model,
+//! run with `cargo bench --features rayon`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use pmx_parser::bone::{Bone, BoneConnection};
+use pmx_parser::header::Header;
+use pmx_parser::material::Material;
+use pmx_parser::pmx::Pmx;
+use pmx_parser::vertex::{Skin, Vertices};
+use pmx_parser::{pmx_from_slice_parallel, pmx_read, pmx_write_to_vec};
+
+const VERTICES: usize = 500_000;
+const BONES: i32 = 500;
+
+/// a model of [`VERTICES`] vertices, every other one of them a BDEF2 one,
+/// a triangle for every three and a chain of [`BONES`] bones
+fn model() -> Pmx {
+    let mut pmx = Pmx::default();
+    pmx.vertices.ext_vec4s = vec![Vec::new()];
+    for i in 0..VERTICES {
+        let x = i as f32;
+        pmx.vertices.position3s.extend([x, 1.0, 2.0]);
+        pmx.vertices.normal3s.extend([0.0, 0.0, 1.0]);
+        pmx.vertices.uv2s.extend([x / VERTICES as f32, 0.5]);
+        pmx.vertices.ext_vec4s[0].extend([0.0, 0.0, 0.0, x]);
+        let bone = (i % BONES as usize) as i32;
+        pmx.vertices.skins.push(if i % 2 == 0 {
+            Skin::BDEF1 { bone_index: bone }
+        } else {
+            Skin::BDEF2 {
+                bone_index_1: bone,
+                bone_index_2: (bone + 1) % BONES,
+                bone_weight_1: 0.5,
+            }
+        });
+        pmx.vertices.edges.push(1.0);
+    }
+    let elements = (VERTICES / 3 * 3) as u32;
+    pmx.elements.element_indices = (0..elements).collect();
+    pmx.materials.materials = vec![Material {
+        element_count: elements,
+        ..Material::new("material")
+    }];
+    pmx.bones.bones = (0..BONES)
+        .map(|i| Bone {
+            name: format!("bone {i}"),
+            name_en: String::new(),
+            position: [0.0, i as f32, 0.0],
+            parent_bone_index: i - 1,
+            priority: 0,
+            connect: BoneConnection::BoneIndex(-1),
+            rotatable: true,
+            translatable: false,
+            is_visible: true,
+            enable: true,
+            inherit_local: false,
+            inherit_rotate_or_translation: None,
+            fixed_axis: None,
+            local_axis: None,
+            physics_after_deform: false,
+            external_parent_bone_index: None,
+            ik: None,
+            unknown_0040: false,
+            unknown_4000: false,
+            unknown_8000: false,
+        })
+        .collect();
+    pmx
+}
+
+fn read(c: &mut Criterion) {
+    let pmx = model();
+    let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+    let mut group = c.benchmark_group("model");
+    group.sample_size(10);
+    group.bench_function("pmx_read", |b| {
+        b.iter(|| pmx_read(&mut bytes.as_slice()).unwrap())
+    });
+    group.bench_function("pmx_from_slice_parallel", |b| {
+        b.iter(|| pmx_from_slice_parallel(&bytes).unwrap())
+    });
+    group.finish();
+
+    let header = Header::from_best(2.1, &pmx);
+    let mut bytes = Vec::new();
+    pmx.vertices.write(&header, &mut bytes).unwrap();
+    let mut group = c.benchmark_group("500k vertices");
+    group.sample_size(10);
+    group.bench_function("Vertices::read", |b| {
+        b.iter(|| Vertices::read(&header, &mut bytes.as_slice()).unwrap())
+    });
+    group.bench_function("Vertices::read_parallel", |b| {
+        b.iter(|| Vertices::read_parallel(&header, &mut bytes.as_slice()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, read);
+criterion_main!(benches);
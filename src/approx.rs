@@ -0,0 +1,82 @@
+//! compare models with a tolerance on the float fields
+
+/// equality with every float compared within an absolute tolerance
+/// and every other field compared exactly.
+///
+/// float arithmetic like a transform and its inverse rarely gives back the same bits,
+/// this tells such results apart from real changes.
+/// `NaN` is not equal to anything, like with `==`.
+pub trait ApproxEq {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+impl ApproxEq for f32 {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        // the equality covers infinities of the same sign
+        self == other || (self - other).abs() <= epsilon
+    }
+}
+
+impl<T: ApproxEq + ?Sized> ApproxEq for &T {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (**self).approx_eq(*other, epsilon)
+    }
+}
+
+impl<T: ApproxEq, const N: usize> ApproxEq for [T; N] {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.as_slice().approx_eq(other.as_slice(), epsilon)
+    }
+}
+
+impl<T: ApproxEq> ApproxEq for [T] {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.approx_eq(b, epsilon))
+    }
+}
+
+impl<T: ApproxEq> ApproxEq for Vec<T> {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.as_slice().approx_eq(other.as_slice(), epsilon)
+    }
+}
+
+impl<T: ApproxEq> ApproxEq for Option<T> {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.approx_eq(b, epsilon),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<A: ApproxEq, B: ApproxEq> ApproxEq for (A, B) {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.0.approx_eq(&other.0, epsilon) && self.1.approx_eq(&other.1, epsilon)
+    }
+}
+
+crate::kits::impl_approx_eq_exact!(bool, u8, u16, u32, i32, String);
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::sample;
+
+    #[test]
+    fn positions_a_little_apart_are_approximately_equal() {
+        let pmx = sample();
+        let mut moved = sample();
+        moved.vertices.position3s[3 + 1] += 1e-7;
+        moved.bones[2].position[0] += 1e-7;
+        assert_ne!(moved, pmx);
+        assert!(moved.approx_eq(&pmx, 1e-6));
+        assert!(!moved.approx_eq(&pmx, 0.0));
+
+        moved.bones[0].name.push('x');
+        assert!(!moved.approx_eq(&pmx, 1.0));
+        moved = sample();
+        moved.vertices.position3s[0] = f32::NAN;
+        assert!(!moved.approx_eq(&moved.clone(), 1.0));
+    }
+}
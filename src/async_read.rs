@@ -0,0 +1,360 @@
+//! read a model from a tokio stream
+
+use std::io::{ErrorKind, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::bone::{Bone, Bones};
+use crate::display_frame::{DisplayFrame, DisplayFrames};
+use crate::element_index::ElementIndices;
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::joint::{Joint, Joints};
+use crate::material::{Material, Materials};
+use crate::model_info::ModelInfo;
+use crate::morph::{Morph, Morphs};
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::rigid_body::{RigidBodies, RigidBody};
+use crate::soft_body::{SoftBodies, SoftBody};
+use crate::texture::Textures;
+use crate::vertex::Vertices;
+
+/// the bytes buffered so far read like a slice,
+/// noting how many more bytes a reader asked for when they ran out
+struct Probe<'a> {
+    bytes: &'a [u8],
+    missing: usize,
+}
+
+impl Read for Probe<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.bytes.is_empty() && !buf.is_empty() {
+            // a plain read does not tell how much it needs, one more byte it surely does
+            self.missing = 1;
+        }
+        Read::read(&mut self.bytes, buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        if buf.len() > self.bytes.len() {
+            self.missing = buf.len() - self.bytes.len();
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Read::read_exact(&mut self.bytes, buf)
+    }
+}
+
+/// an async stream buffering a section at a time for the sync readers,
+/// taking from the stream only the bytes they ask for, so string limits hold
+/// and whatever follows the model is left in the stream
+struct SectionReader<'r, R> {
+    read: &'r mut R,
+    buffer: Vec<u8>,
+    offset: u64,
+}
+
+impl<'r, R: AsyncRead + Unpin> SectionReader<'r, R> {
+    fn new(read: &'r mut R) -> Self {
+        Self {
+            read,
+            buffer: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// take `length` more bytes from the stream into the buffer,
+    /// growing it with the data actually received
+    async fn fetch(&mut self, length: u64) -> Result<(), PmxError> {
+        let taken = (&mut *self.read)
+            .take(length)
+            .read_to_end(&mut self.buffer)
+            .await?;
+        self.offset += taken as u64;
+        if (taken as u64) < length {
+            return Err(PmxError::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    /// read the next item with a sync reader, fetching the bytes it runs out of and retrying
+    async fn item<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Probe) -> Result<T, PmxError>,
+    ) -> Result<T, PmxError> {
+        let start = self.buffer.len();
+        loop {
+            let mut probe = Probe {
+                bytes: &self.buffer[start..],
+                missing: 0,
+            };
+            match f(&mut probe) {
+                Ok(item) => return Ok(item),
+                Err(_) if probe.missing > 0 => {
+                    let missing = probe.missing as u64;
+                    self.fetch(missing).await?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// read a single item on its own, like the header or the model info
+    async fn single<T>(
+        &mut self,
+        f: impl FnMut(&mut Probe) -> Result<T, PmxError>,
+    ) -> Result<T, PmxError> {
+        self.buffer.clear();
+        self.item(f).await
+    }
+
+    /// buffer a counted section item by item, then parse the buffer with `read`
+    async fn section<T, I>(
+        &mut self,
+        mut item: impl FnMut(&mut Probe) -> Result<I, PmxError>,
+        read: impl FnOnce(&mut &[u8]) -> Result<T, PmxError>,
+    ) -> Result<T, PmxError> {
+        self.buffer.clear();
+        let count = self
+            .item(|read| Ok(read.read_u32::<LittleEndian>()?))
+            .await?;
+        for _ in 0..count {
+            self.item(&mut item).await?;
+        }
+        read(&mut self.buffer.as_slice())
+    }
+
+    /// buffer the element section, whose length follows from its count
+    async fn elements(
+        &mut self,
+        header: &Header,
+        options: &ReadOptions,
+    ) -> Result<ElementIndices, PmxError> {
+        self.buffer.clear();
+        let count = self
+            .item(|read| Ok(read.read_u32::<LittleEndian>()?))
+            .await?;
+        self.fetch(count as u64 * header.vertex_index.bytes())
+            .await?;
+        ElementIndices::read_checked(header, options, &mut self.buffer.as_slice())
+    }
+
+    async fn pmx(&mut self, header: &Header, options: &ReadOptions) -> Result<Pmx, PmxError> {
+        let info = self
+            .single(|read| ModelInfo::read(header, options, read))
+            .await
+            .map_err(|e| e.in_section("model info"))?;
+        let vertices = self
+            .section(
+                |read| Vertices::read_count(header, 1, read),
+                |read| Vertices::read(header, read),
+            )
+            .await
+            .map_err(|e| e.in_section("vertices"))?;
+        let elements = self
+            .elements(header, options)
+            .await
+            .map_err(|e| e.in_section("elements"))?;
+        let textures = self
+            .section(
+                |read| header.encoding.read_with(read, options),
+                |read| Textures::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("textures"))?;
+        let materials = self
+            .section(
+                |read| Material::read(header, options, read),
+                |read| Materials::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("materials"))?;
+        let bones = self
+            .section(
+                |read| Bone::read(header, options, read),
+                |read| Bones::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("bones"))?;
+        let morphs = self
+            .section(
+                |read| Morph::read(header, options, read),
+                |read| Morphs::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("morphs"))?;
+        let display_frames = self
+            .section(
+                |read| DisplayFrame::read(header, options, read),
+                |read| DisplayFrames::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("display frames"))?;
+        let rigid_bodies = self
+            .section(
+                |read| RigidBody::read(header, options, read),
+                |read| RigidBodies::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("rigid bodies"))?;
+        let joints = self
+            .section(
+                |read| Joint::read(header, options, read),
+                |read| Joints::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("joints"))?;
+        let soft_bodies = if header.supports_soft_bodies() {
+            self.section(
+                |read| SoftBody::read(header, options, read),
+                |read| SoftBodies::read(header, options, read),
+            )
+            .await
+            .map_err(|e| e.in_section("soft bodies"))?
+        } else {
+            SoftBodies::default()
+        };
+        let mut pmx = Pmx {
+            info,
+            vertices,
+            elements,
+            textures,
+            materials,
+            bones,
+            morphs,
+            display_frames,
+            rigid_bodies,
+            joints,
+            soft_bodies,
+            trailing: Vec::new(),
+        };
+        if options.keep_trailing {
+            self.read.read_to_end(&mut pmx.trailing).await?;
+        }
+        if options.reject_non_finite {
+            pmx.reject_non_finite()?;
+        }
+        Ok(pmx)
+    }
+}
+
+impl Header {
+    /// read a header like [`Header::read`] from an async stream
+    pub async fn read_async<R: AsyncRead + Unpin>(read: &mut R) -> Result<Self, PmxError> {
+        SectionReader::new(read)
+            .single(|read| Header::read(read))
+            .await
+    }
+}
+
+impl Pmx {
+    /// read a model like [`Pmx::read`] from an async stream.
+    ///
+    /// every section is taken from the stream item by item as the sync readers ask for
+    /// the bytes, checking the string limits of the options before reading a string,
+    /// and buffered until it is parsed, so the stream is left right after the model
+    pub async fn read_async<R: AsyncRead + Unpin>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        SectionReader::new(read).pmx(header, options).await
+    }
+}
+
+/// read a model from an async stream like [`crate::pmx_read`], see [`Pmx::read_async`]
+pub async fn pmx_read_async<R: AsyncRead + Unpin>(read: &mut R) -> Result<(Header, Pmx), PmxError> {
+    pmx_read_async_with(read, &ReadOptions::default()).await
+}
+
+/// read a model from an async stream with non-default options, see [`pmx_read_async`]
+pub async fn pmx_read_async_with<R: AsyncRead + Unpin>(
+    read: &mut R,
+    options: &ReadOptions,
+) -> Result<(Header, Pmx), PmxError> {
+    let mut read = SectionReader::new(read);
+    let result = async {
+        let header = read
+            .single(|read| Header::read(read))
+            .await
+            .map_err(|e| e.in_section("header"))?;
+        let pmx = read.pmx(&header, options).await?;
+        Ok::<_, PmxError>((header, pmx))
+    }
+    .await;
+    result.map_err(|e| PmxError::At {
+        offset: read.offset,
+        source: Box::new(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::BufReader;
+
+    use super::*;
+    use crate::testing::sample;
+    use crate::{pmx_read, pmx_write_to_vec};
+
+    #[tokio::test]
+    async fn reads_like_the_sync_reader() {
+        let bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let mut read = BufReader::new(bytes.as_slice());
+        let model = pmx_read_async(&mut read).await.unwrap();
+        assert_eq!(model, pmx_read(&mut bytes.as_slice()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn leaves_what_follows_the_model_in_the_stream() {
+        let mut bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        bytes.extend_from_slice(b"after");
+        let mut read = BufReader::new(bytes.as_slice());
+        let (_, pmx) = pmx_read_async(&mut read).await.unwrap();
+        assert_eq!(pmx, sample());
+
+        let mut rest = Vec::new();
+        read.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"after");
+    }
+
+    #[tokio::test]
+    async fn checks_the_string_limit_before_reading_the_string() {
+        let mut bytes = pmx_write_to_vec(&Pmx::default(), 2.0).unwrap();
+        // the model name claims 64 bytes, the limit is 16
+        bytes.truncate(17);
+        bytes.extend_from_slice(&64_u32.to_le_bytes());
+        bytes.extend_from_slice(&[b'a'; 64]);
+        let options = ReadOptions {
+            max_string_bytes: 16,
+            ..ReadOptions::default()
+        };
+        let mut read = bytes.as_slice();
+        match pmx_read_async_with(&mut read, &options).await {
+            Err(PmxError::At { offset, source }) => {
+                assert!(matches!(*source, PmxError::StringTooLong(64)));
+                assert_eq!(offset, 21);
+            }
+            e => panic!("{e:?}"),
+        }
+        assert_eq!(read.len(), 64);
+    }
+
+    #[tokio::test]
+    async fn names_the_section_a_truncated_stream_ends_in() {
+        let bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let mut read = &bytes[..bytes.len() - 3];
+        match pmx_read_async(&mut read).await {
+            Err(PmxError::At { offset, source }) => {
+                assert!(matches!(
+                    *source,
+                    PmxError::Truncated {
+                        section: "soft bodies"
+                    }
+                ));
+                assert_eq!(offset, bytes.len() as u64 - 3);
+            }
+            e => panic!("{e:?}"),
+        }
+    }
+}
@@ -1,13 +1,71 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fmt::{Debug, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crate::BoneIndex;
 
+use crate::approx::ApproxEq;
 use crate::error::PmxError;
-use crate::header::Header;
-use crate::kits::{read_bool, read_f32x3, read_vec, write_f32x3};
+use crate::header::{Header, IndexSize};
+use crate::kits::{
+    flip_z, flip_z_euler_range, impl_approx_eq, impl_approx_eq_exact, impl_collection,
+    impl_eq_ignoring_english, index_position, read_bool, read_f32x3, read_vec, sanitize, skip,
+    skip_vec, vec_size, write_f32x3, Affine,
+};
+use crate::options::ReadOptions;
 
+/// the bones of a model, which dereference to a slice of [`Bone`],
+/// so they index and iterate like one
+///
+/// ```
+/// use pmx_parser::bone::{Bone, BoneConnection, Bones};
+/// use pmx_parser::pmx::Pmx;
+///
+/// # fn bone(name: &str, parent_bone_index: i32) -> Bone {
+/// #     Bone {
+/// #         name: name.into(),
+/// #         name_en: String::new(),
+/// #         position: [0.0, parent_bone_index as f32 + 1.0, 0.0],
+/// #         parent_bone_index,
+/// #         priority: 0,
+/// #         connect: BoneConnection::BoneIndex(-1),
+/// #         rotatable: true,
+/// #         translatable: false,
+/// #         is_visible: true,
+/// #         enable: true,
+/// #         inherit_local: false,
+/// #         inherit_rotate_or_translation: None,
+/// #         fixed_axis: None,
+/// #         local_axis: None,
+/// #         physics_after_deform: false,
+/// #         external_parent_bone_index: None,
+/// #         ik: None,
+/// #         unknown_0040: false,
+/// #         unknown_4000: false,
+/// #         unknown_8000: false,
+/// #     }
+/// # }
+/// let mut bones = Bones {
+///     bones: vec![bone("center", -1), bone("upper body", 0), bone("neck", 1)],
+/// };
+/// for bone in &mut bones {
+///     bone.position[1] *= 2.0;
+/// }
+/// let names: Vec<&str> = bones.iter().map(|bone| bone.name.as_str()).collect();
+/// assert_eq!(names, ["center", "upper body", "neck"]);
+/// assert_eq!(bones[2].position, [0.0, 4.0, 0.0]);
+/// let mut parents = Vec::new();
+/// for bone in &bones {
+///     parents.push(bone.parent_bone_index);
+/// }
+/// assert_eq!(parents, [-1, 0, 1]);
+///
+/// let mut pmx = Pmx::default();
+/// pmx.bones = bones;
+/// assert_eq!(pmx.bones.len(), 3);
+/// ```
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Bones {
     pub bones: Vec<Bone>,
@@ -17,11 +75,18 @@ impl Bones {
     pub fn count(&self) -> u32 {
         self.bones.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            bones: read_vec(read, |read| Bone::read(header, read))?,
+            bones: read_vec(read, |read| Bone::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| Bone::skip(header, read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.bones {
@@ -29,8 +94,280 @@ impl Bones {
         }
         Ok(())
     }
+    /// the size [`Bones::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.bones, |i| i.serialized_size(header))
+    }
+
+    /// the parent of a bone, `None` for a root or an out of range parent
+    pub fn parent_of(&self, index: usize) -> Option<usize> {
+        usize::try_from(self.bones.get(index)?.parent_bone_index)
+            .ok()
+            .filter(|&i| i < self.bones.len())
+    }
+
+    /// the bones without a parent in this model, in bone order
+    pub fn roots(&self) -> Vec<usize> {
+        (0..self.bones.len())
+            .filter(|&i| self.parent_of(i).is_none())
+            .collect()
+    }
+
+    /// the children of every bone, in bone order
+    pub fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.bones.len()];
+        for index in 0..self.bones.len() {
+            if let Some(parent) = self.parent_of(index) {
+                children[parent].push(index);
+            }
+        }
+        children
+    }
+
+    /// whether every parent comes before its children, which mmd relies on
+    /// to deform the bones of the same deform layer in index order
+    pub fn is_topologically_sorted(&self) -> bool {
+        (0..self.bones.len()).all(|i| self.parent_of(i).is_none_or(|parent| parent < i))
+    }
+
+    /// the bones in an order putting every parent before its children,
+    /// taking the lowest index ready at each step so a sorted model keeps its order.
+    /// bones on a parent cycle and their descendants come last, in index order
+    pub(crate) fn topological_order(&self) -> Vec<usize> {
+        let children = self.children();
+        let mut placed = vec![false; self.bones.len()];
+        let mut ready: BinaryHeap<Reverse<usize>> = self.roots().into_iter().map(Reverse).collect();
+        let mut order = Vec::with_capacity(self.bones.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            placed[index] = true;
+            order.push(index);
+            ready.extend(children[index].iter().map(|&i| Reverse(i)));
+        }
+        order.extend((0..self.bones.len()).filter(|&i| !placed[i]));
+        order
+    }
+
+    /// the position of every bone relative to its parent, the position itself for a root.
+    ///
+    /// like the positions this is in the model space of mmd, left-handed with y up,
+    /// only the origin moves to the parent, the axes are not rotated by any local frame.
+    pub fn local_positions(&self) -> Vec<[f32; 3]> {
+        (0..self.bones.len())
+            .map(|index| {
+                let position = self.bones[index].position;
+                match self.parent_of(index) {
+                    Some(parent) => {
+                        let parent = self.bones[parent].position;
+                        std::array::from_fn(|i| position[i] - parent[i])
+                    }
+                    None => position,
+                }
+            })
+            .collect()
+    }
+
+    /// the model space position of every bone, summing [`Bones::local_positions`]
+    /// from the root down the parent chain.
+    ///
+    /// pmx stores model space positions, so this gives back every `position`
+    /// up to rounding as long as the parent chains are sound.
+    /// a chain running into a parent cycle is summed from the bone closing the cycle,
+    /// which is taken as a root.
+    pub fn world_positions(&self) -> Vec<[f32; 3]> {
+        let local = self.local_positions();
+        let mut world: Vec<Option<[f32; 3]>> = vec![None; self.bones.len()];
+        let mut on_path = vec![false; self.bones.len()];
+        let mut path = Vec::new();
+        for index in 0..self.bones.len() {
+            // walk up until a root, a bone already done or a bone already on the path
+            let mut current = Some(index);
+            while let Some(bone) = current.filter(|&i| world[i].is_none() && !on_path[i]) {
+                on_path[bone] = true;
+                path.push(bone);
+                current = self.parent_of(bone);
+            }
+            let mut base = match current {
+                Some(bone) => world[bone].unwrap_or(self.bones[bone].position),
+                None => [0.0; 3],
+            };
+            while let Some(bone) = path.pop() {
+                on_path[bone] = false;
+                base = if Some(bone) == current {
+                    self.bones[bone].position
+                } else {
+                    std::array::from_fn(|i| base[i] + local[bone][i])
+                };
+                world[bone] = Some(base);
+            }
+        }
+        world.into_iter().flatten().collect()
+    }
+
+    /// an indented tree of the bone names, two spaces per level.
+    ///
+    /// every root starts at column 0. bones on a parent cycle are listed after the roots,
+    /// and the bone closing a cycle is marked with `(cycle)` instead of being expanded again.
+    pub fn format_tree(&self) -> String {
+        let children = self.children();
+        let mut visited = vec![false; self.bones.len()];
+        let mut out = String::new();
+        let mut stack = Vec::new();
+        let mut starts = self.roots();
+        // the roots first, then whatever was left on a cycle
+        for index in std::iter::once(None).chain((0..self.bones.len()).map(Some)) {
+            if let Some(index) = index.filter(|&i| !visited[i]) {
+                // walk up until a bone repeats, that bone is on the cycle
+                let mut seen = vec![false; self.bones.len()];
+                let mut current = index;
+                while !seen[current] {
+                    seen[current] = true;
+                    current = self.parent_of(current).unwrap_or(current);
+                }
+                starts.push(current);
+            }
+            for start in starts.drain(..).rev() {
+                stack.push((start, 0));
+            }
+            while let Some((bone, depth)) = stack.pop() {
+                for _ in 0..depth {
+                    out.push_str("  ");
+                }
+                out.push_str(&self.bones[bone].name);
+                if visited[bone] {
+                    out.push_str(" (cycle)\n");
+                    continue;
+                }
+                out.push('\n');
+                visited[bone] = true;
+                for &child in children[bone].iter().rev() {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        out
+    }
+
+    /// copy the bone at `root` and all of its descendants as a standalone fragment.
+    ///
+    /// bone references are rebased to the fragment, the root's parent becomes `-1`.
+    /// references that leave the fragment are dropped:
+    /// tail bones become `-1`, inherit and ik are removed and out of fragment ik links are skipped.
+    /// an out of range `root` gives an empty fragment.
+    pub fn extract_subtree(&self, root: BoneIndex) -> Bones {
+        let count = self.bones.len();
+        let Some(root) = usize::try_from(root).ok().filter(|&i| i < count) else {
+            return Bones::default();
+        };
+        let mut remap = vec![None; count];
+        let mut next = 0;
+        for (index, new_index) in remap.iter_mut().enumerate() {
+            let mut current = index;
+            // limit the walk to `count` steps so a parent cycle can not hang
+            for _ in 0..count {
+                if current == root {
+                    *new_index = Some(next as BoneIndex);
+                    next += 1;
+                    break;
+                }
+                match usize::try_from(self.bones[current].parent_bone_index) {
+                    Ok(parent) if parent < count => current = parent,
+                    _ => break,
+                }
+            }
+        }
+        let rebase = |index: BoneIndex| -> Option<BoneIndex> {
+            usize::try_from(index)
+                .ok()
+                .and_then(|i| remap.get(i).copied().flatten())
+        };
+
+        let mut bones = Vec::with_capacity(next);
+        for (index, bone) in self.bones.iter().enumerate() {
+            if remap[index].is_none() {
+                continue;
+            }
+            let mut bone = bone.clone();
+            bone.parent_bone_index = if index == root {
+                -1
+            } else {
+                rebase(bone.parent_bone_index).unwrap_or(-1)
+            };
+            if let BoneConnection::BoneIndex(i) = &mut bone.connect {
+                *i = rebase(*i).unwrap_or(-1);
+            }
+            bone.inherit_rotate_or_translation =
+                bone.inherit_rotate_or_translation.and_then(|mut inherit| {
+                    inherit.bone_index = rebase(inherit.bone_index)?;
+                    Some(inherit)
+                });
+            bone.ik = bone.ik.and_then(|mut ik| {
+                ik.target_bone_index = rebase(ik.target_bone_index)?;
+                ik.links.retain_mut(|link| match rebase(link.bone_index) {
+                    Some(i) => {
+                        link.bone_index = i;
+                        true
+                    }
+                    None => false,
+                });
+                Some(ik)
+            });
+            bones.push(bone);
+        }
+        Bones { bones }
+    }
+
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        for bone in &mut self.bones {
+            bone.for_each_bone_index_mut(f);
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        self.bones.iter_mut().map(Bone::sanitize_floats).sum()
+    }
+
+    pub(crate) fn flip_z(&mut self) {
+        for bone in &mut self.bones {
+            flip_z(&mut bone.position);
+            if let BoneConnection::Position(offset) = &mut bone.connect {
+                flip_z(offset);
+            }
+            if let Some(axis) = &mut bone.fixed_axis {
+                flip_z(axis);
+            }
+            if let Some((x_axis, z_axis)) = &mut bone.local_axis {
+                flip_z(x_axis);
+                flip_z(z_axis);
+            }
+            if let Some(ik) = &mut bone.ik {
+                for link in &mut ik.links {
+                    if let Some((min, max)) = &mut link.angle_limit {
+                        flip_z_euler_range(min, max);
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn transform(&mut self, transform: &Affine) {
+        for bone in &mut self.bones {
+            transform.point(&mut bone.position);
+            if let BoneConnection::Position(offset) = &mut bone.connect {
+                transform.vector(offset);
+            }
+            if let Some(axis) = &mut bone.fixed_axis {
+                transform.direction(axis);
+            }
+            if let Some((x_axis, z_axis)) = &mut bone.local_axis {
+                transform.direction(x_axis);
+                transform.direction(z_axis);
+            }
+        }
+    }
 }
 
+impl_collection!(Bones, bones, Bone);
+
 #[derive(Clone, PartialEq)]
 pub struct Bone {
     pub name: String,
@@ -51,7 +388,6 @@ pub struct Bone {
     pub external_parent_bone_index: Option<BoneIndex>,
     pub ik: Option<Ik>,
     pub unknown_0040: bool,
-    pub unknown_2000: bool,
     pub unknown_4000: bool,
     pub unknown_8000: bool,
 }
@@ -69,9 +405,13 @@ impl Debug for Bone {
 }
 
 impl Bone {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
-        let name = header.encoding.read(read)?;
-        let name_en = header.encoding.read(read)?;
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        let name = header.encoding.read_with(read, options)?;
+        let name_en = header.encoding.read_with(read, options)?;
         let position = read_f32x3(read)?;
         let parent_bone_index = header.bone_index.read(read)?;
         let priority = read.read_u32::<LittleEndian>()?;
@@ -132,12 +472,51 @@ impl Bone {
                 None
             },
             unknown_0040: flags.contains(BoneFlags::UNKNOWN_0040),
-            unknown_2000: flags.contains(BoneFlags::UNKNOWN_2000),
             unknown_4000: flags.contains(BoneFlags::UNKNOWN_4000),
             unknown_8000: flags.contains(BoneFlags::UNKNOWN_8000),
         })
     }
 
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        let bone = header.bone_index.bytes();
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        skip(read, 12 + bone + 4)?;
+        let flags = BoneFlags::from_bits_retain(read.read_u16::<LittleEndian>()?);
+        let mut length = if flags.contains(BoneFlags::CONNECT_TO_OTHER_BONE) {
+            bone
+        } else {
+            12
+        };
+        if flags.intersects(BoneFlags::INHERIT_ROTATION | BoneFlags::INHERIT_TRANSLATION) {
+            length += bone + 4;
+        }
+        if flags.contains(BoneFlags::FIXED_AXIS) {
+            length += 12;
+        }
+        if flags.contains(BoneFlags::LOCAL_COORDINATE) {
+            length += 24;
+        }
+        if flags.contains(BoneFlags::EXTERNAL_PARENT_DEFORM) {
+            length += bone;
+        }
+        skip(read, length)?;
+        if flags.contains(BoneFlags::IK) {
+            skip(read, bone + 4 + 4)?;
+            skip_vec(read, |read| {
+                header.bone_index.skip(read)?;
+                if read_bool(read)? {
+                    skip(read, 24)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// write the bone, the optional blocks following the flags in the order of the format:
+    /// connection, inherit, fixed axis, local axis, external parent and ik.
+    /// [`Bone::inherit_local`] is only a flag and has no block
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -160,9 +539,9 @@ impl Bone {
         if let Some(i) = self.fixed_axis {
             write_f32x3(write, i)?;
         }
-        if let Some((min_angle, max_angle)) = self.local_axis {
-            write_f32x3(write, min_angle)?;
-            write_f32x3(write, max_angle)?;
+        if let Some((x_axis, z_axis)) = self.local_axis {
+            write_f32x3(write, x_axis)?;
+            write_f32x3(write, z_axis)?;
         }
         if let Some(i) = self.external_parent_bone_index {
             header.bone_index.write(write, i)?;
@@ -173,6 +552,25 @@ impl Bone {
         Ok(())
     }
 
+    /// the size [`Bone::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let bone = header.bone_index.bytes() as usize;
+        let connect = match self.connect {
+            BoneConnection::BoneIndex(_) => bone,
+            BoneConnection::Position(_) => 12,
+        };
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            // position, parent, priority and flags
+            + 12 + bone + 4 + 2
+            + connect
+            + self.inherit_rotate_or_translation.map_or(0, |_| bone + 4)
+            + self.fixed_axis.map_or(0, |_| 12)
+            + self.local_axis.map_or(0, |_| 24)
+            + self.external_parent_bone_index.map_or(0, |_| bone)
+            + self.ik.as_ref().map_or(0, |i| i.serialized_size(header))
+    }
+
     pub fn flags(&self) -> BoneFlags {
         let mut flags = BoneFlags::empty();
         if matches!(self.connect, BoneConnection::BoneIndex(_)) {
@@ -224,9 +622,6 @@ impl Bone {
         if self.external_parent_bone_index.is_some() {
             flags |= BoneFlags::EXTERNAL_PARENT_DEFORM;
         }
-        if self.unknown_2000 {
-            flags |= BoneFlags::UNKNOWN_2000;
-        }
         if self.unknown_4000 {
             flags |= BoneFlags::UNKNOWN_4000;
         }
@@ -235,10 +630,52 @@ impl Bone {
         }
         flags
     }
+
+    /// the parent, tail, inherit, ik target and ik link bone indices
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        f(&mut self.parent_bone_index);
+        if let BoneConnection::BoneIndex(i) = &mut self.connect {
+            f(i);
+        }
+        if let Some(inherit) = &mut self.inherit_rotate_or_translation {
+            f(&mut inherit.bone_index);
+        }
+        if let Some(ik) = &mut self.ik {
+            f(&mut ik.target_bone_index);
+            for link in &mut ik.links {
+                f(&mut link.bone_index);
+            }
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        let mut count = sanitize(&mut self.position);
+        if let BoneConnection::Position(offset) = &mut self.connect {
+            count += sanitize(offset);
+        }
+        if let Some(inherit) = &mut self.inherit_rotate_or_translation {
+            count += sanitize([&mut inherit.weight]);
+        }
+        if let Some(axis) = &mut self.fixed_axis {
+            count += sanitize(axis);
+        }
+        if let Some((x_axis, z_axis)) = &mut self.local_axis {
+            count += sanitize(x_axis.iter_mut().chain(z_axis));
+        }
+        if let Some(ik) = &mut self.ik {
+            count += sanitize([&mut ik.limit_angle]);
+            for link in &mut ik.links {
+                if let Some((min, max)) = &mut link.angle_limit {
+                    count += sanitize(min.iter_mut().chain(max));
+                }
+            }
+        }
+        count
+    }
 }
 
 bitflags::bitflags! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub struct BoneFlags: u16 {
         const CONNECT_TO_OTHER_BONE = 0x0001;
         const ROTATABLE = 0x0002;
@@ -254,9 +691,8 @@ bitflags::bitflags! {
         const LOCAL_COORDINATE = 0x0800;
         const PHYSICS_AFTER_DEFORM = 0x1000;
         const EXTERNAL_PARENT_DEFORM = 0x2000;
-        const UNKNOWN_2000 = 0x2000;
-        const UNKNOWN_4000 = 0x2000;
-        const UNKNOWN_8000 = 0x2000;
+        const UNKNOWN_4000 = 0x4000;
+        const UNKNOWN_8000 = 0x8000;
     }
 }
 
@@ -268,12 +704,12 @@ pub enum BoneConnection {
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct InheritRotateOrTranslation {
-    rotate_or_translation: RotateOrTranslation,
-    bone_index: BoneIndex,
-    weight: f32,
+    pub rotate_or_translation: RotateOrTranslation,
+    pub bone_index: BoneIndex,
+    pub weight: f32,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum RotateOrTranslation {
     Rotate,
     Translation,
@@ -308,6 +744,54 @@ impl Ik {
         }
         Ok(())
     }
+
+    /// the size [`Ik::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let bone = header.bone_index.bytes() as usize;
+        // target, iteration count and limit angle, then the links
+        bone + 4 + 4 + vec_size(&self.links, |i| bone + 1 + i.angle_limit.map_or(0, |_| 24))
+    }
+
+    /// check the ik can be solved: it has a target, the target and link bones
+    /// are below `bone_count`, the target is not one of the links, it iterates at least once
+    /// and its limit angle is finite.
+    /// fails with [`PmxError::InvalidIk`] naming the first problem found
+    pub fn validate(&self, bone_count: u32) -> Result<(), PmxError> {
+        let in_range = |i: BoneIndex| index_position(i).is_some_and(|i| i < bone_count as usize);
+        if IndexSize::Bit32.is_none(self.target_bone_index as u32) {
+            return Err(PmxError::InvalidIk("no target bone"));
+        }
+        if !in_range(self.target_bone_index) {
+            return Err(PmxError::InvalidIk("target bone index out of range"));
+        }
+        if self.links.iter().any(|link| !in_range(link.bone_index)) {
+            return Err(PmxError::InvalidIk("link bone index out of range"));
+        }
+        if self
+            .links
+            .iter()
+            .any(|link| link.bone_index == self.target_bone_index)
+        {
+            return Err(PmxError::InvalidIk("link bone is the target bone"));
+        }
+        if self.iter_count == 0 {
+            return Err(PmxError::InvalidIk("zero iteration count"));
+        }
+        if !self.limit_angle.is_finite() {
+            return Err(PmxError::InvalidIk("limit angle is not finite"));
+        }
+        Ok(())
+    }
+
+    /// clamp the parameters [`Ik::validate`] rejects to safe values:
+    /// a zero iteration count becomes 1 and a non-finite limit angle becomes 1 radian.
+    /// bone indices are left as they are, there is no safe bone to point them to
+    pub fn sanitize(&mut self) {
+        self.iter_count = self.iter_count.max(1);
+        if !self.limit_angle.is_finite() {
+            self.limit_angle = 1.0;
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -338,4 +822,334 @@ impl IkLink {
         }
         Ok(())
     }
+
+    /// set the angle limit from euler angles in degrees, which are stored in radians.
+    /// fails with [`PmxError::InvalidIk`] unless `min <= max` on every axis
+    pub fn with_angle_limit_degrees(
+        mut self,
+        min: [f32; 3],
+        max: [f32; 3],
+    ) -> Result<Self, PmxError> {
+        if !(0..3).all(|i| min[i] <= max[i]) {
+            return Err(PmxError::InvalidIk("angle limit min is above max"));
+        }
+        self.angle_limit = Some((min.map(f32::to_radians), max.map(f32::to_radians)));
+        Ok(self)
+    }
+
+    /// the `(min, max)` angle limit in degrees
+    pub fn angle_limit_degrees(&self) -> Option<([f32; 3], [f32; 3])> {
+        self.angle_limit
+            .map(|(min, max)| (min.map(f32::to_degrees), max.map(f32::to_degrees)))
+    }
+}
+
+impl_approx_eq!(Bones { bones });
+impl_approx_eq!(Bone {
+    name,
+    name_en,
+    position,
+    parent_bone_index,
+    priority,
+    connect,
+    rotatable,
+    translatable,
+    is_visible,
+    enable,
+    inherit_local,
+    inherit_rotate_or_translation,
+    fixed_axis,
+    local_axis,
+    physics_after_deform,
+    external_parent_bone_index,
+    ik,
+    unknown_0040,
+    unknown_4000,
+    unknown_8000,
+});
+impl_eq_ignoring_english!(Bone {
+    name,
+    position,
+    parent_bone_index,
+    priority,
+    connect,
+    rotatable,
+    translatable,
+    is_visible,
+    enable,
+    inherit_local,
+    inherit_rotate_or_translation,
+    fixed_axis,
+    local_axis,
+    physics_after_deform,
+    external_parent_bone_index,
+    ik,
+    unknown_0040,
+    unknown_4000,
+    unknown_8000,
+});
+impl_approx_eq!(InheritRotateOrTranslation {
+    rotate_or_translation,
+    bone_index,
+    weight,
+});
+impl_approx_eq!(Ik {
+    target_bone_index,
+    iter_count,
+    limit_angle,
+    links,
+});
+impl_approx_eq!(IkLink {
+    bone_index,
+    angle_limit,
+});
+impl_approx_eq_exact!(RotateOrTranslation);
+
+impl ApproxEq for BoneConnection {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        match (self, other) {
+            (Self::BoneIndex(a), Self::BoneIndex(b)) => a == b,
+            (Self::Position(a), Self::Position(b)) => a.approx_eq(b, epsilon),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::IndexSize;
+    use crate::testing::sample;
+
+    fn bone(blocks: u8) -> Bone {
+        let inherit = match blocks & 0b11 {
+            0b01 => Some(RotateOrTranslation::Rotate),
+            0b10 => Some(RotateOrTranslation::Translation),
+            0b11 => Some(RotateOrTranslation::RotateTranslation),
+            _ => None,
+        };
+        Bone {
+            name: "ボーン".into(),
+            name_en: "bone".into(),
+            position: [1.0, 2.0, 3.0],
+            parent_bone_index: -1,
+            priority: 2,
+            connect: if blocks & 0b1000000 != 0 {
+                BoneConnection::BoneIndex(3)
+            } else {
+                BoneConnection::Position([0.0, 1.0, 0.0])
+            },
+            rotatable: true,
+            translatable: false,
+            is_visible: true,
+            enable: true,
+            inherit_local: inherit.is_some(),
+            inherit_rotate_or_translation: inherit.map(|rotate_or_translation| {
+                InheritRotateOrTranslation {
+                    rotate_or_translation,
+                    bone_index: 4,
+                    weight: 0.5,
+                }
+            }),
+            fixed_axis: (blocks & 0b100 != 0).then_some([0.0, 0.0, 1.0]),
+            local_axis: (blocks & 0b1000 != 0).then_some(([1.0, 0.0, 0.0], [0.0, 0.0, 1.0])),
+            physics_after_deform: false,
+            external_parent_bone_index: (blocks & 0b10000 != 0).then_some(-1),
+            ik: (blocks & 0b100000 != 0).then(|| Ik {
+                target_bone_index: 5,
+                iter_count: 40,
+                limit_angle: 0.5,
+                links: vec![
+                    IkLink {
+                        bone_index: 6,
+                        angle_limit: Some(([-1.0, 0.0, 0.0], [-0.1, 0.0, 0.0])),
+                    },
+                    IkLink {
+                        bone_index: 7,
+                        angle_limit: None,
+                    },
+                ],
+            }),
+            unknown_0040: false,
+            unknown_4000: false,
+            unknown_8000: false,
+        }
+    }
+
+    #[test]
+    fn every_optional_block_round_trips_at_every_index_width() {
+        for size in [IndexSize::Bit8, IndexSize::Bit16, IndexSize::Bit32] {
+            let header = Header::builder().index_size(size).build();
+            // inherit rotate, inherit translate, fixed axis, local axis, external parent,
+            // ik and a bone connection
+            for blocks in 0..0b10000000 {
+                let bone = bone(blocks);
+                let mut bytes = Vec::new();
+                bone.write(&header, &mut bytes).unwrap();
+
+                let mut read = bytes.as_slice();
+                let back = Bone::read(&header, &ReadOptions::default(), &mut read).unwrap();
+                assert_eq!(back, bone, "blocks {blocks:#09b} at {size:?}");
+                assert!(read.is_empty(), "blocks {blocks:#09b} at {size:?}");
+
+                let mut read = bytes.as_slice();
+                Bone::skip(&header, &mut read).unwrap();
+                assert!(read.is_empty(), "skip of blocks {blocks:#09b} at {size:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_flags_do_not_alias_external_parent() {
+        let header = Header::default();
+        let mut bone = bone(0b10000);
+        bone.unknown_4000 = true;
+        let mut bytes = Vec::new();
+        bone.write(&header, &mut bytes).unwrap();
+        let back = Bone::read(&header, &ReadOptions::default(), &mut bytes.as_slice()).unwrap();
+        assert_eq!(back.external_parent_bone_index, Some(-1));
+        assert!(back.unknown_4000);
+        assert!(!back.unknown_8000);
+        assert!(!back.unknown_0040);
+    }
+
+    #[test]
+    fn validate_names_every_broken_ik() {
+        type BreakIk = fn(&mut Ik);
+        let ik = bone(0b100000).ik.unwrap();
+        assert!(ik.validate(8).is_ok());
+        let cases: [(&str, BreakIk); 7] = [
+            ("no target bone", |ik| ik.target_bone_index = -1),
+            ("target bone index out of range", |ik| {
+                ik.target_bone_index = 8
+            }),
+            ("target bone index out of range", |ik| {
+                ik.target_bone_index = -2
+            }),
+            ("link bone index out of range", |ik| {
+                ik.links[1].bone_index = 8
+            }),
+            ("link bone is the target bone", |ik| {
+                ik.links[0].bone_index = 5
+            }),
+            ("zero iteration count", |ik| ik.iter_count = 0),
+            ("limit angle is not finite", |ik| ik.limit_angle = f32::NAN),
+        ];
+        for (message, break_ik) in cases {
+            let mut broken = ik.clone();
+            break_ik(&mut broken);
+            match broken.validate(8) {
+                Err(PmxError::InvalidIk(m)) => assert_eq!(m, message),
+                e => panic!("{message}: {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn angle_limit_degrees_convert_both_ways() {
+        let link = IkLink {
+            bone_index: 0,
+            angle_limit: None,
+        };
+        let limited = link
+            .with_angle_limit_degrees([-45.0, 0.0, -90.0], [45.0, 0.0, 180.0])
+            .unwrap();
+        let (min, max) = limited.angle_limit.unwrap();
+        let quarter = std::f32::consts::FRAC_PI_4;
+        assert_eq!(min, [-quarter, 0.0, -2.0 * quarter]);
+        assert_eq!(max, [quarter, 0.0, std::f32::consts::PI]);
+        let (min, max) = limited.angle_limit_degrees().unwrap();
+        assert!((min[0] + 45.0).abs() < 1e-4 && (max[0] - 45.0).abs() < 1e-4);
+        assert!((min[2] + 90.0).abs() < 1e-4 && (max[2] - 180.0).abs() < 1e-4);
+        assert_eq!(link.angle_limit_degrees(), None);
+
+        let e = link
+            .with_angle_limit_degrees([0.0, 10.0, 0.0], [0.0, 5.0, 0.0])
+            .unwrap_err();
+        assert!(matches!(e, PmxError::InvalidIk(_)));
+    }
+
+    #[test]
+    fn subtree_is_rebased_to_its_root() {
+        let bones = sample().bones;
+
+        let body = bones.extract_subtree(1);
+        let names: Vec<&str> = body.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["body", "arm", "hand"]);
+        let parents: Vec<BoneIndex> = body.iter().map(|b| b.parent_bone_index).collect();
+        assert_eq!(parents, [-1, 0, 1]);
+        let inherit = body[2].inherit_rotate_or_translation.as_ref().unwrap();
+        assert_eq!(inherit.bone_index, 0);
+
+        // the hand inherits from the body, which is outside the arm fragment
+        let arm = bones.extract_subtree(2);
+        assert_eq!(arm.len(), 2);
+        assert_eq!(arm[0].parent_bone_index, -1);
+        assert_eq!(arm[1].parent_bone_index, 0);
+        assert!(arm[1].inherit_rotate_or_translation.is_none());
+
+        assert_eq!(bones.extract_subtree(0), bones);
+        assert!(bones.extract_subtree(5).is_empty());
+        assert!(bones.extract_subtree(-1).is_empty());
+    }
+
+    #[test]
+    fn tree_is_indented_by_depth_and_marks_cycles() {
+        let mut bones = sample().bones;
+        assert_eq!(
+            bones.format_tree(),
+            "root\n  body\n    arm\n      hand\n  ik\n"
+        );
+
+        let mut a = bones[0].clone();
+        a.name = "a".into();
+        a.parent_bone_index = 6;
+        let mut b = a.clone();
+        b.name = "b".into();
+        b.parent_bone_index = 5;
+        bones.bones.extend([a, b]);
+        assert_eq!(
+            bones.format_tree(),
+            "root\n  body\n    arm\n      hand\n  ik\na\n  b\n    a (cycle)\n"
+        );
+    }
+
+    #[test]
+    fn world_positions_sum_the_chain_back_to_model_space() {
+        let template = sample().bones[0].clone();
+        let chain = |links: [(BoneIndex, [f32; 3]); 3]| Bones {
+            bones: links
+                .into_iter()
+                .map(|(parent_bone_index, position)| Bone {
+                    parent_bone_index,
+                    position,
+                    ..template.clone()
+                })
+                .collect(),
+        };
+        let bones = chain([
+            (-1, [0.0, 1.0, 0.0]),
+            (0, [0.0, 2.0, 1.0]),
+            (1, [1.0, 3.0, 1.0]),
+        ]);
+        assert_eq!(
+            bones.local_positions(),
+            [[0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [1.0, 1.0, 0.0]]
+        );
+        assert_eq!(
+            bones.world_positions(),
+            [[0.0, 1.0, 0.0], [0.0, 2.0, 1.0], [1.0, 3.0, 1.0]]
+        );
+
+        // the first two bones are each other's parent
+        let cycle = chain([
+            (1, [1.0, 0.0, 0.0]),
+            (0, [0.0, 2.0, 0.0]),
+            (1, [0.0, 3.0, 0.0]),
+        ]);
+        assert_eq!(
+            cycle.world_positions(),
+            [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 3.0, 0.0]]
+        );
+    }
 }
@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+
+use crate::bone::Bone;
+use crate::display_frame::DisplayFrame;
+use crate::element_index::ElementIndices;
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::joint::Joint;
+use crate::kits::read_vec;
+use crate::material::Material;
+use crate::model_info::{MAX_COMMENT_BYTES, MAX_NAME_BYTES};
+use crate::morph::Morph;
+use crate::options::ReadOptions;
+use crate::rigid_body::RigidBody;
+use crate::soft_body::SoftBody;
+use crate::vertex::Vertices;
+
+/// the name and english name of an element of a [`PmxRef`]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct NameRef<'a> {
+    pub name: Cow<'a, str>,
+    pub name_en: Cow<'a, str>,
+}
+
+/// the strings of a model borrowed from the bytes of its file, for indexers
+/// that only look at the names.
+///
+/// the strings of a utf-8 model are slices of the bytes, only the ones needing
+/// [`ReadOptions::lenient_strings`] or another fallback are allocated. the strings of
+/// a utf-16 model are decoded into owned ones like [`crate::pmx::Pmx::read`] does.
+/// everything else is skipped over, so the lists are in file order
+/// and have the lengths of the sections.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct PmxRef<'a> {
+    pub header: Header,
+    pub name: Cow<'a, str>,
+    pub name_en: Cow<'a, str>,
+    pub comment: Cow<'a, str>,
+    pub comment_en: Cow<'a, str>,
+    pub textures: Vec<Cow<'a, str>>,
+    pub materials: Vec<NameRef<'a>>,
+    pub bones: Vec<NameRef<'a>>,
+    pub morphs: Vec<NameRef<'a>>,
+    pub display_frames: Vec<NameRef<'a>>,
+    pub rigid_bodies: Vec<NameRef<'a>>,
+    pub joints: Vec<NameRef<'a>>,
+    pub soft_bodies: Vec<NameRef<'a>>,
+}
+
+impl<'a> PmxRef<'a> {
+    /// parse the strings of a whole file, errors are wrapped in [`PmxError::At`]
+    /// with the offset where parsing failed
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, PmxError> {
+        Self::parse_with(bytes, &ReadOptions::default())
+    }
+
+    /// parse the strings of a whole file with non-default options, see [`PmxRef::parse`]
+    pub fn parse_with(bytes: &'a [u8], options: &ReadOptions) -> Result<Self, PmxError> {
+        let mut read = bytes;
+        Self::parse_inner(&mut read, options).map_err(|e| PmxError::At {
+            offset: (bytes.len() - read.len()) as u64,
+            source: Box::new(e),
+        })
+    }
+
+    fn parse_inner(read: &mut &'a [u8], options: &ReadOptions) -> Result<Self, PmxError> {
+        let header = Header::read(read)?;
+        let encoding = header.encoding;
+        let name = encoding.read_borrowed_limited(read, options, MAX_NAME_BYTES)?;
+        let name_en = encoding.read_borrowed_limited(read, options, MAX_NAME_BYTES)?;
+        let comment = encoding.read_borrowed_limited(read, options, MAX_COMMENT_BYTES)?;
+        let comment_en = encoding.read_borrowed_limited(read, options, MAX_COMMENT_BYTES)?;
+        Vertices::skip(&header, read)?;
+        ElementIndices::skip(&header, read)?;
+        let textures = read_vec(read, |read| encoding.read_borrowed(read, options))?;
+        let materials = read_names(&header, options, read, Material::skip)?;
+        let bones = read_names(&header, options, read, Bone::skip)?;
+        let morphs = read_names(&header, options, read, |header, read| {
+            Morph::skip(header, read).map(|_| ())
+        })?;
+        let display_frames = read_names(&header, options, read, DisplayFrame::skip)?;
+        let rigid_bodies = read_names(&header, options, read, RigidBody::skip)?;
+        let joints = read_names(&header, options, read, Joint::skip)?;
+        let soft_bodies = if header.supports_soft_bodies() {
+            read_names(&header, options, read, SoftBody::skip)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            header,
+            name,
+            name_en,
+            comment,
+            comment_en,
+            textures,
+            materials,
+            bones,
+            morphs,
+            display_frames,
+            rigid_bodies,
+            joints,
+            soft_bodies,
+        })
+    }
+}
+
+/// read the names of a counted section whose elements start with their two names,
+/// `skip` skipping each whole element, names included
+fn read_names<'a>(
+    header: &Header,
+    options: &ReadOptions,
+    read: &mut &'a [u8],
+    skip: impl Fn(&Header, &mut &'a [u8]) -> Result<(), PmxError>,
+) -> Result<Vec<NameRef<'a>>, PmxError> {
+    read_vec(read, |read| {
+        let mut names: &'a [u8] = read;
+        let name = NameRef {
+            name: header.encoding.read_borrowed(&mut names, options)?,
+            name_en: header.encoding.read_borrowed(&mut names, options)?,
+        };
+        skip(header, read)?;
+        Ok(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Encoding;
+    use crate::pmx::Pmx;
+    use crate::testing::sample;
+    use crate::{pmx_read, pmx_write_with_encoding};
+
+    /// the name pairs of a section of the owned model
+    fn names<'a, T: 'a>(
+        items: impl IntoIterator<Item = &'a T>,
+        f: impl Fn(&'a T) -> (&'a str, &'a str),
+    ) -> Vec<NameRef<'a>> {
+        items
+            .into_iter()
+            .map(|i| {
+                let (name, name_en) = f(i);
+                NameRef {
+                    name: name.into(),
+                    name_en: name_en.into(),
+                }
+            })
+            .collect()
+    }
+
+    fn assert_matches_owned(borrowed: &PmxRef, pmx: &Pmx) {
+        assert_eq!(borrowed.name, pmx.info.name);
+        assert_eq!(borrowed.name_en, pmx.info.name_en);
+        assert_eq!(borrowed.comment, pmx.info.comment);
+        assert_eq!(borrowed.comment_en, pmx.info.comment_en);
+        assert_eq!(borrowed.textures, pmx.textures.textures);
+        assert_eq!(
+            borrowed.materials,
+            names(&pmx.materials, |i| (&i.name, &i.name_en))
+        );
+        assert_eq!(borrowed.bones, names(&pmx.bones, |i| (&i.name, &i.name_en)));
+        assert_eq!(
+            borrowed.morphs,
+            names(&pmx.morphs, |i| (&i.name, &i.name_en))
+        );
+        assert_eq!(
+            borrowed.display_frames,
+            names(&pmx.display_frames, |i| (&i.name, &i.name_en))
+        );
+        assert_eq!(
+            borrowed.rigid_bodies,
+            names(&pmx.rigid_bodies, |i| (&i.name, &i.name_en))
+        );
+        assert_eq!(
+            borrowed.joints,
+            names(&pmx.joints, |i| (&i.name, &i.name_en))
+        );
+        assert_eq!(
+            borrowed.soft_bodies,
+            names(&pmx.soft_bodies, |i| (&i.name, &i.name_en))
+        );
+    }
+
+    #[test]
+    fn names_match_the_owned_parse() {
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le] {
+            let mut bytes = Vec::new();
+            pmx_write_with_encoding(&mut bytes, &sample(), 2.1, encoding).unwrap();
+            let (header, pmx) = pmx_read(&mut bytes.as_slice()).unwrap();
+            let borrowed = PmxRef::parse(&bytes).unwrap();
+            assert_eq!(borrowed.header, header);
+            assert_matches_owned(&borrowed, &pmx);
+            // only utf-8 strings are borrowed
+            let is_borrowed = matches!(borrowed.bones[0].name, Cow::Borrowed(_));
+            assert_eq!(is_borrowed, encoding == Encoding::Utf8);
+        }
+    }
+}
@@ -5,9 +5,13 @@ use crate::{BoneIndex, MorphIndex};
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_bool, read_vec};
+use crate::kits::{
+    impl_approx_eq, impl_approx_eq_exact, impl_collection, impl_eq_ignoring_english, read_bool,
+    read_vec, skip_vec, vec_size,
+};
+use crate::options::ReadOptions;
 
-#[derive(Default, Debug, Clone, Eq, PartialEq)]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
 pub struct DisplayFrames {
     pub display_frames: Vec<DisplayFrame>,
 }
@@ -16,11 +20,18 @@ impl DisplayFrames {
     pub fn count(&self) -> u32 {
         self.display_frames.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            display_frames: read_vec(read, |read| DisplayFrame::read(header, read))?,
+            display_frames: read_vec(read, |read| DisplayFrame::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| DisplayFrame::skip(header, read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.display_frames {
@@ -28,9 +39,61 @@ impl DisplayFrames {
         }
         Ok(())
     }
+    /// the size [`DisplayFrames::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.display_frames, |i| i.serialized_size(header))
+    }
+}
+
+impl DisplayFrames {
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        for frame in &mut self.display_frames {
+            for item in &mut frame.items {
+                if let DisplayFrameItem::BoneIndex(i) = item {
+                    f(i);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn for_each_morph_index_mut(&mut self, f: &mut impl FnMut(&mut MorphIndex)) {
+        for frame in &mut self.display_frames {
+            for item in &mut frame.items {
+                if let DisplayFrameItem::MorphIndex(i) = item {
+                    f(i);
+                }
+            }
+        }
+    }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+impl DisplayFrames {
+    /// put the special "Root" and "表情" frames that MMD expects at indices 0 and 1,
+    /// moving them there with their items if they are elsewhere and inserting empty ones
+    /// if they are missing, the other frames being kept after them in their order
+    pub fn ensure_system_frames(&mut self) {
+        let root = self.take_special(ROOT).unwrap_or_else(DisplayFrame::root);
+        let expression = self
+            .take_special(EXPRESSION)
+            .unwrap_or_else(DisplayFrame::expression);
+        self.display_frames.splice(0..0, [root, expression]);
+    }
+
+    fn take_special(&mut self, name: &str) -> Option<DisplayFrame> {
+        let index = self
+            .display_frames
+            .iter()
+            .position(|i| i.is_special && i.name == name)?;
+        Some(self.display_frames.remove(index))
+    }
+}
+
+impl_collection!(DisplayFrames, display_frames, DisplayFrame);
+
+const ROOT: &str = "Root";
+const EXPRESSION: &str = "表情";
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DisplayFrame {
     pub name: String,
     pub name_en: String,
@@ -39,14 +102,29 @@ pub struct DisplayFrame {
 }
 
 impl DisplayFrame {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             is_special: read_bool(read)?,
             items: read_vec(read, |read| DisplayFrameItem::read(header, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        read_bool(read)?;
+        skip_vec(read, |read| match read.read_u8()? {
+            0 => header.bone_index.skip(read),
+            1 => header.morph_index.skip(read),
+            _ => Err(PmxError::DisplayFrameError),
+        })?;
+        Ok(())
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -57,9 +135,43 @@ impl DisplayFrame {
         }
         Ok(())
     }
+
+    /// the size [`DisplayFrame::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let items = vec_size(&self.items, |i| {
+            1 + match i {
+                DisplayFrameItem::BoneIndex(_) => header.bone_index.bytes() as usize,
+                DisplayFrameItem::MorphIndex(_) => header.morph_index.bytes() as usize,
+            }
+        });
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            + 1
+            + items
+    }
+
+    /// the empty special "Root" frame
+    pub(crate) fn root() -> Self {
+        Self {
+            name: ROOT.to_owned(),
+            name_en: "Root".to_owned(),
+            is_special: true,
+            items: Vec::new(),
+        }
+    }
+
+    /// the empty special "表情" frame
+    pub(crate) fn expression() -> Self {
+        Self {
+            name: EXPRESSION.to_owned(),
+            name_en: "Exp".to_owned(),
+            is_special: true,
+            items: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum DisplayFrameItem {
     BoneIndex(BoneIndex),
     MorphIndex(MorphIndex),
@@ -88,3 +200,84 @@ impl DisplayFrameItem {
         Ok(())
     }
 }
+
+impl_approx_eq!(DisplayFrames { display_frames });
+impl_approx_eq!(DisplayFrame {
+    name,
+    name_en,
+    is_special,
+    items,
+});
+impl_eq_ignoring_english!(DisplayFrame {
+    name,
+    is_special,
+    items,
+});
+impl_approx_eq_exact!(DisplayFrameItem);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(name: &str, is_special: bool) -> DisplayFrame {
+        DisplayFrame {
+            name: name.into(),
+            is_special,
+            items: vec![DisplayFrameItem::BoneIndex(0)],
+            ..DisplayFrame::default()
+        }
+    }
+
+    fn names(frames: &DisplayFrames) -> Vec<&str> {
+        frames.iter().map(|i| i.name.as_str()).collect()
+    }
+
+    #[test]
+    fn missing_system_frames_are_created_first() {
+        let mut frames = DisplayFrames {
+            display_frames: vec![frame("体", false)],
+        };
+        frames.ensure_system_frames();
+        assert_eq!(names(&frames), [ROOT, EXPRESSION, "体"]);
+        assert_eq!(frames[0], DisplayFrame::root());
+        assert_eq!(frames[1], DisplayFrame::expression());
+
+        let mut empty = DisplayFrames::default();
+        empty.ensure_system_frames();
+        assert_eq!(names(&empty), [ROOT, EXPRESSION]);
+    }
+
+    #[test]
+    fn present_system_frames_are_kept_in_place() {
+        let mut frames = DisplayFrames {
+            display_frames: vec![
+                frame(ROOT, true),
+                frame(EXPRESSION, true),
+                frame("体", false),
+            ],
+        };
+        let before = frames.clone();
+        frames.ensure_system_frames();
+        assert_eq!(frames, before);
+    }
+
+    #[test]
+    fn misplaced_system_frames_are_moved_to_the_front() {
+        let mut frames = DisplayFrames {
+            display_frames: vec![
+                frame("体", false),
+                frame(EXPRESSION, true),
+                frame("髪", false),
+                // a frame named like the root but not special is an ordinary one
+                frame(ROOT, false),
+                frame(ROOT, true),
+            ],
+        };
+        frames.ensure_system_frames();
+        assert_eq!(names(&frames), [ROOT, EXPRESSION, "体", "髪", ROOT]);
+        assert!(frames[0].is_special && !frames[4].is_special);
+        // the moved frames keep their items
+        assert_eq!(frames[0].items, [DisplayFrameItem::BoneIndex(0)]);
+        assert_eq!(frames[1].items, [DisplayFrameItem::BoneIndex(0)]);
+    }
+}
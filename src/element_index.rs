@@ -1,14 +1,16 @@
 use std::fmt::{Debug, Formatter};
 use std::io::{Read, Write};
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
-use crate::header::Header;
-use crate::kits::read_vec;
+use crate::header::{Header, IndexSize};
+use crate::kits::{impl_approx_eq, impl_collection, read_vec, skip};
+use crate::options::ReadOptions;
+use crate::vertex::Vertices;
 use crate::VertexIndex;
 
-#[derive(Default, Clone, Eq, PartialEq)]
+#[derive(Default, Clone, Eq, PartialEq, Hash)]
 pub struct ElementIndices {
     pub element_indices: Vec<VertexIndex>,
 }
@@ -32,6 +34,27 @@ impl ElementIndices {
         })
     }
 
+    /// read the element section, checking it makes whole triangles
+    /// with [`ReadOptions::strict_elements`]
+    pub(crate) fn read_checked<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        let elements = Self::read(header, read)?;
+        if options.strict_elements && !elements.element_indices.len().is_multiple_of(3) {
+            return Err(PmxError::ElementCountError(elements.count()));
+        }
+        Ok(elements)
+    }
+
+    /// skip the element section, returning the element count
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        let count = read.read_u32::<LittleEndian>()?;
+        skip(read, count as u64 * header.vertex_index.bytes())?;
+        Ok(count)
+    }
+
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.element_indices {
@@ -39,4 +62,168 @@ impl ElementIndices {
         }
         Ok(())
     }
+
+    /// the size [`ElementIndices::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        4 + self.element_indices.len() * header.vertex_index.bytes() as usize
+    }
+
+    pub fn as_u32(&self) -> &[u32] {
+        &self.element_indices
+    }
+
+    /// the indices narrowed for an index buffer, `None` if one exceeds `u16::MAX`
+    pub fn as_u16(&self) -> Option<Vec<u16>> {
+        self.element_indices
+            .iter()
+            .map(|&i| u16::try_from(i).ok())
+            .collect()
+    }
+
+    /// the narrowest width holding every index, unlike the header sizes
+    /// the largest value of each width is allowed
+    pub fn min_index_type(&self) -> IndexSize {
+        match self.element_indices.iter().max().copied().unwrap_or(0) {
+            0..=0xFF => IndexSize::Bit8,
+            0x100..=0xFFFF => IndexSize::Bit16,
+            _ => IndexSize::Bit32,
+        }
+    }
+
+    /// swap the second and third index of every triangle, turning the clockwise faces of pmx
+    /// into counter-clockwise ones and back, failing with [`PmxError::ElementCountError`]
+    /// if the indices do not make whole triangles
+    pub fn reverse_winding(&mut self) -> Result<(), PmxError> {
+        if !self.element_indices.len().is_multiple_of(3) {
+            return Err(PmxError::ElementCountError(self.count()));
+        }
+        self.swap_winding();
+        Ok(())
+    }
+
+    /// the indices of the triangles whose area is below `epsilon` or NaN, from the vertex
+    /// positions. triangles naming a vertex out of range are not reported
+    pub fn degenerate_triangles(&self, vertices: &Vertices, epsilon: f32) -> Vec<usize> {
+        let position = |i: VertexIndex| -> Option<[f32; 3]> {
+            let i = 3 * i as usize;
+            let p = vertices.position3s.get(i..i + 3)?;
+            Some([p[0], p[1], p[2]])
+        };
+        self.element_indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter(|(_, triangle)| {
+                let (Some(a), Some(b), Some(c)) = (
+                    position(triangle[0]),
+                    position(triangle[1]),
+                    position(triangle[2]),
+                ) else {
+                    return false;
+                };
+                let ab: [f32; 3] = std::array::from_fn(|i| b[i] - a[i]);
+                let ac: [f32; 3] = std::array::from_fn(|i| c[i] - a[i]);
+                let cross = [
+                    ab[1] * ac[2] - ab[2] * ac[1],
+                    ab[2] * ac[0] - ab[0] * ac[2],
+                    ab[0] * ac[1] - ab[1] * ac[0],
+                ];
+                let area = 0.5 * cross.iter().map(|i| i * i).sum::<f32>().sqrt();
+                area.is_nan() || area < epsilon
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub(crate) fn swap_winding(&mut self) {
+        for i in self.element_indices.chunks_exact_mut(3) {
+            i.swap(1, 2);
+        }
+    }
+}
+
+impl_collection!(ElementIndices, element_indices, VertexIndex);
+
+impl_approx_eq!(ElementIndices { element_indices });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(element_indices: &[VertexIndex]) -> ElementIndices {
+        ElementIndices {
+            element_indices: element_indices.to_vec(),
+        }
+    }
+
+    #[test]
+    fn index_buffers_narrow_up_to_their_largest_index() {
+        let small = elements(&[0, 255, 65535]);
+        assert_eq!(small.as_u32(), [0, 255, 65535]);
+        assert_eq!(small.as_u16().unwrap(), [0, 255, 65535]);
+        assert_eq!(small.min_index_type(), IndexSize::Bit16);
+
+        let large = elements(&[0, 1, 65536]);
+        assert_eq!(large.as_u32(), [0, 1, 65536]);
+        assert_eq!(large.as_u16(), None);
+        assert_eq!(large.min_index_type(), IndexSize::Bit32);
+
+        assert_eq!(elements(&[0, 255]).min_index_type(), IndexSize::Bit8);
+        assert_eq!(elements(&[256]).min_index_type(), IndexSize::Bit16);
+        assert_eq!(elements(&[]).min_index_type(), IndexSize::Bit8);
+        assert_eq!(elements(&[]).as_u16().unwrap(), []);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_the_flat_ones() {
+        let mut vertices = Vertices::default();
+        for p in [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0],
+        ] {
+            vertices.position3s.extend(p);
+        }
+        vertices.position3s.extend([f32::NAN, 0.0, 0.0]);
+        // a triangle, a line, a repeated vertex, a NaN and an out of range vertex
+        let elements = elements(&[0, 1, 2, 0, 1, 3, 2, 2, 1, 0, 1, 4, 0, 1, 9]);
+        assert_eq!(elements.degenerate_triangles(&vertices, 1e-6), [1, 2, 3]);
+        // the triangle has an area of 0.5
+        assert_eq!(elements.degenerate_triangles(&vertices, 0.6), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn reversing_the_winding_swaps_the_last_two_corners() {
+        let mut triangles = elements(&[0, 1, 2, 3, 4, 5]);
+        triangles.reverse_winding().unwrap();
+        assert_eq!(triangles.element_indices, [0, 2, 1, 3, 5, 4]);
+        triangles.reverse_winding().unwrap();
+        assert_eq!(triangles.element_indices, [0, 1, 2, 3, 4, 5]);
+
+        let mut partial = elements(&[0, 1, 2, 3]);
+        let e = partial.reverse_winding().unwrap_err();
+        assert!(matches!(e, PmxError::ElementCountError(4)));
+        assert_eq!(partial.element_indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn strict_reads_reject_a_partial_triangle() {
+        let header = Header::default();
+        let mut bytes = Vec::new();
+        elements(&[0, 1, 2, 3, 4])
+            .write(&header, &mut bytes)
+            .unwrap();
+
+        let strict = ReadOptions {
+            strict_elements: true,
+            ..ReadOptions::default()
+        };
+        let e = ElementIndices::read_checked(&header, &strict, &mut bytes.as_slice()).unwrap_err();
+        assert!(matches!(e, PmxError::ElementCountError(5)));
+        assert_eq!(e.to_string(), "element count 5 is not a multiple of 3");
+
+        let lenient =
+            ElementIndices::read_checked(&header, &ReadOptions::default(), &mut bytes.as_slice());
+        assert_eq!(lenient.unwrap().len(), 5);
+    }
 }
@@ -14,6 +14,9 @@ pub enum PmxError {
     #[error("vertex count error")]
     VertexCountError,
 
+    #[error("element count {0} is not a multiple of 3")]
+    ElementCountError(u32),
+
     #[error("morph error")]
     MorphError,
 
@@ -50,18 +53,113 @@ pub enum PmxError {
     #[error("encoding error")]
     EncodingError,
 
+    #[error("invalid utf-16 in a string of {0} bytes, lenient strings would replace it")]
+    Utf16Error(usize),
+
+    #[error("utf-16 string of odd length {0}")]
+    OddUtf16Length(u32),
+
     #[error("skin error")]
     SkinError,
 
+    #[error("qdef skin requires version 2.1")]
+    QdefVersionError,
+
+    #[error("soft bodies require version 2.1")]
+    SoftBodyVersionError,
+
     #[error("global data length too long")]
     GlobalDataLengthTooLong,
 
     #[error("invalid encoding {0}")]
     InvalidEncoding(u8),
 
+    #[error("string length {0} exceeds the limit")]
+    StringTooLong(u32),
+
     #[error("invalid index size {0}")]
     InvalidIndexSize(u8),
 
+    #[error("unknown encoding name {0:?}")]
+    UnknownEncodingName(String),
+
+    #[error("unknown index size name {0:?}")]
+    UnknownIndexSizeName(String),
+
+    #[error("invalid ik, {0}")]
+    InvalidIk(&'static str),
+
+    #[error("invalid material element counts, {0}")]
+    InvalidMaterialElementCounts(&'static str),
+
+    #[error("non-finite float in {location}")]
+    NonFinite { location: String },
+
+    #[error("the written {section} read back differently")]
+    WriteVerificationFailed { section: &'static str },
+
+    /// the data ended inside the model, as with a truncated file
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+
+    /// the data ended inside a section of the model, as with a truncated file,
+    /// the section being named like in [`PmxError::WriteVerificationFailed`]
+    #[error("file truncated in the {section}")]
+    Truncated { section: &'static str },
+
     #[error("io error {0}")]
-    Io(#[from] std::io::Error),
+    Io(std::io::Error),
+
+    /// the error `source` happened after reading `offset` bytes,
+    /// the readers of whole files wrap their errors in it, see [`PmxError::root`]
+    #[error("{source} at offset {offset}")]
+    At { offset: u64, source: Box<PmxError> },
+}
+
+impl PmxError {
+    /// the error without its [`PmxError::At`] wrappers, to match on what went wrong
+    /// whatever reader returned it
+    pub fn root(&self) -> &PmxError {
+        match self {
+            Self::At { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// take the error out of its [`PmxError::At`] wrappers, see [`PmxError::root`]
+    pub fn into_root(self) -> PmxError {
+        match self {
+            Self::At { source, .. } => source.into_root(),
+            e => e,
+        }
+    }
+
+    /// the offset of the outermost [`PmxError::At`] wrapper,
+    /// `None` for an error that is not wrapped
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// name the section an [`PmxError::UnexpectedEof`] happened in,
+    /// turning it into [`PmxError::Truncated`]
+    pub(crate) fn in_section(self, section: &'static str) -> Self {
+        match self {
+            Self::UnexpectedEof => Self::Truncated { section },
+            e => e,
+        }
+    }
+}
+
+/// an io error of a reader running out of data is [`PmxError::UnexpectedEof`],
+/// any other one [`PmxError::Io`]
+impl From<std::io::Error> for PmxError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            _ => Self::Io(e),
+        }
+    }
 }
@@ -1,12 +1,27 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::io::{Read, Write};
+use std::str::FromStr;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
+use crate::kits::skip;
+use crate::options::ReadOptions;
 use crate::pmx::Pmx;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// the magic number at the start of every pmx file
+pub const PMX_MAGIC: u32 = 0x20584D50;
+
+/// the little endian bytes of [`PMX_MAGIC`]
+pub const PMX_MAGIC_BYTES: [u8; 4] = *b"PMX ";
+
+/// check the first four bytes for the pmx magic without parsing the file
+pub fn is_pmx(bytes: &[u8]) -> bool {
+    bytes.starts_with(&PMX_MAGIC_BYTES)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum Encoding {
     Utf16Le = 0x00,
@@ -25,9 +40,88 @@ impl TryFrom<u8> for Encoding {
     }
 }
 
+/// `utf8` or `utf16`, also spelled `utf-8`, `utf16le` or `utf-16le`, ignoring ascii case
+impl FromStr for Encoding {
+    type Err = PmxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(Self::Utf8),
+            "utf16" | "utf-16" | "utf16le" | "utf-16le" => Ok(Self::Utf16Le),
+            _ => Err(PmxError::UnknownEncodingName(s.to_owned())),
+        }
+    }
+}
+
 impl Encoding {
     pub fn read<R: Read>(&self, read: &mut R) -> Result<String, PmxError> {
+        self.read_with(read, &ReadOptions::default())
+    }
+
+    pub fn read_with<R: Read>(
+        &self,
+        read: &mut R,
+        options: &ReadOptions,
+    ) -> Result<String, PmxError> {
+        self.read_limited(read, options, options.max_string_bytes)
+    }
+
+    /// read a string of a utf-8 model borrowed from the bytes when it is valid utf-8,
+    /// any other string being read by [`Encoding::read_with`] into an owned one
+    pub fn read_borrowed<'a>(
+        &self,
+        read: &mut &'a [u8],
+        options: &ReadOptions,
+    ) -> Result<Cow<'a, str>, PmxError> {
+        self.read_borrowed_limited(read, options, options.max_string_bytes)
+    }
+
+    /// read a string like [`Encoding::read_borrowed`] with a byte limit,
+    /// see [`Encoding::read_limited`]
+    pub fn read_borrowed_limited<'a>(
+        &self,
+        read: &mut &'a [u8],
+        options: &ReadOptions,
+        limit: usize,
+    ) -> Result<Cow<'a, str>, PmxError> {
+        if *self == Encoding::Utf8 {
+            let bytes: &'a [u8] = read;
+            if let Some((length, rest)) = bytes.split_first_chunk::<4>() {
+                let length = u32::from_le_bytes(*length) as usize;
+                let str = rest
+                    .get(..length)
+                    .filter(|_| length <= limit.min(options.max_string_bytes))
+                    .and_then(|i| std::str::from_utf8(i).ok());
+                if let Some(str) = str {
+                    *read = &rest[length..];
+                    return Ok(Cow::Borrowed(str));
+                }
+            }
+        }
+        // errors, lenient strings and utf-16 take the owned path
+        self.read_limited(read, options, limit).map(Cow::Owned)
+    }
+
+    /// read a string, failing with [`PmxError::StringTooLong`] before allocating
+    /// if it claims more than `limit` or [`ReadOptions::max_string_bytes`] bytes
+    pub fn read_limited<R: Read>(
+        &self,
+        read: &mut R,
+        options: &ReadOptions,
+        limit: usize,
+    ) -> Result<String, PmxError> {
         let length = read.read_u32::<LittleEndian>()?;
+        if length as usize > limit.min(options.max_string_bytes) {
+            return Err(PmxError::StringTooLong(length));
+        }
+        if *self == Encoding::Utf16Le && length % 2 == 1 && !options.lenient_strings {
+            // lenient strings decode the dangling byte as U+FFFD instead
+            return Err(PmxError::OddUtf16Length(length));
+        }
+        if length == 0 {
+            // empty names and comments are common, skip the buffer entirely
+            return Ok(String::new());
+        }
         let mut buffer = vec![0_u8; length as usize];
         read.read_exact(buffer.as_mut_slice())?;
         match self {
@@ -35,14 +129,35 @@ impl Encoding {
                 // TODO: use String::from_utf16le when it's stable
                 let (str, error) =
                     encoding_rs::UTF_16LE.decode_without_bom_handling(buffer.as_slice());
-                if error {
-                    return Err(PmxError::EncodingError);
+                if error && !options.lenient_strings {
+                    return Err(PmxError::Utf16Error(buffer.len()));
                 }
-                Ok(str.to_string())
+                Ok(str.into_owned())
             }
-            Encoding::Utf8 => String::from_utf8(buffer).map_err(|_| PmxError::EncodingError),
+            Encoding::Utf8 => match String::from_utf8(buffer) {
+                Ok(str) => Ok(str),
+                Err(e) => {
+                    #[cfg(feature = "shift_jis")]
+                    if options.shift_jis_fallback {
+                        let (str, error) =
+                            encoding_rs::SHIFT_JIS.decode_without_bom_handling(e.as_bytes());
+                        if !error {
+                            return Ok(str.into_owned());
+                        }
+                    }
+                    if options.lenient_strings {
+                        Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+                    } else {
+                        Err(PmxError::EncodingError)
+                    }
+                }
+            },
         }
     }
+    pub(crate) fn skip<R: Read>(&self, read: &mut R) -> Result<(), PmxError> {
+        let length = read.read_u32::<LittleEndian>()?;
+        skip(read, length as u64)
+    }
     pub fn write<W: Write>(&self, write: &mut W, value: &str) -> Result<(), PmxError> {
         match self {
             Encoding::Utf16Le => {
@@ -61,9 +176,16 @@ impl Encoding {
         }
         Ok(())
     }
+    /// the size [`Encoding::write`] writes `value` in, its length prefix included
+    pub(crate) fn serialized_size(&self, value: &str) -> usize {
+        4 + match self {
+            Encoding::Utf16Le => value.encode_utf16().count() * 2,
+            Encoding::Utf8 => value.len(),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum IndexSize {
     Bit8 = 0x01,
@@ -84,6 +206,20 @@ impl TryFrom<u8> for IndexSize {
     }
 }
 
+/// the width in bits, `8`, `16` or `32`, or in bytes, `1`, `2` or `4`
+impl FromStr for IndexSize {
+    type Err = PmxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" | "1" => Ok(Self::Bit8),
+            "16" | "2" => Ok(Self::Bit16),
+            "32" | "4" => Ok(Self::Bit32),
+            _ => Err(PmxError::UnknownIndexSizeName(s.to_owned())),
+        }
+    }
+}
+
 pub(crate) trait PmxIndexType: Sized {
     fn read_pmx_index<R: Read>(read: &mut R, size: IndexSize) -> Result<Self, PmxError>;
     fn write_pmx_index<W: Write>(write: &mut W, size: IndexSize, index: Self) -> Result<(), PmxError>;
@@ -134,6 +270,7 @@ impl PmxIndexType for i32 {
 }
 
 impl IndexSize {
+    /// the narrowest width for the unsigned indices of a section of `count` entries
     pub fn from_count_u(count: u32) -> Self {
         match count {
             0..=0xFE => Self::Bit8,
@@ -142,6 +279,7 @@ impl IndexSize {
         }
     }
 
+    /// the narrowest width for the signed indices of a section of `count` entries
     pub fn from_count_i(count: u32) -> Self {
         match count {
             0..=0x7E => Self::Bit8,
@@ -150,6 +288,60 @@ impl IndexSize {
         }
     }
 
+    /// the encoded width of an index in bytes
+    pub fn bytes(self) -> u64 {
+        self as u64
+    }
+
+    /// the all-ones "none" value of the width as read by [`IndexSize::read_u`],
+    /// `0xFF`, `0xFFFF` or `0xFFFFFFFF`.
+    ///
+    /// [`IndexSize::read_i`] reads it as `-1`. vertex indices have no "none" value,
+    /// for them this is a valid index
+    pub fn none_sentinel_u(self) -> u32 {
+        match self {
+            IndexSize::Bit8 => 0xFF,
+            IndexSize::Bit16 => 0xFFFF,
+            IndexSize::Bit32 => 0xFFFF_FFFF,
+        }
+    }
+
+    /// whether an index read by [`IndexSize::read_u`] is the "none" value of the width
+    pub fn is_none(self, index: u32) -> bool {
+        index == self.none_sentinel_u()
+    }
+
+    /// read an unsigned index, the way vertex indices are stored.
+    ///
+    /// the value is zero extended, so `0xFF` in a [`IndexSize::Bit8`] index reads as `255`.
+    pub fn read_u<R: Read>(self, read: &mut R) -> Result<u32, PmxError> {
+        self.read(read)
+    }
+
+    /// read a signed index, the way every index except vertex indices is stored.
+    ///
+    /// the value is sign extended, so the all-ones "none" sentinel
+    /// (`0xFF`, `0xFFFF` or `0xFFFFFFFF` depending on the width) reads as `-1`.
+    pub fn read_i<R: Read>(self, read: &mut R) -> Result<i32, PmxError> {
+        self.read(read)
+    }
+
+    /// write an unsigned index, failing with [`PmxError::IndexError`] if it does not fit
+    pub fn write_u<W: Write>(self, write: &mut W, index: u32) -> Result<(), PmxError> {
+        self.write(write, index)
+    }
+
+    /// write a signed index, failing with [`PmxError::IndexError`] if it does not fit,
+    /// `-1` is written as the all-ones sentinel of the width
+    pub fn write_i<W: Write>(self, write: &mut W, index: i32) -> Result<(), PmxError> {
+        self.write(write, index)
+    }
+
+    #[inline(always)]
+    pub(crate) fn skip<R: Read>(self, read: &mut R) -> Result<(), PmxError> {
+        skip(read, self.bytes())
+    }
+
     #[inline(always)]
     pub(crate) fn read<R: Read, T: PmxIndexType>(self, read: &mut R) -> Result<T, PmxError> {
         T::read_pmx_index(read, self)
@@ -161,6 +353,43 @@ impl IndexSize {
     }
 }
 
+/// the known format versions
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PmxVersion {
+    V2_0,
+    V2_1,
+}
+
+impl PmxVersion {
+    /// the latest known version a version number covers,
+    /// `None` for numbers below 2.0 or NaN
+    pub fn from_f32(version: f32) -> Option<Self> {
+        if version >= 2.1 * (1.0 - f32::EPSILON) {
+            Some(Self::V2_1)
+        } else if version >= 2.0 * (1.0 - f32::EPSILON) {
+            Some(Self::V2_0)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_f32(self) -> f32 {
+        match self {
+            PmxVersion::V2_0 => 2.0,
+            PmxVersion::V2_1 => 2.1,
+        }
+    }
+
+    pub fn supports_soft_bodies(self) -> bool {
+        self >= Self::V2_1
+    }
+
+    /// the dual quaternion [`crate::vertex::Skin::QDEF`] skin, sdef is already in 2.0
+    pub fn supports_qdef(self) -> bool {
+        self >= Self::V2_1
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Header {
     pub version: f32,
@@ -175,25 +404,143 @@ pub struct Header {
     pub unknown_data: Vec<u8>,
 }
 
+impl Default for Header {
+    /// version 2.0, utf-16, no extra uv and 32 bit indices everywhere
+    fn default() -> Self {
+        Self {
+            version: 2.0,
+            encoding: Encoding::Utf16Le,
+            vertex_ext_vec4: 0,
+            vertex_index: IndexSize::Bit32,
+            texture_index: IndexSize::Bit32,
+            material_index: IndexSize::Bit32,
+            bone_index: IndexSize::Bit32,
+            morph_index: IndexSize::Bit32,
+            rigid_body_index: IndexSize::Bit32,
+            unknown_data: vec![],
+        }
+    }
+}
+
+/// build a [`Header`] from the defaults of [`Header::default`]
+#[derive(Default, Debug, Clone)]
+pub struct HeaderBuilder {
+    header: Header,
+}
+
+impl HeaderBuilder {
+    pub fn version(mut self, version: f32) -> Self {
+        self.header.version = version;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.header.encoding = encoding;
+        self
+    }
+
+    pub fn vertex_ext_vec4(mut self, vertex_ext_vec4: u8) -> Self {
+        self.header.vertex_ext_vec4 = vertex_ext_vec4;
+        self
+    }
+
+    pub fn vertex_index(mut self, size: IndexSize) -> Self {
+        self.header.vertex_index = size;
+        self
+    }
+
+    pub fn texture_index(mut self, size: IndexSize) -> Self {
+        self.header.texture_index = size;
+        self
+    }
+
+    pub fn material_index(mut self, size: IndexSize) -> Self {
+        self.header.material_index = size;
+        self
+    }
+
+    pub fn bone_index(mut self, size: IndexSize) -> Self {
+        self.header.bone_index = size;
+        self
+    }
+
+    pub fn morph_index(mut self, size: IndexSize) -> Self {
+        self.header.morph_index = size;
+        self
+    }
+
+    pub fn rigid_body_index(mut self, size: IndexSize) -> Self {
+        self.header.rigid_body_index = size;
+        self
+    }
+
+    /// set every index size at once
+    pub fn index_size(self, size: IndexSize) -> Self {
+        self.vertex_index(size)
+            .texture_index(size)
+            .material_index(size)
+            .bone_index(size)
+            .morph_index(size)
+            .rigid_body_index(size)
+    }
+
+    pub fn unknown_data(mut self, unknown_data: Vec<u8>) -> Self {
+        self.header.unknown_data = unknown_data;
+        self
+    }
+
+    pub fn build(self) -> Header {
+        self.header
+    }
+}
+
 impl Header {
+    pub fn builder() -> HeaderBuilder {
+        HeaderBuilder::default()
+    }
+
+    /// the header [`crate::pmx_write`] uses, with the narrowest index sizes for the counts
+    /// of the model, 8 bit ones for empty sections.
+    ///
+    /// vertex indices are unsigned and sized by [`IndexSize::from_count_u`], every other
+    /// index is signed, with `-1` for "none", and sized by [`IndexSize::from_count_i`]
     pub fn from_best(version: f32, pmx: &Pmx) -> Self {
         Self {
             version,
             encoding: Encoding::Utf16Le,
             vertex_ext_vec4: pmx.vertices.ext_vec4s.len() as u8,
-            vertex_index: IndexSize::from_count_i(pmx.vertices.count()),
-            texture_index: IndexSize::from_count_u(pmx.textures.count()),
-            material_index: IndexSize::from_count_u(pmx.materials.count()),
-            bone_index: IndexSize::from_count_u(pmx.bones.count()),
-            morph_index: IndexSize::from_count_u(pmx.morphs.count()),
-            rigid_body_index: IndexSize::from_count_u(pmx.rigid_bodies.count()),
+            vertex_index: IndexSize::from_count_u(pmx.vertices.count()),
+            texture_index: IndexSize::from_count_i(pmx.textures.count()),
+            material_index: IndexSize::from_count_i(pmx.materials.count()),
+            bone_index: IndexSize::from_count_i(pmx.bones.count()),
+            morph_index: IndexSize::from_count_i(pmx.morphs.count()),
+            rigid_body_index: IndexSize::from_count_i(pmx.rigid_bodies.count()),
             unknown_data: vec![],
         }
     }
 
+    /// the version number as written, which may be an exotic one
+    pub fn version_f32(&self) -> f32 {
+        self.version
+    }
+
+    /// the known version the version number covers, see [`PmxVersion::from_f32`]
+    pub fn pmx_version(&self) -> Option<PmxVersion> {
+        PmxVersion::from_f32(self.version)
+    }
+
+    pub fn supports_soft_bodies(&self) -> bool {
+        self.pmx_version()
+            .is_some_and(PmxVersion::supports_soft_bodies)
+    }
+
+    pub fn supports_qdef(&self) -> bool {
+        self.pmx_version().is_some_and(PmxVersion::supports_qdef)
+    }
+
     pub fn read<R: Read>(read: &mut R) -> Result<Self, PmxError> {
         let magic = read.read_u32::<LittleEndian>()?;
-        if magic != 0x20584D50 {
+        if magic != PMX_MAGIC {
             return Err(PmxError::MagicError);
         }
 
@@ -204,6 +551,11 @@ impl Header {
         }
         let mut global_data = vec![0_u8; global_data_length as usize];
         read.read_exact(global_data.as_mut_slice())?;
+        Self::from_global_data(version, &global_data)
+    }
+
+    /// the header of `version` described by the global data, at least 8 bytes
+    pub(crate) fn from_global_data(version: f32, global_data: &[u8]) -> Result<Self, PmxError> {
         Ok(Self {
             version,
             encoding: global_data[0].try_into()?,
@@ -219,9 +571,11 @@ impl Header {
     }
 
     pub fn write<W: Write>(&self, write: &mut W) -> Result<(), PmxError> {
-        write.write_u32::<LittleEndian>(0x20584D50)?;
+        let global_data_length = u8::try_from(self.unknown_data.len() + 8)
+            .map_err(|_| PmxError::GlobalDataLengthTooLong)?;
+        write.write_u32::<LittleEndian>(PMX_MAGIC)?;
         write.write_f32::<LittleEndian>(self.version)?;
-        write.write_u8(self.unknown_data.len() as u8 + 8)?;
+        write.write_u8(global_data_length)?;
         write.write_u8(self.encoding as u8)?;
         write.write_u8(self.vertex_ext_vec4)?;
         write.write_u8(self.vertex_index as u8)?;
@@ -233,4 +587,314 @@ impl Header {
         write.write_all(self.unknown_data.as_slice())?;
         Ok(())
     }
+
+    /// the size [`Header::write`] writes
+    pub(crate) fn serialized_size(&self) -> usize {
+        // magic, version, global data length and the 8 known global data bytes
+        4 + 4 + 1 + 8 + self.unknown_data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kits::index_position;
+    use crate::testing::bone;
+    use crate::vertex::Skin;
+    use crate::{pmx_write_to_vec, roundtrip};
+
+    const SIZES: [IndexSize; 3] = [IndexSize::Bit8, IndexSize::Bit16, IndexSize::Bit32];
+
+    #[test]
+    fn none_is_the_all_ones_value_of_every_width() {
+        for size in SIZES {
+            let mut bytes = Vec::new();
+            size.write_i(&mut bytes, -1).unwrap();
+            assert_eq!(bytes, vec![0xFF; size.bytes() as usize]);
+
+            let none = size.read_u(&mut bytes.as_slice()).unwrap();
+            assert_eq!(none, size.none_sentinel_u());
+            assert!(size.is_none(none));
+            assert!(!size.is_none(none - 1));
+
+            let index = size.read_i(&mut bytes.as_slice()).unwrap();
+            assert_eq!(index, -1);
+            assert_eq!(index_position(index), None);
+        }
+        assert_eq!(IndexSize::Bit8.none_sentinel_u(), 0xFF);
+        assert_eq!(IndexSize::Bit16.none_sentinel_u(), 0xFFFF);
+        assert_eq!(IndexSize::Bit32.none_sentinel_u(), 0xFFFF_FFFF);
+        assert_eq!(index_position(0), Some(0));
+    }
+
+    #[test]
+    fn recognizes_the_magic_of_a_written_header() {
+        let mut bytes = Vec::new();
+        Header::default().write(&mut bytes).unwrap();
+        assert!(bytes.starts_with(b"PMX "));
+        assert!(is_pmx(&bytes));
+        assert!(is_pmx(&pmx_write_to_vec(&Pmx::default(), 2.1).unwrap()));
+
+        assert!(!is_pmx(&bytes[..3]));
+        assert!(!is_pmx(b""));
+        assert!(!is_pmx(b"Pmd\0\0\x80\x3F"));
+        bytes[3] = 0;
+        assert!(!is_pmx(&bytes));
+    }
+
+    #[test]
+    fn builds_the_header_it_writes() {
+        let header = Header::builder()
+            .version(2.1)
+            .encoding(Encoding::Utf8)
+            .vertex_ext_vec4(2)
+            .index_size(IndexSize::Bit8)
+            .vertex_index(IndexSize::Bit16)
+            .rigid_body_index(IndexSize::Bit32)
+            .unknown_data(vec![0xAB])
+            .build();
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+
+        let mut expected = b"PMX ".to_vec();
+        expected.extend_from_slice(&2.1_f32.to_le_bytes());
+        expected.extend_from_slice(&[9, 0x01, 2, 0x02, 0x01, 0x01, 0x01, 0x01, 0x04, 0xAB]);
+        assert_eq!(bytes, expected);
+        assert_eq!(Header::read(&mut bytes.as_slice()).unwrap(), header);
+
+        assert_eq!(Header::builder().build(), Header::default());
+    }
+
+    #[test]
+    fn empty_strings_are_a_zero_length_prefix() {
+        for encoding in [Encoding::Utf16Le, Encoding::Utf8] {
+            let mut bytes = Vec::new();
+            encoding.write(&mut bytes, "").unwrap();
+            assert_eq!(bytes, [0, 0, 0, 0], "{encoding:?}");
+
+            bytes.push(0xAB);
+            let mut read = bytes.as_slice();
+            assert_eq!(encoding.read(&mut read).unwrap(), "");
+            assert_eq!(read, [0xAB]);
+            let mut read = bytes.as_slice();
+            let text = encoding.read_borrowed(&mut read, &ReadOptions::default());
+            assert_eq!(text.unwrap(), "");
+            assert_eq!(read, [0xAB]);
+        }
+    }
+
+    #[test]
+    fn odd_utf16_lengths_fail_unless_lenient() {
+        let bytes = [3, 0, 0, 0, b'a', 0, b'b'];
+        let text = Encoding::Utf16Le.read(&mut bytes.as_slice());
+        assert!(matches!(text, Err(PmxError::OddUtf16Length(3))));
+
+        let options = ReadOptions {
+            lenient_strings: true,
+            ..ReadOptions::default()
+        };
+        let text = Encoding::Utf16Le.read_with(&mut bytes.as_slice(), &options);
+        assert_eq!(text.unwrap(), "a\u{FFFD}");
+    }
+
+    #[test]
+    fn strings_over_the_limit_fail_before_reading_them() {
+        let options = ReadOptions {
+            max_string_bytes: 4,
+            ..ReadOptions::default()
+        };
+        // the limit counts bytes, "abcd" takes 4 of them in utf-8 and 8 in utf-16
+        let mut bytes = Vec::new();
+        Encoding::Utf8.write(&mut bytes, "abcd").unwrap();
+        let text = Encoding::Utf8.read_with(&mut bytes.as_slice(), &options);
+        assert_eq!(text.unwrap(), "abcd");
+        let mut bytes = Vec::new();
+        Encoding::Utf16Le.write(&mut bytes, "abcd").unwrap();
+        let mut read = bytes.as_slice();
+        let text = Encoding::Utf16Le.read_with(&mut read, &options);
+        assert!(matches!(text, Err(PmxError::StringTooLong(8))));
+        assert_eq!(read.len(), 8);
+
+        let bytes = [5, 0, 0, 0, b'a', b'b', b'c', b'd', b'e'];
+        let mut read = bytes.as_slice();
+        let text = Encoding::Utf8.read_with(&mut read, &options);
+        assert!(matches!(text, Err(PmxError::StringTooLong(5))));
+        assert_eq!(read, b"abcde");
+        let mut read = bytes.as_slice();
+        let text = Encoding::Utf8.read_borrowed(&mut read, &options);
+        assert!(matches!(text, Err(PmxError::StringTooLong(5))));
+        let text = Encoding::Utf8.read_limited(&mut &bytes[..], &ReadOptions::default(), 4);
+        assert!(matches!(text, Err(PmxError::StringTooLong(5))));
+    }
+
+    /// a model of `vertices` vertices and `bones` bones, a chain from the root,
+    /// whose last vertex and bone are referenced by an element and a vertex
+    fn sized(vertices: u32, bones: i32) -> Pmx {
+        let mut pmx = Pmx::default();
+        for _ in 0..vertices {
+            pmx.vertices.position3s.extend([0.0, 1.0, 2.0]);
+            pmx.vertices.normal3s.extend([0.0, 0.0, 1.0]);
+            pmx.vertices.uv2s.extend([0.5, 0.5]);
+            pmx.vertices.edges.push(1.0);
+            pmx.vertices.skins.push(Skin::BDEF1 {
+                bone_index: bones - 1,
+            });
+        }
+        pmx.elements.element_indices = vec![0, 1, vertices - 1];
+        pmx.bones.bones = (0..bones).map(|i| bone("bone", i - 1)).collect();
+        pmx
+    }
+
+    #[test]
+    fn writes_and_reads_back_bone_counts_around_the_8_bit_boundary() {
+        for (bones, size) in [
+            (126, IndexSize::Bit8),
+            (127, IndexSize::Bit16),
+            (128, IndexSize::Bit16),
+            (129, IndexSize::Bit16),
+        ] {
+            let pmx = sized(3, bones);
+            let header = Header::from_best(2.0, &pmx);
+            assert_eq!(header.bone_index, size, "{bones} bones");
+            let bytes = pmx_write_to_vec(&pmx, 2.0).unwrap();
+            assert_eq!(Header::read(&mut bytes.as_slice()).unwrap(), header);
+            assert_eq!(roundtrip(&pmx, 2.0).unwrap(), pmx, "{bones} bones");
+        }
+    }
+
+    #[test]
+    fn writes_and_reads_back_vertex_counts_around_the_8_bit_boundary() {
+        for (vertices, size) in [
+            (254, IndexSize::Bit8),
+            (255, IndexSize::Bit16),
+            (256, IndexSize::Bit16),
+        ] {
+            let pmx = sized(vertices, 1);
+            let header = Header::from_best(2.0, &pmx);
+            assert_eq!(header.vertex_index, size, "{vertices} vertices");
+            assert_eq!(roundtrip(&pmx, 2.0).unwrap(), pmx, "{vertices} vertices");
+        }
+    }
+
+    /// "日本語" in Shift-JIS, not valid utf-8
+    fn shift_jis_string() -> Vec<u8> {
+        let text = [0x93, 0xFA, 0x96, 0x7B, 0x8C, 0xEA];
+        let mut bytes = (text.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&text);
+        bytes
+    }
+
+    #[test]
+    #[cfg(feature = "shift_jis")]
+    fn decodes_shift_jis_in_a_utf8_model_with_the_fallback() {
+        let bytes = shift_jis_string();
+        let options = ReadOptions {
+            shift_jis_fallback: true,
+            ..ReadOptions::default()
+        };
+        let text = Encoding::Utf8.read_with(&mut bytes.as_slice(), &options);
+        assert_eq!(text.unwrap(), "日本語");
+
+        let text = Encoding::Utf8.read_with(&mut bytes.as_slice(), &ReadOptions::default());
+        assert!(matches!(text, Err(PmxError::EncodingError)));
+    }
+
+    #[test]
+    #[cfg(not(feature = "shift_jis"))]
+    fn ignores_the_shift_jis_fallback_without_the_feature() {
+        let bytes = shift_jis_string();
+        let options = ReadOptions {
+            shift_jis_fallback: true,
+            ..ReadOptions::default()
+        };
+        let text = Encoding::Utf8.read_with(&mut bytes.as_slice(), &options);
+        assert!(matches!(text, Err(PmxError::EncodingError)));
+    }
+
+    #[test]
+    fn only_version_2_1_reads_soft_bodies() {
+        assert_eq!(PmxVersion::from_f32(2.0), Some(PmxVersion::V2_0));
+        assert_eq!(PmxVersion::from_f32(2.1), Some(PmxVersion::V2_1));
+        assert_eq!(PmxVersion::from_f32(2.5), Some(PmxVersion::V2_1));
+        assert_eq!(PmxVersion::from_f32(1.0), None);
+        assert_eq!(PmxVersion::from_f32(f32::NAN), None);
+        assert_eq!(PmxVersion::V2_1.to_f32(), 2.1);
+        assert!(!PmxVersion::V2_0.supports_soft_bodies());
+        assert!(PmxVersion::V2_1.supports_soft_bodies());
+
+        let mut pmx = crate::testing::sample();
+        pmx.vertices.skins.pop();
+        pmx.vertices.skins.push(Skin::BDEF1 { bone_index: 0 });
+        let mut bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        let (header, read, trailing) = crate::pmx_read_full(&mut bytes.as_slice()).unwrap();
+        assert_eq!(header.pmx_version(), Some(PmxVersion::V2_1));
+        assert_eq!(read.soft_bodies.len(), 1);
+        assert!(trailing.is_empty());
+
+        // the same bytes under a 2.0 header leave the soft body section unread
+        bytes[4..8].copy_from_slice(&2.0f32.to_le_bytes());
+        let (header, read, trailing) = crate::pmx_read_full(&mut bytes.as_slice()).unwrap();
+        assert_eq!(header.version_f32(), 2.0);
+        assert!(!header.supports_soft_bodies());
+        assert!(read.soft_bodies.is_empty());
+        assert!(!trailing.is_empty());
+    }
+
+    #[test]
+    fn lone_surrogates_fail_unless_lenient() {
+        let lenient = ReadOptions {
+            lenient_strings: true,
+            ..ReadOptions::default()
+        };
+        let pair = [4, 0, 0, 0, 0x34, 0xD8, 0x1E, 0xDD];
+        for options in [&ReadOptions::default(), &lenient] {
+            let text = Encoding::Utf16Le.read_with(&mut pair.as_slice(), options);
+            assert_eq!(text.unwrap(), "\u{1D11E}");
+        }
+
+        // a high surrogate followed by "a" instead of a low one
+        let lone = [4, 0, 0, 0, 0x34, 0xD8, b'a', 0];
+        let text = Encoding::Utf16Le.read(&mut lone.as_slice());
+        assert!(matches!(text, Err(PmxError::Utf16Error(4))));
+        let text = Encoding::Utf16Le.read_with(&mut lone.as_slice(), &lenient);
+        assert_eq!(text.unwrap(), "\u{FFFD}a");
+    }
+
+    #[test]
+    fn global_data_over_255_bytes_fails_to_write() {
+        let fits = Header::builder().unknown_data(vec![0; 247]).build();
+        let mut bytes = Vec::new();
+        fits.write(&mut bytes).unwrap();
+        assert_eq!(bytes[8], 255);
+
+        let oversized = Header::builder().unknown_data(vec![0; 248]).build();
+        let mut bytes = Vec::new();
+        let e = oversized.write(&mut bytes).unwrap_err();
+        assert!(matches!(e, PmxError::GlobalDataLengthTooLong));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn encodings_and_index_sizes_parse_by_name() {
+        for name in ["utf8", "UTF-8"] {
+            assert_eq!(name.parse::<Encoding>().unwrap(), Encoding::Utf8);
+        }
+        for name in ["utf16", "utf-16", "UTF16LE", "utf-16le"] {
+            assert_eq!(name.parse::<Encoding>().unwrap(), Encoding::Utf16Le);
+        }
+        for name in ["", "utf32", "shift_jis"] {
+            let e = name.parse::<Encoding>().unwrap_err();
+            assert!(matches!(e, PmxError::UnknownEncodingName(n) if n == name));
+        }
+
+        let sizes = [("8", "1"), ("16", "2"), ("32", "4")];
+        for ((bits, bytes), size) in sizes.into_iter().zip(SIZES) {
+            assert_eq!(bits.parse::<IndexSize>().unwrap(), size);
+            assert_eq!(bytes.parse::<IndexSize>().unwrap(), size);
+        }
+        for name in ["", "0", "64", "bit8", " 8"] {
+            let e = name.parse::<IndexSize>().unwrap_err();
+            assert!(matches!(e, PmxError::UnknownIndexSizeName(n) if n == name));
+        }
+    }
 }
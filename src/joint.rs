@@ -1,11 +1,16 @@
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_f32x3, read_vec, write_f32x3};
+use crate::kits::{
+    flip_z, flip_z_euler, flip_z_euler_range, flip_z_range, impl_approx_eq, impl_approx_eq_exact,
+    impl_collection, impl_eq_ignoring_english, index_position, read_f32x3, read_vec, sanitize,
+    skip, skip_vec, vec_size, write_f32x3, Affine,
+};
+use crate::options::ReadOptions;
 use crate::RigidBodyIndex;
 
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -17,11 +22,18 @@ impl Joints {
     pub fn count(&self) -> u32 {
         self.joints.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            joints: read_vec(read, |read| Joint::read(header, read))?,
+            joints: read_vec(read, |read| Joint::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| Joint::skip(header, read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.joints {
@@ -29,8 +41,56 @@ impl Joints {
         }
         Ok(())
     }
+    /// the size [`Joints::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.joints, |i| i.serialized_size(header))
+    }
+
+    /// the joints attached to a rigid body on either side, in joint order
+    pub fn for_rigid_body(&self, rigid_index: u32) -> Vec<usize> {
+        let rigid = Some(rigid_index as usize);
+        (0..self.joints.len())
+            .filter(|&i| self.joints[i].a_rigid() == rigid || self.joints[i].b_rigid() == rigid)
+            .collect()
+    }
+
+    pub(crate) fn for_each_rigid_body_index_mut(
+        &mut self,
+        f: &mut impl FnMut(&mut RigidBodyIndex),
+    ) {
+        for joint in &mut self.joints {
+            f(&mut joint.a_rigid_index);
+            f(&mut joint.b_rigid_index);
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        self.joints.iter_mut().map(Joint::sanitize_floats).sum()
+    }
+
+    pub(crate) fn flip_z(&mut self) {
+        for joint in &mut self.joints {
+            flip_z(&mut joint.position);
+            flip_z_euler(&mut joint.rotation);
+            flip_z_range(&mut joint.move_limit_down, &mut joint.move_limit_up);
+            flip_z_euler_range(&mut joint.rotation_limit_down, &mut joint.rotation_limit_up);
+        }
+    }
+
+    pub(crate) fn transform(&mut self, transform: &Affine) {
+        for joint in &mut self.joints {
+            transform.point(&mut joint.position);
+            joint
+                .move_limit_down
+                .iter_mut()
+                .chain(&mut joint.move_limit_up)
+                .for_each(|i| transform.length(i));
+        }
+    }
 }
 
+impl_collection!(Joints, joints, Joint);
+
 #[derive(Clone, PartialEq)]
 pub struct Joint {
     pub name: String,
@@ -57,10 +117,14 @@ impl Debug for Joint {
 }
 
 impl Joint {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             joint_type: JointType::try_from(read.read_u8()?)?,
             a_rigid_index: header.rigid_body_index.read(read)?,
             b_rigid_index: header.rigid_body_index.read(read)?,
@@ -74,6 +138,12 @@ impl Joint {
             spring_const_rotation: read_f32x3(read)?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        // joint type, rigid bodies and eight vectors
+        skip(read, 1 + header.rigid_body_index.bytes() * 2 + 8 * 12)
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -90,9 +160,112 @@ impl Joint {
         write_f32x3(write, self.spring_const_rotation)?;
         Ok(())
     }
+
+    /// the size [`Joint::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            // joint type, rigid bodies and eight vectors
+            + 1 + header.rigid_body_index.bytes() as usize * 2 + 8 * 12
+    }
+
+    /// the rigid body a, `None` for the `-1` index of a joint anchored to the world,
+    /// which is written as the all-ones value of the rigid body index width
+    pub fn a_rigid(&self) -> Option<usize> {
+        index_position(self.a_rigid_index)
+    }
+
+    /// the rigid body b, `None` for the `-1` index of a joint anchored to the world
+    pub fn b_rigid(&self) -> Option<usize> {
+        index_position(self.b_rigid_index)
+    }
+
+    /// the limits and springs of a [`JointType::Spring6DOF`] joint,
+    /// or the limits of a [`JointType::SixDof`] one which ignores the springs
+    pub fn as_six_dof(&self) -> Option<SixDofJoint> {
+        let spring = match self.joint_type {
+            JointType::Spring6DOF => Some((self.spring_const_move, self.spring_const_rotation)),
+            JointType::SixDof => None,
+            _ => return None,
+        };
+        Some(SixDofJoint {
+            move_limit: (self.move_limit_down, self.move_limit_up),
+            rotation_limit: (self.rotation_limit_down, self.rotation_limit_up),
+            spring,
+        })
+    }
+
+    /// the pivot of a [`JointType::P2P`] joint, which ignores the limits and springs
+    pub fn as_p2p(&self) -> Option<P2PJoint> {
+        (self.joint_type == JointType::P2P).then_some(P2PJoint {
+            position: self.position,
+        })
+    }
+
+    /// the x components of the limits of a [`JointType::Slider`] joint, which ignores the rest
+    pub fn as_slider(&self) -> Option<SliderJoint> {
+        (self.joint_type == JointType::Slider).then_some(SliderJoint {
+            move_limit: (self.move_limit_down[0], self.move_limit_up[0]),
+            rotation_limit: (self.rotation_limit_down[0], self.rotation_limit_up[0]),
+        })
+    }
+
+    /// the x components of the rotation limits of a [`JointType::Hinge`] joint
+    pub fn as_hinge(&self) -> Option<HingeJoint> {
+        (self.joint_type == JointType::Hinge).then_some(HingeJoint {
+            rotation_limit: (self.rotation_limit_down[0], self.rotation_limit_up[0]),
+        })
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        sanitize(
+            self.position
+                .iter_mut()
+                .chain(&mut self.rotation)
+                .chain(&mut self.move_limit_down)
+                .chain(&mut self.move_limit_up)
+                .chain(&mut self.rotation_limit_down)
+                .chain(&mut self.rotation_limit_up)
+                .chain(&mut self.spring_const_move)
+                .chain(&mut self.spring_const_rotation),
+        )
+    }
+}
+
+/// the parameters a 6dof joint uses, see [`Joint::as_six_dof`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SixDofJoint {
+    /// the `(down, up)` move limits
+    pub move_limit: ([f32; 3], [f32; 3]),
+    /// the `(down, up)` rotation limits
+    pub rotation_limit: ([f32; 3], [f32; 3]),
+    /// the `(move, rotation)` spring constants, `None` without springs
+    pub spring: Option<([f32; 3], [f32; 3])>,
+}
+
+/// the parameters a point to point joint uses, see [`Joint::as_p2p`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct P2PJoint {
+    pub position: [f32; 3],
+}
+
+/// the parameters a slider joint uses, see [`Joint::as_slider`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SliderJoint {
+    /// the `(down, up)` limits of the move along the slider axis
+    pub move_limit: (f32, f32),
+    /// the `(down, up)` limits of the rotation around the slider axis
+    pub rotation_limit: (f32, f32),
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// the parameters a hinge joint uses, see [`Joint::as_hinge`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HingeJoint {
+    /// the `(down, up)` limits of the rotation around the hinge axis
+    pub rotation_limit: (f32, f32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum JointType {
     Spring6DOF = 0x00,
@@ -118,3 +291,115 @@ impl TryFrom<u8> for JointType {
         }
     }
 }
+
+impl Display for JointType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Spring6DOF => "Spring 6DOF",
+            Self::SixDof => "6DOF",
+            Self::P2P => "Point to Point",
+            Self::ConeTwist => "Cone Twist",
+            Self::Slider => "Slider",
+            Self::Hinge => "Hinge",
+        })
+    }
+}
+
+impl_approx_eq!(Joints { joints });
+impl_approx_eq!(Joint {
+    name,
+    name_en,
+    joint_type,
+    a_rigid_index,
+    b_rigid_index,
+    position,
+    rotation,
+    move_limit_down,
+    move_limit_up,
+    rotation_limit_down,
+    rotation_limit_up,
+    spring_const_move,
+    spring_const_rotation,
+});
+impl_eq_ignoring_english!(Joint {
+    name,
+    joint_type,
+    a_rigid_index,
+    b_rigid_index,
+    position,
+    rotation,
+    move_limit_down,
+    move_limit_up,
+    rotation_limit_down,
+    rotation_limit_up,
+    spring_const_move,
+    spring_const_rotation,
+});
+impl_approx_eq_exact!(JointType);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Encoding, IndexSize};
+    use crate::testing::joint;
+
+    #[test]
+    fn world_anchor_is_the_all_ones_index_of_every_width() {
+        for size in [IndexSize::Bit8, IndexSize::Bit16, IndexSize::Bit32] {
+            let header = Header::builder()
+                .encoding(Encoding::Utf8)
+                .index_size(size)
+                .build();
+            let joint = joint("j", -1, 1);
+            let mut bytes = Vec::new();
+            joint.write(&header, &mut bytes).unwrap();
+
+            // the names "j" and "" and the joint type come first
+            let width = size.bytes() as usize;
+            assert_eq!(bytes[10..10 + width], vec![0xFF; width], "{size:?}");
+            assert_eq!(size.read_u(&mut &bytes[10 + width..]).unwrap(), 1);
+
+            let back =
+                Joint::read(&header, &ReadOptions::default(), &mut bytes.as_slice()).unwrap();
+            assert_eq!(back, joint, "{size:?}");
+            assert_eq!(back.a_rigid(), None);
+            assert_eq!(back.b_rigid(), Some(1));
+        }
+    }
+
+    #[test]
+    fn joint_type_displays_its_english_name() {
+        assert_eq!(JointType::Spring6DOF.to_string(), "Spring 6DOF");
+        assert_eq!(JointType::P2P.to_string(), "Point to Point");
+    }
+
+    #[test]
+    fn hinge_and_slider_views_take_the_x_limits() {
+        let mut hinge = joint("hinge", 0, 1);
+        hinge.joint_type = JointType::Hinge;
+        hinge.rotation_limit_down = [-0.5, -1.0, -2.0];
+        hinge.rotation_limit_up = [0.25, 1.0, 2.0];
+        assert_eq!(
+            hinge.as_hinge(),
+            Some(HingeJoint {
+                rotation_limit: (-0.5, 0.25)
+            })
+        );
+        assert_eq!(hinge.as_slider(), None);
+        assert!(hinge.as_six_dof().is_none());
+
+        let mut slider = hinge.clone();
+        slider.joint_type = JointType::Slider;
+        slider.move_limit_down = [-3.0, -1.0, -1.0];
+        slider.move_limit_up = [4.0, 1.0, 1.0];
+        assert_eq!(
+            slider.as_slider(),
+            Some(SliderJoint {
+                move_limit: (-3.0, 4.0),
+                rotation_limit: (-0.5, 0.25),
+            })
+        );
+        assert_eq!(slider.as_hinge(), None);
+        assert_eq!(slider.as_p2p(), None);
+    }
+}
@@ -3,24 +3,102 @@ use std::io::{Read, Write};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
+use crate::header::IndexSize;
+
+/// a reader that counts the bytes consumed from the inner reader
+pub(crate) struct CountingRead<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> CountingRead<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Read for CountingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// a writer that counts the bytes accepted by the inner writer
+pub(crate) struct CountingWrite<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWrite<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<W: Write> Write for CountingWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// a writer feeding the 64 bit fnv-1a hash, which is the same on every platform and release
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// read a fixed number of floats with a single `read_exact`
+#[inline(always)]
+pub(crate) fn read_f32s<R: Read, const N: usize>(read: &mut R) -> Result<[f32; N], std::io::Error> {
+    let mut value = [0.0; N];
+    read.read_f32_into::<LittleEndian>(&mut value)?;
+    Ok(value)
+}
 
 #[inline(always)]
 pub(crate) fn read_f32x3<R: Read>(read: &mut R) -> Result<[f32; 3], std::io::Error> {
-    Ok([
-        read.read_f32::<LittleEndian>()?,
-        read.read_f32::<LittleEndian>()?,
-        read.read_f32::<LittleEndian>()?,
-    ])
+    read_f32s(read)
 }
 
 #[inline(always)]
 pub(crate) fn read_f32x4<R: Read>(read: &mut R) -> Result<[f32; 4], std::io::Error> {
-    Ok([
-        read.read_f32::<LittleEndian>()?,
-        read.read_f32::<LittleEndian>()?,
-        read.read_f32::<LittleEndian>()?,
-        read.read_f32::<LittleEndian>()?,
-    ])
+    read_f32s(read)
 }
 
 #[inline(always)]
@@ -49,6 +127,33 @@ pub(crate) fn read_bool<R: Read>(read: &mut R) -> Result<bool, PmxError> {
     }
 }
 
+/// consume `length` bytes without keeping them
+pub(crate) fn skip<R: Read>(read: &mut R, length: u64) -> Result<(), PmxError> {
+    let skipped = std::io::copy(&mut read.take(length), &mut std::io::sink())?;
+    if skipped != length {
+        return Err(PmxError::UnexpectedEof);
+    }
+    Ok(())
+}
+
+/// skip a counted list of elements, returning the count
+#[inline(always)]
+pub(crate) fn skip_vec<R: Read, F: FnMut(&mut R) -> Result<(), PmxError>>(
+    read: &mut R,
+    mut f: F,
+) -> Result<u32, PmxError> {
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        f(read.by_ref())?;
+    }
+    Ok(count)
+}
+
+/// the size of a counted list of elements as written, the count included
+pub(crate) fn vec_size<T>(items: &[T], f: impl Fn(&T) -> usize) -> usize {
+    4 + items.iter().map(f).sum::<usize>()
+}
+
 #[inline(always)]
 pub(crate) fn read_vec<R: Read, F: FnMut(&mut R) -> Result<T, PmxError>, T>(
     read: &mut R,
@@ -61,3 +166,352 @@ pub(crate) fn read_vec<R: Read, F: FnMut(&mut R) -> Result<T, PmxError>, T>(
     }
     Ok(r)
 }
+
+/// read a counted section, passing each element and its index to `visit`
+pub(crate) fn visit_vec<R: Read, F: FnMut(&mut R) -> Result<T, PmxError>, T>(
+    read: &mut R,
+    mut f: F,
+    mut visit: impl FnMut(u32, T),
+) -> Result<(), PmxError> {
+    let count = read.read_u32::<LittleEndian>()?;
+    for i in 0..count {
+        visit(i, f(read.by_ref())?);
+    }
+    Ok(())
+}
+
+/// mirror a position or direction through the xy plane
+#[inline(always)]
+pub(crate) fn flip_z(value: &mut [f32; 3]) {
+    value[2] = -value[2];
+}
+
+/// mirror euler angles through the xy plane,
+/// the rotations around x and y change direction while the rotation around z is kept
+#[inline(always)]
+pub(crate) fn flip_z_euler(value: &mut [f32; 3]) {
+    value[0] = -value[0];
+    value[1] = -value[1];
+}
+
+/// mirror a `(min, max)` euler range through the xy plane,
+/// the negated x and y bounds swap sides to keep `min <= max`
+#[inline(always)]
+pub(crate) fn flip_z_euler_range(min: &mut [f32; 3], max: &mut [f32; 3]) {
+    for i in 0..2 {
+        let (low, high) = (-max[i], -min[i]);
+        min[i] = low;
+        max[i] = high;
+    }
+}
+
+/// mirror a `(min, max)` position range through the xy plane,
+/// the negated z bounds swap sides to keep `min <= max`
+#[inline(always)]
+pub(crate) fn flip_z_range(min: &mut [f32; 3], max: &mut [f32; 3]) {
+    let (low, high) = (-max[2], -min[2]);
+    min[2] = low;
+    max[2] = high;
+}
+
+/// an affine transform split into its linear part and translation,
+/// with the cofactor matrix used to transform normals
+pub(crate) struct Affine {
+    linear: [[f32; 3]; 3],
+    translation: [f32; 3],
+    cofactor: [[f32; 3]; 3],
+    determinant: f32,
+}
+
+impl Affine {
+    /// build from a column-major 4x4 matrix, `matrix[3]` holds the translation
+    /// and the last row is ignored
+    pub(crate) fn new(matrix: [[f32; 4]; 4]) -> Self {
+        let linear: [[f32; 3]; 3] =
+            std::array::from_fn(|row| std::array::from_fn(|col| matrix[col][row]));
+        let cofactor = std::array::from_fn(|row| {
+            std::array::from_fn(|col| {
+                let (r1, r2) = ((row + 1) % 3, (row + 2) % 3);
+                let (c1, c2) = ((col + 1) % 3, (col + 2) % 3);
+                linear[r1][c1] * linear[r2][c2] - linear[r1][c2] * linear[r2][c1]
+            })
+        });
+        let determinant = (0..3).map(|i| linear[0][i] * cofactor[0][i]).sum();
+        Self {
+            linear,
+            translation: [matrix[3][0], matrix[3][1], matrix[3][2]],
+            cofactor,
+            determinant,
+        }
+    }
+
+    /// whether the transform mirrors the model, which flips the triangle winding
+    pub(crate) fn mirrors(&self) -> bool {
+        self.determinant < 0.0
+    }
+
+    /// transform a position
+    pub(crate) fn point(&self, value: &mut [f32; 3]) {
+        self.vector(value);
+        for (value, translation) in value.iter_mut().zip(self.translation) {
+            *value += translation;
+        }
+    }
+
+    /// transform an offset, ignoring the translation
+    pub(crate) fn vector(&self, value: &mut [f32; 3]) {
+        *value = mul(&self.linear, *value);
+    }
+
+    /// transform a unit direction, keeping it unit length
+    pub(crate) fn direction(&self, value: &mut [f32; 3]) {
+        self.vector(value);
+        normalize(value);
+    }
+
+    /// transform a normal by the inverse transpose, keeping it unit length
+    pub(crate) fn normal(&self, value: &mut [f32; 3]) {
+        *value = mul(&self.cofactor, *value);
+        if self.mirrors() {
+            value.iter_mut().for_each(|i| *i = -*i);
+        }
+        normalize(value);
+    }
+
+    /// scale a length by the mean scale factor of the transform
+    pub(crate) fn length(&self, value: &mut f32) {
+        *value *= self.determinant.abs().cbrt();
+    }
+}
+
+fn mul(matrix: &[[f32; 3]; 3], value: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|row| (0..3).map(|col| matrix[row][col] * value[col]).sum())
+}
+
+fn normalize(value: &mut [f32; 3]) {
+    let length = value.iter().map(|i| i * i).sum::<f32>().sqrt();
+    if length > 0.0 {
+        value.iter_mut().for_each(|i| *i /= length);
+    }
+}
+
+/// replace the non-finite values with zero, returning how many were replaced
+pub(crate) fn sanitize<'a>(values: impl IntoIterator<Item = &'a mut f32>) -> usize {
+    let mut count = 0;
+    for value in values {
+        if !value.is_finite() {
+            *value = 0.0;
+            count += 1;
+        }
+    }
+    count
+}
+
+/// reset a quaternion with non-finite components to the identity,
+/// returning how many components were non-finite
+pub(crate) fn sanitize_quaternion(value: &mut [f32; 4]) -> usize {
+    let count = value.iter().filter(|i| !i.is_finite()).count();
+    if count > 0 {
+        *value = [0.0, 0.0, 0.0, 1.0];
+    }
+    count
+}
+
+/// the position a signed index points to, `None` for the "none" value.
+///
+/// signed indices are sign extended as they are read, so the "none" value of every
+/// width is the one of [`IndexSize::Bit32`] by then
+pub(crate) fn index_position(index: i32) -> Option<usize> {
+    if IndexSize::Bit32.is_none(index as u32) {
+        return None;
+    }
+    usize::try_from(index).ok()
+}
+
+/// the cell of a coordinate in a grid of `epsilon` wide cells, so values within `epsilon`
+/// of each other are in the same or neighbouring cells.
+///
+/// an `epsilon` of 0 or less only matches equal values, whose bits are the cell then,
+/// as dividing by it would put every value in the same saturated cell
+pub(crate) fn grid_cell(value: f32, epsilon: f32) -> i64 {
+    if epsilon > 0.0 {
+        (value / epsilon).floor() as i64
+    } else if value == 0.0 {
+        // 0.0 and -0.0 are equal
+        0
+    } else {
+        value.to_bits() as i64
+    }
+}
+
+/// a worklist of the reachable entries of a section, used to drop the unreferenced ones
+pub(crate) struct Reachable {
+    used: Vec<bool>,
+    pending: Vec<usize>,
+}
+
+impl Reachable {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            used: vec![false; count],
+            pending: Vec::new(),
+        }
+    }
+
+    /// mark an entry as reachable, negative and out of range indices are ignored
+    pub(crate) fn mark(&mut self, index: i32) {
+        if let Some(used) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.used.get_mut(i))
+        {
+            if !*used {
+                *used = true;
+                self.pending.push(index as usize);
+            }
+        }
+    }
+
+    /// the next newly reached entry whose own references have not been followed yet
+    pub(crate) fn pop(&mut self) -> Option<usize> {
+        self.pending.pop()
+    }
+
+    /// the old to new index remap keeping the reached entries in order, `-1` for the others
+    pub(crate) fn remap(&self) -> Vec<i32> {
+        let mut next = 0;
+        self.used
+            .iter()
+            .map(|&used| {
+                if used {
+                    next += 1;
+                    next - 1
+                } else {
+                    -1
+                }
+            })
+            .collect()
+    }
+}
+
+/// rewrite an index through an old to new remap, negative and out of range indices are kept
+pub(crate) fn remap_index(index: &mut i32, remap: &[i32]) {
+    if let Some(&i) = usize::try_from(*index).ok().and_then(|i| remap.get(i)) {
+        *index = i;
+    }
+}
+
+/// keep the items whose remap entry is not negative
+pub(crate) fn retain_remapped<T>(items: &mut Vec<T>, remap: &[i32]) {
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        remap[index - 1] >= 0
+    });
+}
+
+/// implement slice access, indexing and iteration for a section that wraps a `Vec`,
+/// the slice giving every section the same `len` and `is_empty`
+macro_rules! impl_collection {
+    ($collection:ty, $field:ident, $item:ty) => {
+        impl std::ops::Deref for $collection {
+            type Target = [$item];
+
+            fn deref(&self) -> &Self::Target {
+                self.$field.as_slice()
+            }
+        }
+
+        impl std::ops::DerefMut for $collection {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                self.$field.as_mut_slice()
+            }
+        }
+
+        impl std::ops::Index<usize> for $collection {
+            type Output = $item;
+
+            fn index(&self, index: usize) -> &Self::Output {
+                &self.$field[index]
+            }
+        }
+
+        impl std::ops::IndexMut<usize> for $collection {
+            fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+                &mut self.$field[index]
+            }
+        }
+
+        impl IntoIterator for $collection {
+            type Item = $item;
+            type IntoIter = std::vec::IntoIter<$item>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.$field.into_iter()
+            }
+        }
+
+        impl<'a> IntoIterator for &'a $collection {
+            type Item = &'a $item;
+            type IntoIter = std::slice::Iter<'a, $item>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.$field.iter()
+            }
+        }
+
+        impl<'a> IntoIterator for &'a mut $collection {
+            type Item = &'a mut $item;
+            type IntoIter = std::slice::IterMut<'a, $item>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.$field.iter_mut()
+            }
+        }
+    };
+}
+
+pub(crate) use impl_collection;
+
+/// implement [`crate::approx::ApproxEq`] field by field, every field has to be listed
+macro_rules! impl_approx_eq {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::approx::ApproxEq for $type {
+            fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+                let Self { $($field),* } = self;
+                true $(&& $crate::approx::ApproxEq::approx_eq($field, &other.$field, epsilon))*
+            }
+        }
+    };
+}
+
+pub(crate) use impl_approx_eq;
+
+/// implement [`crate::approx::ApproxEq`] as `==` for types without floats
+macro_rules! impl_approx_eq_exact {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl $crate::approx::ApproxEq for $type {
+                fn approx_eq(&self, other: &Self, _epsilon: f32) -> bool {
+                    self == other
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_approx_eq_exact;
+
+/// implement `eq_ignoring_english`, `==` on every field but `name_en`,
+/// every other field has to be listed, see [`crate::pmx::Pmx::eq_ignoring_english`]
+macro_rules! impl_eq_ignoring_english {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $type {
+            pub(crate) fn eq_ignoring_english(&self, other: &Self) -> bool {
+                let Self { name_en: _, $($field),* } = self;
+                true $(&& *$field == other.$field)*
+            }
+        }
+    };
+}
+
+pub(crate) use impl_eq_ignoring_english;
@@ -1,12 +1,19 @@
 //! load and save '.pmx' file
 
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
 
 use crate::error::PmxError;
-use crate::header::Header;
+use crate::header::{Encoding, Header};
+use crate::kits::{CountingRead, CountingWrite};
+use crate::options::{ReadOptions, WriteOptions};
 use crate::pmx::Pmx;
+use crate::progress::ReadStage;
 
+pub mod approx;
 pub mod bone;
+pub mod borrowed;
 pub mod display_frame;
 pub mod element_index;
 pub mod error;
@@ -15,13 +22,34 @@ pub mod joint;
 pub mod material;
 pub mod model_info;
 pub mod morph;
+pub mod options;
+pub mod pmd;
 pub mod pmx;
+pub mod progress;
+pub mod reader;
 pub mod rigid_body;
 pub mod soft_body;
+pub mod stats;
 pub mod texture;
 pub mod vertex;
+pub mod visitor;
 
+#[cfg(feature = "tokio")]
+mod async_read;
 pub(crate) mod kits;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(test)]
+mod testing;
+
+#[cfg(feature = "tokio")]
+pub use crate::async_read::{pmx_read_async, pmx_read_async_with};
+#[cfg(feature = "mmap")]
+pub use crate::mmap::{pmx_read_mmap, pmx_read_mmap_with};
+#[cfg(feature = "rayon")]
+pub use crate::parallel::{pmx_from_slice_parallel, pmx_from_slice_parallel_with};
 
 pub type VertexIndex = u32;
 pub type TextureIndex = i32;
@@ -30,15 +58,384 @@ pub type BoneIndex = i32;
 pub type MorphIndex = i32;
 pub type RigidBodyIndex = i32;
 
+/// read a model, errors are wrapped in [`PmxError::At`] with the offset where parsing failed,
+/// [`PmxError::root`] giving the error itself, e.g. [`PmxError::Truncated`] for a cut file
 pub fn pmx_read<R: Read>(read: &mut R) -> Result<(Header, Pmx), PmxError> {
-    let header = Header::read(read)?;
-    let pmx = Pmx::read(&header, read)?;
+    pmx_read_with(read, &ReadOptions::default())
+}
+
+/// read a model with non-default options, see [`pmx_read`]
+pub fn pmx_read_with<R: Read>(
+    read: &mut R,
+    options: &ReadOptions,
+) -> Result<(Header, Pmx), PmxError> {
+    let mut read = CountingRead::new(read);
+    pmx_read_inner(&mut read, options).map_err(|e| PmxError::At {
+        offset: read.count(),
+        source: Box::new(e),
+    })
+}
+
+/// read a model like [`pmx_read`], also returning the bytes after its last section,
+/// which some editors use to append their own data.
+///
+/// the bytes are the ones [`ReadOptions::keep_trailing`] keeps in [`Pmx::trailing`],
+/// moved out of the model, whose [`Pmx::trailing`] is left empty
+pub fn pmx_read_full<R: Read>(read: &mut R) -> Result<(Header, Pmx, Vec<u8>), PmxError> {
+    let options = ReadOptions {
+        keep_trailing: true,
+        ..ReadOptions::default()
+    };
+    let (header, mut pmx) = pmx_read_with(read, &options)?;
+    let trailing = std::mem::take(&mut pmx.trailing);
+    Ok((header, pmx, trailing))
+}
+
+/// read a model like [`pmx_read`], calling `progress` at every section boundary
+/// with the counts read from the file, see [`ReadStage`]
+pub fn pmx_read_progress<R: Read>(
+    read: &mut R,
+    mut progress: impl FnMut(ReadStage),
+) -> Result<(Header, Pmx), PmxError> {
+    let mut read = CountingRead::new(read);
+    pmx_read_progress_inner(&mut read, &mut progress).map_err(|e| PmxError::At {
+        offset: read.count(),
+        source: Box::new(e),
+    })
+}
+
+fn pmx_read_progress_inner<R: Read>(
+    read: &mut R,
+    progress: &mut impl FnMut(ReadStage),
+) -> Result<(Header, Pmx), PmxError> {
+    let header = Header::read(read).map_err(|e| e.in_section("header"))?;
+    progress(ReadStage::Header);
+    let pmx = Pmx::read_progress(&header, &ReadOptions::default(), read, progress)?;
+    Ok((header, pmx))
+}
+
+fn pmx_read_inner<R: Read>(read: &mut R, options: &ReadOptions) -> Result<(Header, Pmx), PmxError> {
+    let header = Header::read(read).map_err(|e| e.in_section("header"))?;
+    let pmx = Pmx::read(&header, options, read)?;
     Ok((header, pmx))
 }
 
 pub fn pmx_write<W: Write>(write: &mut W, pmx: &Pmx, version: f32) -> Result<(), PmxError> {
-    let header = Header::from_best(version, pmx);
+    pmx_write_with(write, pmx, version, &WriteOptions::default())
+}
+
+/// write a model like [`pmx_write`] with the given options
+pub fn pmx_write_with<W: Write>(
+    write: &mut W,
+    pmx: &Pmx,
+    version: f32,
+    options: &WriteOptions,
+) -> Result<(), PmxError> {
+    if options.reject_non_finite {
+        pmx.reject_non_finite()?;
+    }
+    let mut header = Header::from_best(version, pmx);
+    header.encoding = options.encoding;
     header.write(write)?;
     pmx.write(&header, write)?;
     Ok(())
 }
+
+/// write a model like [`pmx_write`] into a vector allocated once at the right size,
+/// the size being added up without writing the model, see [`Pmx::serialized_size`]
+pub fn pmx_write_to_vec(pmx: &Pmx, version: f32) -> Result<Vec<u8>, PmxError> {
+    let header = Header::from_best(version, pmx);
+    let mut bytes = Vec::with_capacity(pmx.serialized_size(&header));
+    header.write(&mut bytes)?;
+    pmx.write(&header, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// write a model like [`pmx_write`] once its bytes are checked to read back as the same model,
+/// failing with [`PmxError::WriteVerificationFailed`] naming the first section that does not,
+/// or with the error reading them back, in which case nothing is written.
+///
+/// the model is written into memory first. a model holding a NaN never verifies,
+/// NaN being unequal to itself
+pub fn pmx_write_verified<W: Write>(
+    write: &mut W,
+    pmx: &Pmx,
+    version: f32,
+) -> Result<(), PmxError> {
+    let bytes = pmx_write_to_vec(pmx, version)?;
+    if let Some(section) = pmx.first_difference(&read_back(&bytes)?) {
+        return Err(PmxError::WriteVerificationFailed { section });
+    }
+    write.write_all(&bytes)?;
+    Ok(())
+}
+
+/// write a model like [`pmx_write`] into memory and read it back,
+/// returning the model as a reader of the file would see it.
+///
+/// the read takes strings of any length and keeps the trailing bytes,
+/// so a model that writes correctly comes back equal, see [`pmx_write_verified`]
+pub fn roundtrip(pmx: &Pmx, version: f32) -> Result<Pmx, PmxError> {
+    read_back(&pmx_write_to_vec(pmx, version)?)
+}
+
+/// read back the bytes of a written model without the limits meant for untrusted files
+fn read_back(bytes: &[u8]) -> Result<Pmx, PmxError> {
+    let options = ReadOptions {
+        max_string_bytes: usize::MAX,
+        keep_trailing: true,
+        ..ReadOptions::default()
+    };
+    let (_, pmx) = pmx_read_with(&mut &bytes[..], &options)?;
+    Ok(pmx)
+}
+
+/// write a model like [`pmx_write`] into a file, created or truncated, through a [`BufWriter`]
+pub fn pmx_write_file<P: AsRef<Path>>(path: P, pmx: &Pmx, version: f32) -> Result<(), PmxError> {
+    let mut write = BufWriter::new(File::create(path)?);
+    pmx_write(&mut write, pmx, version)?;
+    write.flush()?;
+    Ok(())
+}
+
+/// write a model like [`pmx_write`] followed by the trailing bytes returned by [`pmx_read_full`],
+/// which are written in place of [`Pmx::trailing`]
+pub fn pmx_write_full<W: Write>(
+    write: &mut W,
+    pmx: &Pmx,
+    version: f32,
+    trailing: &[u8],
+) -> Result<(), PmxError> {
+    let header = Header::from_best(version, pmx);
+    header.write(write)?;
+    pmx.write_sections(&header, write)?;
+    write.write_all(trailing)?;
+    Ok(())
+}
+
+/// write a model like [`pmx_write`], returning the number of bytes written
+pub fn pmx_write_counted<W: Write>(
+    write: &mut W,
+    pmx: &Pmx,
+    version: f32,
+) -> Result<u64, PmxError> {
+    let mut write = CountingWrite::new(write);
+    pmx_write(&mut write, pmx, version)?;
+    Ok(write.count())
+}
+
+/// write a model with the header as given instead of one computed by [`Header::from_best`],
+/// e.g. the header returned by [`pmx_read`], so its unknown global data, encoding
+/// and index widths are kept.
+/// fails with [`PmxError::IndexError`] if an index of the model does not fit its width
+pub fn pmx_write_preserving_header<W: Write>(
+    write: &mut W,
+    header: &Header,
+    pmx: &Pmx,
+) -> Result<(), PmxError> {
+    header.write(write)?;
+    pmx.write(header, write)?;
+    Ok(())
+}
+
+/// write a model with every string in the chosen encoding, like re-saving a utf-16 model
+/// as utf-8, the same as [`pmx_write_with`] with [`WriteOptions::with_encoding`]
+pub fn pmx_write_with_encoding<W: Write>(
+    write: &mut W,
+    pmx: &Pmx,
+    version: f32,
+    encoding: Encoding,
+) -> Result<(), PmxError> {
+    pmx_write_with(write, pmx, version, &WriteOptions::with_encoding(encoding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::IndexSize;
+    use crate::testing::sample;
+
+    #[test]
+    fn roundtrip_gives_back_a_populated_model() {
+        let pmx = sample();
+        assert_eq!(roundtrip(&pmx, 2.1).unwrap(), pmx);
+
+        let mut utf8 = Vec::new();
+        pmx_write_with_encoding(&mut utf8, &pmx, 2.1, Encoding::Utf8).unwrap();
+        assert_eq!(read_back(&utf8).unwrap(), pmx);
+    }
+
+    #[test]
+    fn truncated_file_reports_the_offset_and_section() {
+        let pmx = sample();
+        let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        let header = Header::from_best(2.1, &pmx);
+        // the morphs start where the sections after the bones end
+        let mut tail = Vec::new();
+        pmx.morphs.write(&header, &mut tail).unwrap();
+        pmx.display_frames.write(&header, &mut tail).unwrap();
+        pmx.rigid_bodies.write(&header, &mut tail).unwrap();
+        pmx.joints.write(&header, &mut tail).unwrap();
+        pmx.soft_bodies.write(&header, &mut tail).unwrap();
+        let morphs = bytes.len() - tail.len();
+
+        let cut = morphs + 20;
+        let e = pmx_read(&mut &bytes[..cut]).unwrap_err();
+        assert_eq!(e.offset(), Some(cut as u64));
+        assert!(matches!(
+            e.root(),
+            PmxError::Truncated { section: "morphs" }
+        ));
+        assert!(matches!(
+            e.into_root(),
+            PmxError::Truncated { section: "morphs" }
+        ));
+
+        let e = pmx_read(&mut &bytes[..3]).unwrap_err();
+        assert_eq!(e.offset(), Some(3));
+        assert!(matches!(
+            e.root(),
+            PmxError::Truncated { section: "header" }
+        ));
+    }
+
+    #[test]
+    fn roundtrip_writes_the_same_bytes_again() {
+        let bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let again = pmx_write_to_vec(&read_back(&bytes).unwrap(), 2.1).unwrap();
+        assert_eq!(again, bytes);
+    }
+
+    #[test]
+    fn trailing_bytes_are_written_once() {
+        let model = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let trailing: Vec<u8> = (0..16).collect();
+        let mut bytes = model.clone();
+        bytes.extend_from_slice(&trailing);
+
+        let options = ReadOptions {
+            keep_trailing: true,
+            ..ReadOptions::default()
+        };
+        let (_, pmx) = pmx_read_with(&mut bytes.as_slice(), &options).unwrap();
+        assert_eq!(pmx.trailing, trailing);
+        assert_eq!(pmx_write_to_vec(&pmx, 2.1).unwrap(), bytes);
+
+        let (_, read, read_trailing) = pmx_read_full(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read_trailing, trailing);
+        assert!(read.trailing.is_empty());
+        let mut written = Vec::new();
+        pmx_write_full(&mut written, &read, 2.1, &read_trailing).unwrap();
+        assert_eq!(written, bytes);
+
+        // the argument replaces the bytes the model holds
+        let mut written = Vec::new();
+        pmx_write_full(&mut written, &pmx, 2.1, &trailing).unwrap();
+        assert_eq!(written, bytes);
+        let mut written = Vec::new();
+        pmx_write_full(&mut written, &pmx, 2.1, &[]).unwrap();
+        assert_eq!(written, model);
+    }
+
+    #[test]
+    fn verified_write_rejects_a_model_that_does_not_read_back() {
+        let pmx = sample();
+        let mut bytes = Vec::new();
+        pmx_write_verified(&mut bytes, &pmx, 2.1).unwrap();
+        assert_eq!(bytes, pmx_write_to_vec(&pmx, 2.1).unwrap());
+
+        let mut nan = sample();
+        nan.vertices.position3s[4] = f32::NAN;
+        let mut bytes = Vec::new();
+        match pmx_write_verified(&mut bytes, &nan, 2.1) {
+            Err(PmxError::WriteVerificationFailed { section }) => assert_eq!(section, "vertices"),
+            e => panic!("{e:?}"),
+        }
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn vec_output_is_what_pmx_write_writes() {
+        let mut pmx = sample();
+        pmx.trailing = vec![1, 2, 3];
+        let mut written = Vec::new();
+        pmx_write(&mut written, &pmx, 2.1).unwrap();
+        let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        assert_eq!(bytes, written);
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+
+    #[test]
+    fn utf16_model_rewrites_as_utf8() {
+        let utf16 = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let (header, pmx) = pmx_read(&mut utf16.as_slice()).unwrap();
+        assert_eq!(header.encoding, Encoding::Utf16Le);
+
+        let mut utf8 = Vec::new();
+        pmx_write_with_encoding(&mut utf8, &pmx, 2.1, Encoding::Utf8).unwrap();
+        let (header, read) = pmx_read(&mut utf8.as_slice()).unwrap();
+        assert_eq!((header.encoding, header.version), (Encoding::Utf8, 2.1));
+        assert_eq!(read, pmx);
+    }
+
+    #[test]
+    fn japanese_names_survive_utf16_to_utf8() {
+        let mut pmx = sample();
+        pmx.bones[0].name = "センター".into();
+        pmx.morphs[0].name = "まばたき".into();
+        pmx.textures.textures[0] = "テクスチャ\\肌.png".into();
+        let mut utf16 = Vec::new();
+        pmx_write_with_encoding(&mut utf16, &pmx, 2.1, Encoding::Utf16Le).unwrap();
+        let (_, from_utf16) = pmx_read(&mut utf16.as_slice()).unwrap();
+
+        let mut utf8 = Vec::new();
+        pmx_write_with_encoding(&mut utf8, &from_utf16, 2.1, Encoding::Utf8).unwrap();
+        let contains = |bytes: &[u8], s: &str| bytes.windows(s.len()).any(|i| i == s.as_bytes());
+        assert!(contains(&utf8, "センター") && !contains(&utf16, "センター"));
+        let (_, read) = pmx_read(&mut utf8.as_slice()).unwrap();
+        assert_eq!(read.info.name, "モデル");
+        assert_eq!(read.info.comment, "コメント");
+        assert_eq!(read.materials[0].name, "材質1");
+        assert_eq!(read.bones[0].name, "センター");
+        assert_eq!(read.morphs[0].name, "まばたき");
+        assert_eq!(read.textures.textures[0], "テクスチャ\\肌.png");
+        assert_eq!(read, pmx);
+    }
+
+    #[test]
+    fn counted_write_returns_the_bytes_written() {
+        let pmx = sample();
+        let mut bytes = Vec::new();
+        let count = pmx_write_counted(&mut bytes, &pmx, 2.1).unwrap();
+        assert_eq!(count, bytes.len() as u64);
+        assert_eq!(bytes, pmx_write_to_vec(&pmx, 2.1).unwrap());
+
+        // only the bytes of this write are counted
+        let count = pmx_write_counted(&mut bytes, &Pmx::default(), 2.0).unwrap();
+        assert_eq!(
+            count,
+            pmx_write_to_vec(&Pmx::default(), 2.0).unwrap().len() as u64
+        );
+    }
+
+    #[test]
+    fn preserving_the_header_keeps_its_unknown_data() {
+        let pmx = sample();
+        let mut header = Header::from_best(2.1, &pmx);
+        header.unknown_data = vec![1, 2, 3];
+        header.vertex_index = IndexSize::Bit32;
+        let mut bytes = Vec::new();
+        pmx_write_preserving_header(&mut bytes, &header, &pmx).unwrap();
+
+        let (read_header, read) = pmx_read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(read, pmx);
+        let mut rewritten = Vec::new();
+        pmx_write_preserving_header(&mut rewritten, &read_header, &read).unwrap();
+        assert_eq!(rewritten, bytes);
+
+        // a fresh header drops the unknown data
+        let (fresh, _) = pmx_read(&mut pmx_write_to_vec(&read, 2.1).unwrap().as_slice()).unwrap();
+        assert!(fresh.unknown_data.is_empty());
+    }
+}
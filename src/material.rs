@@ -1,10 +1,16 @@
+use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_f32x3, read_f32x4, read_vec, write_f32x3, write_f32x4};
+use crate::kits::{
+    impl_approx_eq, impl_approx_eq_exact, impl_collection, impl_eq_ignoring_english,
+    index_position, read_f32x3, read_f32x4, read_vec, skip, skip_vec, vec_size, write_f32x3,
+    write_f32x4,
+};
+use crate::options::ReadOptions;
 use crate::TextureIndex;
 
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -16,11 +22,18 @@ impl Materials {
     pub fn count(&self) -> u32 {
         self.materials.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            materials: read_vec(read, |read| Material::read(header, read))?,
+            materials: read_vec(read, |read| Material::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| Material::skip(header, read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.materials {
@@ -28,8 +41,33 @@ impl Materials {
         }
         Ok(())
     }
+    /// the size [`Materials::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.materials, |i| i.serialized_size(header))
+    }
+}
+
+impl Materials {
+    /// rewrite every texture reference through an old to new index remap,
+    /// negative and out of range indices are kept
+    pub fn remap_textures(&mut self, remap: &[TextureIndex]) {
+        let map = |index: &mut TextureIndex| {
+            if let Some(&i) = usize::try_from(*index).ok().and_then(|i| remap.get(i)) {
+                *index = i;
+            }
+        };
+        for material in &mut self.materials {
+            map(&mut material.texture_index);
+            map(&mut material.env_texture_index);
+            if let ToonTexture::TextureIndex(i) = &mut material.toon_texture {
+                map(i);
+            }
+        }
+    }
 }
 
+impl_collection!(Materials, materials, Material);
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Material {
     pub name: String,
@@ -49,10 +87,36 @@ pub struct Material {
 }
 
 impl Material {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    /// a material of opaque white diffuse, no specular, mid gray ambient,
+    /// a black edge of size 1, no flags, no texture or sphere texture,
+    /// the first shared toon and no element
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            name_en: String::new(),
+            diffuse: [1.0, 1.0, 1.0, 1.0],
+            specular: [0.0, 0.0, 0.0, 5.0],
+            ambient: [0.5, 0.5, 0.5],
+            flags: MaterialFlags::empty(),
+            edge_color: [0.0, 0.0, 0.0, 1.0],
+            edge_size: 1.0,
+            texture_index: -1,
+            env_texture_index: -1,
+            mix: Mix::No,
+            toon_texture: ToonTexture::CommonIndex(0),
+            comment: String::new(),
+            element_count: 0,
+        }
+    }
+
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             diffuse: read_f32x4(read)?,
             specular: read_f32x4(read)?,
             ambient: read_f32x3(read)?,
@@ -63,11 +127,26 @@ impl Material {
             env_texture_index: header.texture_index.read(read)?,
             mix: Mix::try_from(read.read_u8()?)?,
             toon_texture: ToonTexture::read(header, read)?,
-            comment: header.encoding.read(read)?,
+            comment: header.encoding.read_with(read, options)?,
             element_count: read.read_u32::<LittleEndian>()?,
         })
     }
 
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        // diffuse, specular, ambient, flags, edge color and edge size
+        skip(read, 16 + 16 + 12 + 1 + 16 + 4)?;
+        skip(read, header.texture_index.bytes() * 2 + 1)?;
+        match read.read_u8()? {
+            0x00 => header.texture_index.skip(read)?,
+            0x01 => skip(read, 1)?,
+            _ => return Err(PmxError::ToonError),
+        }
+        header.encoding.skip(read)?;
+        skip(read, 4)
+    }
+
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -85,10 +164,91 @@ impl Material {
         write.write_u32::<LittleEndian>(self.element_count)?;
         Ok(())
     }
+
+    /// the size [`Material::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let encoding = header.encoding;
+        let toon = match self.toon_texture {
+            ToonTexture::TextureIndex(_) => header.texture_index.bytes() as usize,
+            ToonTexture::CommonIndex(_) => 1,
+        };
+        // diffuse, specular, ambient, flags, edge color and edge size
+        let colors = 16 + 16 + 12 + 1 + 16 + 4;
+        // texture, sphere texture, sphere mode and the kind of toon
+        let textures = 2 * header.texture_index.bytes() as usize + 1 + 1 + toon;
+        encoding.serialized_size(&self.name)
+            + encoding.serialized_size(&self.name_en)
+            + colors
+            + textures
+            + encoding.serialized_size(&self.comment)
+            + 4
+    }
+
+    /// the texture, `None` for the `-1` "no texture" index, which is written
+    /// as the all-ones value of the texture index width
+    pub fn texture(&self) -> Option<usize> {
+        index_position(self.texture_index)
+    }
+
+    /// the sphere texture, `None` for the `-1` "no texture" index
+    pub fn env_texture(&self) -> Option<usize> {
+        index_position(self.env_texture_index)
+    }
+
+    /// whether a sphere texture is applied, that is a sphere mode other than
+    /// [`Mix::No`] with a texture to apply
+    pub fn has_sphere_texture(&self) -> bool {
+        self.mix != Mix::No && self.env_texture_index >= 0
+    }
+
+    /// how the sphere texture is applied, the same as the `mix` field
+    pub fn sphere_mode(&self) -> SphereMode {
+        self.mix
+    }
+
+    /// both faces are drawn, [`MaterialFlags::DISABLE_CULLING`]
+    pub fn is_double_sided(&self) -> bool {
+        self.flags.contains(MaterialFlags::DISABLE_CULLING)
+    }
+
+    /// [`MaterialFlags::GROUND_SHADOW`]
+    pub fn casts_ground_shadow(&self) -> bool {
+        self.flags.contains(MaterialFlags::GROUND_SHADOW)
+    }
+
+    /// casts a self shadow, [`MaterialFlags::DRAW_SHADOW`]
+    pub fn casts_shadow(&self) -> bool {
+        self.flags.contains(MaterialFlags::DRAW_SHADOW)
+    }
+
+    /// receives a self shadow, [`MaterialFlags::RECEIVE_SHADOW`]
+    pub fn receives_shadow(&self) -> bool {
+        self.flags.contains(MaterialFlags::RECEIVE_SHADOW)
+    }
+
+    /// [`MaterialFlags::HAS_EDGE`]
+    pub fn has_edge(&self) -> bool {
+        self.flags.contains(MaterialFlags::HAS_EDGE)
+    }
+
+    /// [`MaterialFlags::VERTEX_COLOR`], the color is read from the first additional uv
+    pub fn uses_vertex_color(&self) -> bool {
+        self.flags.contains(MaterialFlags::VERTEX_COLOR)
+    }
+
+    /// [`MaterialFlags::POINT_DRAW`]
+    pub fn is_point_draw(&self) -> bool {
+        self.flags.contains(MaterialFlags::POINT_DRAW)
+    }
+
+    /// [`MaterialFlags::LINE_DRAW`]
+    pub fn is_line_draw(&self) -> bool {
+        self.flags.contains(MaterialFlags::LINE_DRAW)
+    }
 }
 
 bitflags::bitflags! {
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
     pub struct MaterialFlags: u8 {
         const DISABLE_CULLING = 0x01;
         const GROUND_SHADOW = 0x02;
@@ -101,15 +261,23 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// how the sphere texture (`env_texture_index`) of a material is applied
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum Mix {
+    /// no sphere texture
     No = 0x00,
+    /// multiplied, the '.sph' kind
     Mul = 0x01,
+    /// added, the '.spa' kind
     Add = 0x02,
+    /// sampled with the first additional uv like a second regular texture
     SubTexture = 0x03,
 }
 
+/// the descriptive name of [`Mix`]
+pub type SphereMode = Mix;
+
 impl TryFrom<u8> for Mix {
     type Error = PmxError;
 
@@ -123,7 +291,19 @@ impl TryFrom<u8> for Mix {
         }
     }
 }
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+
+impl Display for Mix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::No => "None",
+            Self::Mul => "Multiply",
+            Self::Add => "Add",
+            Self::SubTexture => "Subtract",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum ToonTexture {
     TextureIndex(TextureIndex),
     CommonIndex(u8),
@@ -152,3 +332,153 @@ impl ToonTexture {
         Ok(())
     }
 }
+
+impl_approx_eq!(Materials { materials });
+impl_approx_eq!(Material {
+    name,
+    name_en,
+    diffuse,
+    specular,
+    ambient,
+    flags,
+    edge_color,
+    edge_size,
+    texture_index,
+    env_texture_index,
+    mix,
+    toon_texture,
+    comment,
+    element_count,
+});
+impl_eq_ignoring_english!(Material {
+    name,
+    diffuse,
+    specular,
+    ambient,
+    flags,
+    edge_color,
+    edge_size,
+    texture_index,
+    env_texture_index,
+    mix,
+    toon_texture,
+    comment,
+    element_count,
+});
+impl_approx_eq_exact!(MaterialFlags, Mix, ToonTexture);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Encoding, IndexSize};
+
+    #[test]
+    fn new_material_writes_and_reads_back() {
+        let header = Header::builder()
+            .encoding(Encoding::Utf8)
+            .index_size(IndexSize::Bit8)
+            .build();
+        let material = Material::new("m");
+        assert_eq!(material.texture(), None);
+        assert_eq!(material.env_texture(), None);
+        let mut bytes = Vec::new();
+        material.write(&header, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 87);
+
+        // after the names "m" and "", the colors, flags and edge
+        assert_eq!(bytes[9..25], [1.0_f32; 4].map(f32::to_le_bytes).concat());
+        // no texture or sphere texture, no sphere mix and the first shared toon
+        assert_eq!(bytes[74..79], [0xFF, 0xFF, 0x00, 0x01, 0x00]);
+
+        let mut read = bytes.as_slice();
+        let back = Material::read(&header, &ReadOptions::default(), &mut read).unwrap();
+        assert_eq!(back, material);
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn mix_displays_its_english_name() {
+        let names = [Mix::No, Mix::Mul, Mix::Add, Mix::SubTexture].map(|i| i.to_string());
+        assert_eq!(names, ["None", "Multiply", "Add", "Subtract"]);
+    }
+
+    #[test]
+    fn no_texture_round_trips_at_every_width() {
+        let material = Material {
+            toon_texture: ToonTexture::TextureIndex(-1),
+            ..Material::new("m")
+        };
+        for (size, none) in [
+            (IndexSize::Bit8, vec![0xFF]),
+            (IndexSize::Bit16, vec![0xFF; 2]),
+            (IndexSize::Bit32, vec![0xFF; 4]),
+        ] {
+            let header = Header::builder()
+                .encoding(Encoding::Utf8)
+                .index_size(size)
+                .build();
+            let mut bytes = Vec::new();
+            material.write(&header, &mut bytes).unwrap();
+            assert_eq!(bytes.len(), material.serialized_size(&header));
+            // after the names "m" and "" and the colors, flags and edge
+            let textures = 9 + 65;
+            let width = none.len();
+            assert_eq!(bytes[textures..textures + width], none);
+            assert_eq!(bytes[textures + width..textures + 2 * width], none);
+
+            let mut read = bytes.as_slice();
+            let back = Material::read(&header, &ReadOptions::default(), &mut read).unwrap();
+            assert!(read.is_empty());
+            assert_eq!(back, material);
+            assert_eq!((back.texture(), back.env_texture()), (None, None));
+        }
+    }
+
+    #[test]
+    fn sphere_texture_needs_a_mode_and_a_texture() {
+        for mix in [Mix::No, Mix::Mul, Mix::Add, Mix::SubTexture] {
+            for env_texture_index in [-1, 0] {
+                let material = Material {
+                    mix,
+                    env_texture_index,
+                    ..Material::new("m")
+                };
+                assert_eq!(material.sphere_mode(), mix);
+                assert_eq!(
+                    material.has_sphere_texture(),
+                    mix != Mix::No && env_texture_index == 0,
+                    "{mix:?} {env_texture_index}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn each_flag_accessor_reads_only_its_bit() {
+        type Accessor = fn(&Material) -> bool;
+        let accessors: [(MaterialFlags, Accessor); 8] = [
+            (MaterialFlags::DISABLE_CULLING, Material::is_double_sided),
+            (MaterialFlags::GROUND_SHADOW, Material::casts_ground_shadow),
+            (MaterialFlags::DRAW_SHADOW, Material::casts_shadow),
+            (MaterialFlags::RECEIVE_SHADOW, Material::receives_shadow),
+            (MaterialFlags::HAS_EDGE, Material::has_edge),
+            (MaterialFlags::VERTEX_COLOR, Material::uses_vertex_color),
+            (MaterialFlags::POINT_DRAW, Material::is_point_draw),
+            (MaterialFlags::LINE_DRAW, Material::is_line_draw),
+        ];
+        for (flag, _) in accessors {
+            for (other, accessor) in accessors {
+                let only = Material {
+                    flags: flag,
+                    ..Material::new("m")
+                };
+                let all_but = Material {
+                    flags: !flag,
+                    ..Material::new("m")
+                };
+                assert_eq!(accessor(&only), flag == other, "{flag:?} {other:?}");
+                assert_eq!(accessor(&all_but), flag != other, "{flag:?} {other:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! read a model from a memory-mapped file
+
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::kits::CountingRead;
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::pmx_read_with;
+use crate::stats::ModelStats;
+
+/// read a model from a file mapped in memory instead of read through a buffer,
+/// the strings and arrays of the model are still copied out of the mapping.
+///
+/// the mapping only lives during the call and nothing borrowed from it is returned,
+/// but it is only sound as long as the file is not truncated or modified by another
+/// process meanwhile, which may otherwise crash the process or read torn data.
+/// use [`crate::pmx_read`] over a [`File`] for files that may change while being read.
+pub fn pmx_read_mmap<P: AsRef<Path>>(path: P) -> Result<(Header, Pmx), PmxError> {
+    pmx_read_mmap_with(path, &ReadOptions::default())
+}
+
+/// read a model from a memory-mapped file with non-default options, see [`pmx_read_mmap`]
+pub fn pmx_read_mmap_with<P: AsRef<Path>>(
+    path: P,
+    options: &ReadOptions,
+) -> Result<(Header, Pmx), PmxError> {
+    let file = File::open(path)?;
+    // SAFETY: the mapping is dropped before returning and nothing refers to it afterwards,
+    // the caller is told to not modify the file during the call
+    let map = unsafe { Mmap::map(&file)? };
+    pmx_read_with(&mut Cursor::new(&map[..]), options)
+}
+
+impl Pmx {
+    /// the stats of the model in a memory-mapped file, without reading the model.
+    ///
+    /// the sections are walked over in the mapping, see [`pmx_read_mmap`] for its hazards,
+    /// the result being the same as [`Pmx::stats`] on the model read from the file.
+    /// errors are wrapped in [`PmxError::At`] with the offset where parsing failed
+    pub fn stats_from_path<P: AsRef<Path>>(path: P) -> Result<ModelStats, PmxError> {
+        let file = File::open(path)?;
+        // SAFETY: as in pmx_read_mmap_with
+        let map = unsafe { Mmap::map(&file)? };
+        let mut read = CountingRead::new(&map[..]);
+        ModelStats::read(&mut read).map_err(|e| PmxError::At {
+            offset: read.count(),
+            source: Box::new(e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::sample;
+    use crate::{pmx_read, pmx_write_file};
+
+    /// a file in the temp dir named for the test and the process
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pmx_parser_{name}_{}.pmx", std::process::id()))
+    }
+
+    #[test]
+    fn mapped_file_reads_like_pmx_read() {
+        let path = temp_file("mmap");
+        pmx_write_file(&path, &sample(), 2.1).unwrap();
+        let mapped = pmx_read_mmap(&path).unwrap();
+        let read = pmx_read(&mut File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mapped, read);
+        assert_eq!(mapped.1, sample());
+    }
+
+    #[test]
+    fn stats_from_path_match_the_parsed_model() {
+        let path = temp_file("stats");
+        let pmx = sample();
+        pmx_write_file(&path, &pmx, 2.1).unwrap();
+        let stats = Pmx::stats_from_path(&path);
+        let (_, read) = pmx_read_mmap(&path).unwrap();
+        assert_eq!(stats.unwrap(), read.stats());
+        assert_eq!(read.stats(), pmx.stats());
+
+        // a truncated file fails with the offset
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let e = Pmx::stats_from_path(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(e.offset(), Some(bytes.len() as u64 - 1));
+    }
+}
@@ -2,6 +2,14 @@ use std::io::{Read, Write};
 
 use crate::error::PmxError;
 use crate::header::Header;
+use crate::kits::impl_approx_eq;
+use crate::options::ReadOptions;
+
+/// the byte limit of the model names when reading
+pub const MAX_NAME_BYTES: usize = 0x1000;
+
+/// the byte limit of the model comments when reading
+pub const MAX_COMMENT_BYTES: usize = 0x10_0000;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ModelInfo {
@@ -12,12 +20,17 @@ pub struct ModelInfo {
 }
 
 impl ModelInfo {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        let encoding = header.encoding;
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
-            comment: header.encoding.read(read)?,
-            comment_en: header.encoding.read(read)?,
+            name: encoding.read_limited(read, options, MAX_NAME_BYTES)?,
+            name_en: encoding.read_limited(read, options, MAX_NAME_BYTES)?,
+            comment: encoding.read_limited(read, options, MAX_COMMENT_BYTES)?,
+            comment_en: encoding.read_limited(read, options, MAX_COMMENT_BYTES)?,
         })
     }
 
@@ -28,4 +41,55 @@ impl ModelInfo {
         header.encoding.write(write, self.comment_en.as_str())?;
         Ok(())
     }
+
+    /// the size [`ModelInfo::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        [&self.name, &self.name_en, &self.comment, &self.comment_en]
+            .into_iter()
+            .map(|i| header.encoding.serialized_size(i))
+            .sum()
+    }
+}
+
+impl_approx_eq!(ModelInfo {
+    name,
+    name_en,
+    comment,
+    comment_en,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Encoding;
+
+    #[test]
+    fn names_are_capped_below_the_comment_limit() {
+        let header = Header {
+            encoding: Encoding::Utf8,
+            ..Header::default()
+        };
+        let info = ModelInfo {
+            name: "n".repeat(MAX_NAME_BYTES),
+            name_en: String::new(),
+            comment: "c".repeat(MAX_NAME_BYTES + 1),
+            comment_en: "コメント".into(),
+        };
+        let mut bytes = Vec::new();
+        info.write(&header, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), info.serialized_size(&header));
+        let read = ModelInfo::read(&header, &ReadOptions::default(), &mut bytes.as_slice());
+        assert_eq!(read.unwrap(), info);
+
+        let long = ModelInfo {
+            name_en: "n".repeat(MAX_NAME_BYTES + 1),
+            ..info
+        };
+        let mut bytes = Vec::new();
+        long.write(&header, &mut bytes).unwrap();
+        // only the length prefix is there to read, the name is refused before it
+        bytes.truncate(4 + MAX_NAME_BYTES + 4);
+        let e = ModelInfo::read(&header, &ReadOptions::default(), &mut bytes.as_slice());
+        assert!(matches!(e, Err(PmxError::StringTooLong(n)) if n as usize == MAX_NAME_BYTES + 1));
+    }
 }
@@ -1,11 +1,18 @@
-use std::fmt::{Debug, Formatter};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
+use crate::approx::ApproxEq;
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_bool, read_f32x3, read_f32x4, read_vec, write_f32x3, write_f32x4};
+use crate::kits::{
+    flip_z, flip_z_euler, impl_approx_eq, impl_approx_eq_exact, impl_collection,
+    impl_eq_ignoring_english, read_bool, read_f32x3, read_f32x4, read_vec, sanitize,
+    sanitize_quaternion, skip, skip_vec, vec_size, write_f32x3, write_f32x4, Affine,
+};
+use crate::options::ReadOptions;
 use crate::{BoneIndex, MaterialIndex, MorphIndex, RigidBodyIndex, VertexIndex};
 
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -17,11 +24,18 @@ impl Morphs {
     pub fn count(&self) -> u32 {
         self.morphs.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            morphs: read_vec(read, |read| Morph::read(header, read))?,
+            morphs: read_vec(read, |read| Morph::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| Morph::skip(header, read).map(drop))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.morphs {
@@ -29,8 +43,235 @@ impl Morphs {
         }
         Ok(())
     }
+    /// the size [`Morphs::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.morphs, |i| i.serialized_size(header))
+    }
+
+    /// replace the rotation of every bone morph offset with [`BoneMorph::normalized_rotation`]
+    pub fn normalize_bone_rotations(&mut self) {
+        for morph in &mut self.morphs {
+            if let MorphData::Bone(i) = &mut morph.morph_data {
+                for x in i {
+                    x.rotates = x.normalized_rotation();
+                }
+            }
+        }
+    }
+
+    /// the morphs of every control panel that has some, in morph order,
+    /// the panels being in [`ControlPanel`] order with the system one first
+    pub fn by_panel(&self) -> BTreeMap<ControlPanel, Vec<usize>> {
+        let mut panels: BTreeMap<ControlPanel, Vec<usize>> = BTreeMap::new();
+        for (index, morph) in self.morphs.iter().enumerate() {
+            panels.entry(morph.control_panel).or_default().push(index);
+        }
+        panels
+    }
+
+    /// flatten the morph at `index` applied with `weight` into the non-group morphs it drives,
+    /// as `(morph index, effective weight)` pairs in first reached order.
+    ///
+    /// nested groups multiply their factors and a morph reached several times has its weights summed.
+    /// a group reached again while it is being expanded is skipped, which breaks cycles,
+    /// and out of range indices are skipped too. a non-group morph resolves to itself.
+    pub fn resolve_group(&self, index: usize, weight: f32) -> Vec<(usize, f32)> {
+        let mut resolved = Vec::new();
+        let mut path = Vec::new();
+        self.resolve_group_into(index, weight, &mut path, &mut resolved);
+        resolved
+    }
+
+    fn resolve_group_into(
+        &self,
+        index: usize,
+        weight: f32,
+        path: &mut Vec<usize>,
+        resolved: &mut Vec<(usize, f32)>,
+    ) {
+        let Some(morph) = self.morphs.get(index) else {
+            return;
+        };
+        let MorphData::Group(group) = &morph.morph_data else {
+            match resolved.iter_mut().find(|(i, _)| *i == index) {
+                Some((_, sum)) => *sum += weight,
+                None => resolved.push((index, weight)),
+            }
+            return;
+        };
+        if path.contains(&index) {
+            return;
+        }
+        path.push(index);
+        for x in group {
+            if let Ok(child) = usize::try_from(x.morph_index) {
+                self.resolve_group_into(child, weight * x.morph_factor, path, resolved);
+            }
+        }
+        path.pop();
+    }
+
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        for morph in &mut self.morphs {
+            if let MorphData::Bone(i) = &mut morph.morph_data {
+                for x in i {
+                    f(&mut x.bone_index);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn for_each_morph_index_mut(&mut self, f: &mut impl FnMut(&mut MorphIndex)) {
+        for morph in &mut self.morphs {
+            morph.for_each_morph_index_mut(f);
+        }
+    }
+
+    pub(crate) fn for_each_vertex_index_mut(&mut self, f: &mut impl FnMut(&mut VertexIndex)) {
+        for morph in &mut self.morphs {
+            match &mut morph.morph_data {
+                MorphData::Vertex(i) => i.iter_mut().for_each(|x| f(&mut x.vertex_index)),
+                MorphData::UV(i)
+                | MorphData::UV1(i)
+                | MorphData::UV2(i)
+                | MorphData::UV3(i)
+                | MorphData::UV4(i) => i.iter_mut().for_each(|x| f(&mut x.vertex_index)),
+                _ => {}
+            }
+        }
+    }
+
+    /// the `(morph, offset)` pairs of the vertex and uv morphs for each of `count` vertices,
+    /// a vertex offset taking the first 3 components
+    pub(crate) fn vertex_offsets(&self, count: usize) -> Vec<Vec<(usize, [f32; 4])>> {
+        let mut offsets = vec![Vec::new(); count];
+        let mut push = |vertex: VertexIndex, morph: usize, offset: [f32; 4]| {
+            if let Some(i) = offsets.get_mut(vertex as usize) {
+                i.push((morph, offset));
+            }
+        };
+        for (index, morph) in self.morphs.iter().enumerate() {
+            match &morph.morph_data {
+                MorphData::Vertex(i) => i.iter().for_each(|x| {
+                    let [a, b, c] = x.offset;
+                    push(x.vertex_index, index, [a, b, c, 0.0])
+                }),
+                MorphData::UV(i)
+                | MorphData::UV1(i)
+                | MorphData::UV2(i)
+                | MorphData::UV3(i)
+                | MorphData::UV4(i) => i.iter().for_each(|x| push(x.vertex_index, index, x.offset)),
+                _ => {}
+            }
+        }
+        offsets
+    }
+
+    /// keep the vertex and uv morph offsets whose vertex `keep` holds true for
+    pub(crate) fn retain_vertex_offsets(&mut self, keep: impl Fn(VertexIndex) -> bool) {
+        for morph in &mut self.morphs {
+            match &mut morph.morph_data {
+                MorphData::Vertex(i) => i.retain(|x| keep(x.vertex_index)),
+                MorphData::UV(i)
+                | MorphData::UV1(i)
+                | MorphData::UV2(i)
+                | MorphData::UV3(i)
+                | MorphData::UV4(i) => i.retain(|x| keep(x.vertex_index)),
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn for_each_material_index_mut(&mut self, f: &mut impl FnMut(&mut MaterialIndex)) {
+        for morph in &mut self.morphs {
+            if let MorphData::Material(i) = &mut morph.morph_data {
+                i.iter_mut().for_each(|x| f(&mut x.material_index));
+            }
+        }
+    }
+
+    pub(crate) fn for_each_rigid_body_index_mut(
+        &mut self,
+        f: &mut impl FnMut(&mut RigidBodyIndex),
+    ) {
+        for morph in &mut self.morphs {
+            if let MorphData::Impulse(i) = &mut morph.morph_data {
+                i.iter_mut().for_each(|x| f(&mut x.rigid_index));
+            }
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        self.morphs.iter_mut().map(Morph::sanitize_floats).sum()
+    }
+
+    /// negate the v offset of the uv morphs, and of the additional uv morphs if `additional`
+    pub(crate) fn flip_uv_v(&mut self, additional: bool) {
+        for morph in &mut self.morphs {
+            match &mut morph.morph_data {
+                MorphData::UV(i) => i.iter_mut().for_each(|x| x.offset[1] = -x.offset[1]),
+                MorphData::UV1(i) | MorphData::UV2(i) | MorphData::UV3(i) | MorphData::UV4(i)
+                    if additional =>
+                {
+                    i.iter_mut().for_each(|x| x.offset[1] = -x.offset[1])
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn flip_z(&mut self) {
+        for morph in &mut self.morphs {
+            match &mut morph.morph_data {
+                MorphData::Vertex(i) => {
+                    for x in i {
+                        flip_z(&mut x.offset);
+                    }
+                }
+                MorphData::Bone(i) => {
+                    for x in i {
+                        flip_z(&mut x.translates);
+                        x.rotates[0] = -x.rotates[0];
+                        x.rotates[1] = -x.rotates[1];
+                    }
+                }
+                MorphData::Impulse(i) => {
+                    for x in i {
+                        flip_z(&mut x.velocity);
+                        flip_z_euler(&mut x.torque);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn transform(&mut self, transform: &Affine) {
+        for morph in &mut self.morphs {
+            match &mut morph.morph_data {
+                MorphData::Vertex(i) => {
+                    for x in i {
+                        transform.vector(&mut x.offset);
+                    }
+                }
+                MorphData::Bone(i) => {
+                    for x in i {
+                        transform.vector(&mut x.translates);
+                    }
+                }
+                MorphData::Impulse(i) => {
+                    for x in i {
+                        transform.vector(&mut x.velocity);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
 }
 
+impl_collection!(Morphs, morphs, Morph);
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Morph {
     pub name: String,
@@ -40,15 +281,30 @@ pub struct Morph {
 }
 
 impl Morph {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             control_panel: read.read_u8()?.try_into()?,
             morph_data: MorphData::read(header, read)?,
         })
     }
 
+    /// skip one morph, returning its kind
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<MorphKind, PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        ControlPanel::try_from(read.read_u8()?)?;
+        let kind = MorphKind::try_from(read.read_u8()?)?;
+        let count = read.read_u32::<LittleEndian>()?;
+        skip(read, count as u64 * kind.offset_len(header))?;
+        Ok(kind)
+    }
+
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -56,9 +312,113 @@ impl Morph {
         self.morph_data.write(header, write)?;
         Ok(())
     }
+
+    /// the size [`Morph::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let offsets = self.morph_data.len() * self.kind().offset_len(header) as usize;
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            // control panel, kind and offset count
+            + 1 + 1 + 4
+            + offsets
+    }
+
+    /// the kind of the morph data
+    pub fn kind(&self) -> MorphKind {
+        self.morph_data.kind()
+    }
+
+    /// the bone, vertex, material, morph or rigid body indices the offsets point at,
+    /// whatever the kind, see [`Morph::affected_indices`] for which ones they are
+    pub fn target_indices(&self) -> Vec<u32> {
+        self.affected_indices()
+    }
+
+    /// the morph indices of a group or flip morph
+    pub(crate) fn for_each_morph_index_mut(&mut self, f: &mut impl FnMut(&mut MorphIndex)) {
+        match &mut self.morph_data {
+            MorphData::Group(i) => i.iter_mut().for_each(|x| f(&mut x.morph_index)),
+            MorphData::Flip(i) => i.iter_mut().for_each(|x| f(&mut x.morph_index)),
+            _ => {}
+        }
+    }
+
+    /// the indices this morph touches, in offset order.
+    ///
+    /// what they index depends on the morph data: vertices for vertex and uv morphs,
+    /// bones, materials, morphs for group and flip morphs, and rigid bodies for impulse morphs.
+    /// negative "none" or "all" indices are left out.
+    pub fn affected_indices(&self) -> Vec<u32> {
+        fn signed(i: i32) -> Option<u32> {
+            u32::try_from(i).ok()
+        }
+        match &self.morph_data {
+            MorphData::Group(i) => i.iter().filter_map(|x| signed(x.morph_index)).collect(),
+            MorphData::Vertex(i) => i.iter().map(|x| x.vertex_index).collect(),
+            MorphData::Bone(i) => i.iter().filter_map(|x| signed(x.bone_index)).collect(),
+            MorphData::UV(i)
+            | MorphData::UV1(i)
+            | MorphData::UV2(i)
+            | MorphData::UV3(i)
+            | MorphData::UV4(i) => i.iter().map(|x| x.vertex_index).collect(),
+            MorphData::Material(i) => i.iter().filter_map(|x| signed(x.material_index)).collect(),
+            MorphData::Flip(i) => i.iter().filter_map(|x| signed(x.morph_index)).collect(),
+            MorphData::Impulse(i) => i.iter().filter_map(|x| signed(x.rigid_index)).collect(),
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        let mut count = 0;
+        match &mut self.morph_data {
+            MorphData::Group(i) => {
+                count += sanitize(i.iter_mut().map(|x| &mut x.morph_factor));
+            }
+            MorphData::Vertex(i) => {
+                count += sanitize(i.iter_mut().flat_map(|x| &mut x.offset));
+            }
+            MorphData::Bone(i) => {
+                for x in i {
+                    count += sanitize(&mut x.translates) + sanitize_quaternion(&mut x.rotates);
+                }
+            }
+            MorphData::UV(i)
+            | MorphData::UV1(i)
+            | MorphData::UV2(i)
+            | MorphData::UV3(i)
+            | MorphData::UV4(i) => {
+                count += sanitize(i.iter_mut().flat_map(|x| &mut x.offset));
+            }
+            MorphData::Material(i) => {
+                for x in i {
+                    count += sanitize(
+                        x.diffuse
+                            .iter_mut()
+                            .chain(&mut x.specular)
+                            .chain([&mut x.specular_factor])
+                            .chain(&mut x.ambient)
+                            .chain(&mut x.edge_color)
+                            .chain([&mut x.edge_size])
+                            .chain(&mut x.texture_factor)
+                            .chain(&mut x.sphere_texture_factor)
+                            .chain(&mut x.toon_texture_factor),
+                    );
+                }
+            }
+            MorphData::Flip(i) => {
+                count += sanitize(i.iter_mut().map(|x| &mut x.morph_factor));
+            }
+            MorphData::Impulse(i) => {
+                count += sanitize(
+                    i.iter_mut()
+                        .flat_map(|x| x.velocity.iter_mut().chain(&mut x.torque)),
+                );
+            }
+        }
+        count
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Ord, Eq)]
+#[derive(Debug, Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash)]
 #[repr(u8)]
 pub enum ControlPanel {
     System = 0x00,
@@ -83,6 +443,72 @@ impl TryFrom<u8> for ControlPanel {
     }
 }
 
+impl Display for ControlPanel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::System => "System",
+            Self::BottomLeft => "Eyebrow",
+            Self::TopLeft => "Eye",
+            Self::TopRight => "Mouth",
+            Self::BottomRight => "Other",
+        })
+    }
+}
+
+/// the kind of a [`MorphData`] without its offsets, numbered as in the file
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u8)]
+pub enum MorphKind {
+    Group = 0x00,
+    Vertex = 0x01,
+    Bone = 0x02,
+    UV = 0x03,
+    UV1 = 0x04,
+    UV2 = 0x05,
+    UV3 = 0x06,
+    UV4 = 0x07,
+    Material = 0x08,
+    Flip = 0x09,
+    Impulse = 0x0A,
+}
+
+impl TryFrom<u8> for MorphKind {
+    type Error = PmxError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::Group),
+            0x01 => Ok(Self::Vertex),
+            0x02 => Ok(Self::Bone),
+            0x03 => Ok(Self::UV),
+            0x04 => Ok(Self::UV1),
+            0x05 => Ok(Self::UV2),
+            0x06 => Ok(Self::UV3),
+            0x07 => Ok(Self::UV4),
+            0x08 => Ok(Self::Material),
+            0x09 => Ok(Self::Flip),
+            0x0A => Ok(Self::Impulse),
+            _ => Err(PmxError::MorphError),
+        }
+    }
+}
+
+impl MorphKind {
+    /// the size of one offset of the kind
+    pub(crate) fn offset_len(self, header: &Header) -> u64 {
+        match self {
+            MorphKind::Group | MorphKind::Flip => header.morph_index.bytes() + 4,
+            MorphKind::Vertex => header.vertex_index.bytes() + 12,
+            MorphKind::Bone => header.bone_index.bytes() + 12 + 16,
+            MorphKind::UV | MorphKind::UV1 | MorphKind::UV2 | MorphKind::UV3 | MorphKind::UV4 => {
+                header.vertex_index.bytes() + 16
+            }
+            MorphKind::Material => header.material_index.bytes() + 1 + 28 * 4,
+            MorphKind::Impulse => header.rigid_body_index.bytes() + 1 + 24,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub enum MorphData {
     Group(Vec<GroupMorph>),
@@ -117,126 +543,87 @@ impl Debug for MorphData {
 }
 
 impl MorphData {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
-        let t = read.read_u8()?;
-        match t {
-            0x00 => Ok(MorphData::Group(read_vec(read, |read| {
-                GroupMorph::read(header, read)
-            })?)),
-            0x01 => Ok(MorphData::Vertex(read_vec(read, |read| {
-                VertexMorph::read(header, read)
-            })?)),
-            0x02 => Ok(MorphData::Bone(read_vec(read, |read| {
-                BoneMorph::read(header, read)
-            })?)),
-            0x03 => Ok(MorphData::UV(read_vec(read, |read| {
-                UVMorph::read(header, read)
-            })?)),
-            0x04 => Ok(MorphData::UV1(read_vec(read, |read| {
-                UVMorph::read(header, read)
-            })?)),
-            0x05 => Ok(MorphData::UV2(read_vec(read, |read| {
-                UVMorph::read(header, read)
-            })?)),
-            0x06 => Ok(MorphData::UV3(read_vec(read, |read| {
-                UVMorph::read(header, read)
-            })?)),
-            0x07 => Ok(MorphData::UV4(read_vec(read, |read| {
-                UVMorph::read(header, read)
-            })?)),
-            0x08 => Ok(MorphData::Material(read_vec(read, |read| {
-                MaterialMorph::read(header, read)
-            })?)),
-            0x09 => Ok(MorphData::Flip(read_vec(read, |read| {
-                FlipMorph::read(header, read)
-            })?)),
-            0x0A => Ok(MorphData::Impulse(read_vec(read, |read| {
-                ImpulseMorph::read(header, read)
-            })?)),
-            _ => Err(PmxError::MorphError),
+    pub fn kind(&self) -> MorphKind {
+        match self {
+            MorphData::Group(_) => MorphKind::Group,
+            MorphData::Vertex(_) => MorphKind::Vertex,
+            MorphData::Bone(_) => MorphKind::Bone,
+            MorphData::UV(_) => MorphKind::UV,
+            MorphData::UV1(_) => MorphKind::UV1,
+            MorphData::UV2(_) => MorphKind::UV2,
+            MorphData::UV3(_) => MorphKind::UV3,
+            MorphData::UV4(_) => MorphKind::UV4,
+            MorphData::Material(_) => MorphKind::Material,
+            MorphData::Flip(_) => MorphKind::Flip,
+            MorphData::Impulse(_) => MorphKind::Impulse,
         }
     }
-    pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
+
+    /// the number of offsets
+    pub fn len(&self) -> usize {
         match self {
-            MorphData::Group(i) => {
-                write.write_u8(0x00)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
-            }
-            MorphData::Vertex(i) => {
-                write.write_u8(0x01)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
-            }
-            MorphData::Bone(i) => {
-                write.write_u8(0x02)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
-            }
-            MorphData::UV(i) => {
-                write.write_u8(0x03)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
-            }
-            MorphData::UV1(i) => {
-                write.write_u8(0x04)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
-            }
-            MorphData::UV2(i) => {
-                write.write_u8(0x05)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphData::Group(i) => i.len(),
+            MorphData::Vertex(i) => i.len(),
+            MorphData::Bone(i) => i.len(),
+            MorphData::UV(i)
+            | MorphData::UV1(i)
+            | MorphData::UV2(i)
+            | MorphData::UV3(i)
+            | MorphData::UV4(i) => i.len(),
+            MorphData::Material(i) => i.len(),
+            MorphData::Flip(i) => i.len(),
+            MorphData::Impulse(i) => i.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+        let uv = |read: &mut R| read_vec(read, |read| UVMorph::read(header, read));
+        Ok(match MorphKind::try_from(read.read_u8()?)? {
+            MorphKind::Group => {
+                MorphData::Group(read_vec(read, |read| GroupMorph::read(header, read))?)
             }
-            MorphData::UV3(i) => {
-                write.write_u8(0x06)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphKind::Vertex => {
+                MorphData::Vertex(read_vec(read, |read| VertexMorph::read(header, read))?)
             }
-            MorphData::UV4(i) => {
-                write.write_u8(0x07)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphKind::Bone => {
+                MorphData::Bone(read_vec(read, |read| BoneMorph::read(header, read))?)
             }
-            MorphData::Material(i) => {
-                write.write_u8(0x08)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphKind::UV => MorphData::UV(uv(read)?),
+            MorphKind::UV1 => MorphData::UV1(uv(read)?),
+            MorphKind::UV2 => MorphData::UV2(uv(read)?),
+            MorphKind::UV3 => MorphData::UV3(uv(read)?),
+            MorphKind::UV4 => MorphData::UV4(uv(read)?),
+            MorphKind::Material => {
+                MorphData::Material(read_vec(read, |read| MaterialMorph::read(header, read))?)
             }
-            MorphData::Flip(i) => {
-                write.write_u8(0x09)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphKind::Flip => {
+                MorphData::Flip(read_vec(read, |read| FlipMorph::read(header, read))?)
             }
-            MorphData::Impulse(i) => {
-                write.write_u8(0x0A)?;
-                write.write_u32::<LittleEndian>(i.len() as u32)?;
-                for x in i {
-                    x.write(header, write)?;
-                }
+            MorphKind::Impulse => {
+                MorphData::Impulse(read_vec(read, |read| ImpulseMorph::read(header, read))?)
             }
+        })
+    }
+    pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
+        write.write_u8(self.kind() as u8)?;
+        write.write_u32::<LittleEndian>(self.len() as u32)?;
+        match self {
+            MorphData::Group(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::Vertex(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::Bone(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::UV(i)
+            | MorphData::UV1(i)
+            | MorphData::UV2(i)
+            | MorphData::UV3(i)
+            | MorphData::UV4(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::Material(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::Flip(i) => i.iter().try_for_each(|x| x.write(header, write)),
+            MorphData::Impulse(i) => i.iter().try_for_each(|x| x.write(header, write)),
         }
-        Ok(())
     }
 }
 
@@ -301,6 +688,17 @@ impl BoneMorph {
         write_f32x4(write, self.rotates)?;
         Ok(())
     }
+
+    /// the rotation quaternion `[x, y, z, w]` scaled to unit length,
+    /// the identity if it has a zero or non-finite length
+    pub fn normalized_rotation(&self) -> [f32; 4] {
+        let length = self.rotates.iter().map(|i| i * i).sum::<f32>().sqrt();
+        if length > 0.0 && length.is_finite() {
+            self.rotates.map(|i| i / length)
+        } else {
+            [0.0, 0.0, 0.0, 1.0]
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -345,10 +743,10 @@ impl MaterialMorph {
             formula: read.read_u8()?,
             diffuse: read_f32x4(read)?,
             specular: read_f32x3(read)?,
-            specular_factor: 0.0,
+            specular_factor: read.read_f32::<LittleEndian>()?,
             ambient: read_f32x3(read)?,
             edge_color: read_f32x4(read)?,
-            edge_size: 0.0,
+            edge_size: read.read_f32::<LittleEndian>()?,
             texture_factor: read_f32x4(read)?,
             sphere_texture_factor: read_f32x4(read)?,
             toon_texture_factor: read_f32x4(read)?,
@@ -415,3 +813,345 @@ impl ImpulseMorph {
         Ok(())
     }
 }
+
+impl_approx_eq!(Morphs { morphs });
+impl_approx_eq!(Morph {
+    name,
+    name_en,
+    control_panel,
+    morph_data,
+});
+impl_eq_ignoring_english!(Morph {
+    name,
+    control_panel,
+    morph_data,
+});
+impl_approx_eq!(GroupMorph {
+    morph_index,
+    morph_factor,
+});
+impl_approx_eq!(VertexMorph {
+    vertex_index,
+    offset,
+});
+impl_approx_eq!(BoneMorph {
+    bone_index,
+    translates,
+    rotates,
+});
+impl_approx_eq!(UVMorph {
+    vertex_index,
+    offset,
+});
+impl_approx_eq!(MaterialMorph {
+    material_index,
+    formula,
+    diffuse,
+    specular,
+    specular_factor,
+    ambient,
+    edge_color,
+    edge_size,
+    texture_factor,
+    sphere_texture_factor,
+    toon_texture_factor,
+});
+impl_approx_eq!(FlipMorph {
+    morph_index,
+    morph_factor,
+});
+impl_approx_eq!(ImpulseMorph {
+    rigid_index,
+    is_local,
+    velocity,
+    torque,
+});
+impl_approx_eq_exact!(ControlPanel);
+
+impl ApproxEq for MorphData {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        match (self, other) {
+            (Self::Group(a), Self::Group(b)) => a.approx_eq(b, epsilon),
+            (Self::Vertex(a), Self::Vertex(b)) => a.approx_eq(b, epsilon),
+            (Self::Bone(a), Self::Bone(b)) => a.approx_eq(b, epsilon),
+            (Self::UV(a), Self::UV(b))
+            | (Self::UV1(a), Self::UV1(b))
+            | (Self::UV2(a), Self::UV2(b))
+            | (Self::UV3(a), Self::UV3(b))
+            | (Self::UV4(a), Self::UV4(b)) => a.approx_eq(b, epsilon),
+            (Self::Material(a), Self::Material(b)) => a.approx_eq(b, epsilon),
+            (Self::Flip(a), Self::Flip(b)) => a.approx_eq(b, epsilon),
+            (Self::Impulse(a), Self::Impulse(b)) => a.approx_eq(b, epsilon),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::IndexSize;
+    use crate::options::ReadOptions;
+    use crate::testing::{morph, sample};
+
+    #[test]
+    fn material_morph_reads_the_specular_factor_and_edge_size() {
+        let header = Header::builder().index_size(IndexSize::Bit8).build();
+        // material index, formula, then diffuse, specular, specular factor, ambient,
+        // edge color, edge size, texture, sphere and toon factors as 28 floats numbered 0..28
+        let mut bytes = vec![2, 1];
+        for i in 0..28 {
+            bytes.extend_from_slice(&(i as f32).to_le_bytes());
+        }
+        let morph = MaterialMorph::read(&header, &mut bytes.as_slice()).unwrap();
+        assert_eq!(morph.material_index, 2);
+        assert_eq!(morph.formula, 1);
+        assert_eq!(morph.specular, [4.0, 5.0, 6.0]);
+        assert_eq!(morph.specular_factor, 7.0);
+        assert_eq!(morph.ambient, [8.0, 9.0, 10.0]);
+        assert_eq!(morph.edge_color, [11.0, 12.0, 13.0, 14.0]);
+        assert_eq!(morph.edge_size, 15.0);
+        assert_eq!(morph.toon_texture_factor, [24.0, 25.0, 26.0, 27.0]);
+
+        let mut written = Vec::new();
+        morph.write(&header, &mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn material_morph_keeps_the_next_morph_aligned() {
+        let header = Header::builder().index_size(IndexSize::Bit8).build();
+        let material = MaterialMorph {
+            material_index: 0,
+            formula: 0,
+            diffuse: [1.0; 4],
+            specular: [1.0; 3],
+            specular_factor: 5.0,
+            ambient: [1.0; 3],
+            edge_color: [1.0; 4],
+            edge_size: 2.0,
+            texture_factor: [1.0; 4],
+            sphere_texture_factor: [1.0; 4],
+            toon_texture_factor: [1.0; 4],
+        };
+        let morphs = Morphs {
+            morphs: vec![
+                Morph {
+                    name: "material".into(),
+                    name_en: String::new(),
+                    control_panel: ControlPanel::BottomRight,
+                    morph_data: MorphData::Material(vec![material; 2]),
+                },
+                Morph {
+                    name: "group".into(),
+                    name_en: String::new(),
+                    control_panel: ControlPanel::TopRight,
+                    morph_data: MorphData::Group(vec![GroupMorph {
+                        morph_index: 0,
+                        morph_factor: 0.5,
+                    }]),
+                },
+            ],
+        };
+        let mut bytes = Vec::new();
+        morphs.write(&header, &mut bytes).unwrap();
+        let mut read = bytes.as_slice();
+        let back = Morphs::read(&header, &ReadOptions::default(), &mut read).unwrap();
+        assert_eq!(back, morphs);
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn control_panel_displays_its_english_name() {
+        assert_eq!(ControlPanel::BottomLeft.to_string(), "Eyebrow");
+        assert_eq!(ControlPanel::TopRight.to_string(), "Mouth");
+    }
+
+    #[test]
+    fn group_and_vertex_morphs_give_their_kind_and_targets() {
+        let group = morph(
+            "group",
+            ControlPanel::TopLeft,
+            MorphData::Group(vec![
+                GroupMorph {
+                    morph_index: 3,
+                    morph_factor: 1.0,
+                },
+                GroupMorph {
+                    morph_index: -1,
+                    morph_factor: 1.0,
+                },
+                GroupMorph {
+                    morph_index: 0,
+                    morph_factor: 0.5,
+                },
+            ]),
+        );
+        assert_eq!(group.kind(), MorphKind::Group);
+        assert_eq!(group.target_indices(), [3, 0]);
+
+        let vertex = morph(
+            "vertex",
+            ControlPanel::BottomLeft,
+            MorphData::Vertex(
+                [7, 2, 7]
+                    .map(|vertex_index| VertexMorph {
+                        vertex_index,
+                        offset: [0.0; 3],
+                    })
+                    .to_vec(),
+            ),
+        );
+        assert_eq!(vertex.kind(), MorphKind::Vertex);
+        assert_eq!(vertex.target_indices(), [7, 2, 7]);
+    }
+
+    #[test]
+    fn morph_data_is_written_under_its_kind() {
+        let header = Header::builder().index_size(IndexSize::Bit16).build();
+        for morph in sample().morphs {
+            let mut bytes = Vec::new();
+            morph.morph_data.write(&header, &mut bytes).unwrap();
+            assert_eq!(bytes[0], morph.kind() as u8);
+            assert_eq!(bytes[1..5], (morph.morph_data.len() as u32).to_le_bytes());
+            let mut read = bytes.as_slice();
+            let back = MorphData::read(&header, &mut read).unwrap();
+            assert!(read.is_empty());
+            assert_eq!(back.kind(), morph.kind());
+            assert!(back == morph.morph_data);
+        }
+
+        let mut bytes = vec![0x0B];
+        bytes.extend(0_u32.to_le_bytes());
+        assert!(matches!(
+            MorphData::read(&header, &mut bytes.as_slice()),
+            Err(PmxError::MorphError)
+        ));
+    }
+
+    #[test]
+    fn affected_indices_follow_the_offsets_of_each_kind() {
+        let pmx = sample();
+        let indices: Vec<(&str, Vec<u32>)> = pmx
+            .morphs
+            .iter()
+            .map(|i| (i.name.as_str(), i.affected_indices()))
+            .collect();
+        assert_eq!(
+            indices[..3],
+            [("vertex", vec![1]), ("group", vec![0]), ("bone", vec![1])]
+        );
+        assert_eq!(indices[5], ("material", vec![1]));
+
+        // "none" bone and "all" material indices are left out
+        let bone = morph(
+            "bone",
+            ControlPanel::TopRight,
+            MorphData::Bone(
+                [2, -1, 0]
+                    .map(|bone_index| BoneMorph {
+                        bone_index,
+                        translates: [0.0; 3],
+                        rotates: [0.0, 0.0, 0.0, 1.0],
+                    })
+                    .to_vec(),
+            ),
+        );
+        assert_eq!(bone.affected_indices(), [2, 0]);
+        let MorphData::Material(offsets) = &pmx.morphs[5].morph_data else {
+            unreachable!()
+        };
+        let mut all = offsets[0].clone();
+        all.material_index = -1;
+        let material = morph(
+            "material",
+            ControlPanel::BottomRight,
+            MorphData::Material(vec![all, offsets[0].clone()]),
+        );
+        assert_eq!(material.affected_indices(), [1]);
+    }
+
+    #[test]
+    fn bone_rotations_are_scaled_to_unit_length() {
+        let offset = |rotates| BoneMorph {
+            bone_index: 0,
+            translates: [0.0; 3],
+            rotates,
+        };
+        assert_eq!(
+            offset([0.0, 0.0, 3.0, 4.0]).normalized_rotation(),
+            [0.0, 0.0, 0.6, 0.8]
+        );
+        assert_eq!(offset([0.0; 4]).normalized_rotation(), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            offset([f32::NAN, 0.0, 0.0, 1.0]).normalized_rotation(),
+            [0.0, 0.0, 0.0, 1.0]
+        );
+
+        let mut morphs = Morphs {
+            morphs: vec![
+                morph(
+                    "bone",
+                    ControlPanel::BottomRight,
+                    MorphData::Bone(vec![offset([0.0, 2.0, 0.0, 0.0])]),
+                ),
+                sample().morphs[0].clone(),
+            ],
+        };
+        let vertex = morphs[1].clone();
+        morphs.normalize_bone_rotations();
+        assert!(
+            matches!(&morphs[0].morph_data, MorphData::Bone(b) if b[0].rotates == [0.0, 1.0, 0.0, 0.0])
+        );
+        assert_eq!(morphs[1], vertex);
+    }
+
+    #[test]
+    fn groups_resolve_to_their_leaf_morphs_without_looping() {
+        let group = |name, targets: &[(MorphIndex, f32)]| {
+            let data = targets
+                .iter()
+                .map(|&(morph_index, morph_factor)| GroupMorph {
+                    morph_index,
+                    morph_factor,
+                })
+                .collect();
+            morph(name, ControlPanel::BottomRight, MorphData::Group(data))
+        };
+        let mut morphs = sample().morphs;
+        // group 1 drives the vertex morph 0 at 0.5
+        morphs.morphs.extend([
+            group("nested", &[(1, 0.5), (2, 1.0), (0, 0.25)]),
+            group("self", &[(9, 1.0), (3, 0.5)]),
+            group("ping", &[(11, 2.0), (4, 1.0)]),
+            group("pong", &[(10, 2.0)]),
+        ]);
+
+        assert_eq!(morphs.resolve_group(8, 1.0), [(0, 0.5), (2, 1.0)]);
+        assert_eq!(morphs.resolve_group(8, 2.0), [(0, 1.0), (2, 2.0)]);
+        assert_eq!(morphs.resolve_group(9, 1.0), [(3, 0.5)]);
+        assert_eq!(morphs.resolve_group(10, 1.0), [(4, 1.0)]);
+        assert_eq!(morphs.resolve_group(11, 1.0), [(4, 2.0)]);
+        assert_eq!(morphs.resolve_group(3, 0.7), [(3, 0.7)]);
+        assert!(morphs.resolve_group(12, 1.0).is_empty());
+    }
+
+    #[test]
+    fn morphs_are_grouped_by_panel_in_morph_order() {
+        let mut morphs = sample().morphs;
+        morphs[5].control_panel = ControlPanel::System;
+        morphs[3].control_panel = ControlPanel::BottomLeft;
+        let panels: Vec<_> = morphs.by_panel().into_iter().collect();
+        assert_eq!(
+            panels,
+            [
+                (ControlPanel::System, vec![5]),
+                (ControlPanel::BottomLeft, vec![0, 3]),
+                (ControlPanel::TopLeft, vec![1]),
+                (ControlPanel::TopRight, vec![2]),
+                (ControlPanel::BottomRight, vec![4, 6, 7]),
+            ]
+        );
+        assert!(Morphs::default().by_panel().is_empty());
+    }
+}
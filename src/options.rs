@@ -0,0 +1,81 @@
+use crate::header::Encoding;
+
+/// the default of [`ReadOptions::max_string_bytes`]
+pub const DEFAULT_MAX_STRING_BYTES: usize = 0x100_0000;
+
+/// options for reading a model, see [`crate::pmx_read_with`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ReadOptions {
+    /// replace invalid string bytes with U+FFFD instead of failing,
+    /// some real world models have garbage in their english names
+    pub lenient_strings: bool,
+    /// fail with [`crate::error::PmxError::StringTooLong`] before allocating
+    /// a string claiming more bytes than this, a broken length could otherwise ask for 4 GiB
+    pub max_string_bytes: usize,
+    /// fail with [`crate::error::PmxError::NonFinite`] if a float covered by
+    /// [`crate::pmx::Pmx::sanitize_floats`] is NaN or infinite
+    pub reject_non_finite: bool,
+    /// read the rest of the stream after the last section into [`crate::pmx::Pmx::trailing`],
+    /// some exporters append padding or their own data there
+    pub keep_trailing: bool,
+    /// fail with [`crate::error::PmxError::ElementCountError`] if the element indices
+    /// do not make whole triangles, which happens with a truncated or padded index buffer
+    pub strict_elements: bool,
+    /// decode a string of a utf-8 model that is not valid utf-8 as Shift-JIS if it is valid
+    /// Shift-JIS, as some exporters write it, before [`ReadOptions::lenient_strings`] applies.
+    /// off by default as it can hide broken strings.
+    /// only applies with the feature `shift_jis`, without it the option is ignored
+    pub shift_jis_fallback: bool,
+    /// read a [`crate::vertex::Skin::QDEF`] of a version 2.0 model instead of failing with
+    /// [`crate::error::PmxError::QdefVersionError`], some exporters write one regardless of
+    /// the version. the model then fails to write as 2.0,
+    /// see [`crate::pmx::Pmx::version_warnings`] for what was let through
+    pub lenient_versions: bool,
+}
+
+impl Default for ReadOptions {
+    /// strict strings of at most [`DEFAULT_MAX_STRING_BYTES`], non-finite floats and
+    /// partial triangles are kept, trailing bytes are left unread and versions are checked
+    fn default() -> Self {
+        Self {
+            lenient_strings: false,
+            max_string_bytes: DEFAULT_MAX_STRING_BYTES,
+            reject_non_finite: false,
+            keep_trailing: false,
+            strict_elements: false,
+            shift_jis_fallback: false,
+            lenient_versions: false,
+        }
+    }
+}
+
+/// options for writing a model, see [`crate::pmx_write_with`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct WriteOptions {
+    /// the encoding every string is written in
+    pub encoding: Encoding,
+    /// fail with [`crate::error::PmxError::NonFinite`] before writing anything if a float
+    /// covered by [`crate::pmx::Pmx::sanitize_floats`] is NaN or infinite,
+    /// see [`crate::pmx::Pmx::find_non_finite`]
+    pub reject_non_finite: bool,
+}
+
+impl Default for WriteOptions {
+    /// utf-16 strings, non-finite floats are written as they are
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf16Le,
+            reject_non_finite: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// the default options with strings written in `encoding`
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            ..Self::default()
+        }
+    }
+}
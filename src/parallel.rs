@@ -0,0 +1,368 @@
+//! read a model held in memory with the sections parsed on the rayon thread pool
+
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use rayon::prelude::*;
+
+use crate::bone::Bones;
+use crate::display_frame::DisplayFrames;
+use crate::element_index::ElementIndices;
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::joint::Joints;
+use crate::kits::{read_f32s, read_f32x4, CountingRead};
+use crate::material::Materials;
+use crate::model_info::ModelInfo;
+use crate::morph::Morphs;
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::rigid_body::RigidBodies;
+use crate::soft_body::SoftBodies;
+use crate::texture::Textures;
+use crate::vertex::{Skin, Vertices};
+
+type SliceRead<'a> = CountingRead<&'a [u8]>;
+type SkipSection<'a> = fn(&Header, &mut SliceRead<'a>) -> Result<u32, PmxError>;
+
+/// the vertex section is split in chunks of this many vertices, it is usually the biggest one
+const VERTEX_CHUNK: usize = 0x4000;
+
+/// where the sections start in the bytes
+struct Layout {
+    header: Header,
+    info: ModelInfo,
+    /// the start and vertex count of every vertex chunk
+    vertex_chunks: Vec<(usize, usize)>,
+    /// the starts of the sections after the vertices
+    starts: [usize; 9],
+}
+
+/// read a model held in memory, parsing the sections in parallel.
+///
+/// one pass skips over the sections to find where they start, the vertices in chunks,
+/// then every section and vertex chunk is parsed on the rayon thread pool.
+/// the result is the same as [`crate::pmx_read`] on the same bytes,
+/// including the offset of an error.
+pub fn pmx_from_slice_parallel(bytes: &[u8]) -> Result<(Header, Pmx), PmxError> {
+    pmx_from_slice_parallel_with(bytes, &ReadOptions::default())
+}
+
+/// read a model held in memory with non-default options, see [`pmx_from_slice_parallel`]
+pub fn pmx_from_slice_parallel_with(
+    bytes: &[u8],
+    options: &ReadOptions,
+) -> Result<(Header, Pmx), PmxError> {
+    let mut read = CountingRead::new(bytes);
+    let Layout {
+        header,
+        info,
+        vertex_chunks,
+        starts,
+    } = locate(&mut read, options).map_err(|e| PmxError::At {
+        offset: read.count(),
+        source: Box::new(e),
+    })?;
+    let header = &header;
+
+    let (mut vertices, mut elements, mut textures, mut materials, mut bones) =
+        (None, None, None, None, None);
+    let (mut morphs, mut display_frames, mut rigid_bodies, mut joints, mut soft_bodies) =
+        (None, None, None, None, None);
+    rayon::scope(|s| {
+        s.spawn(|_| vertices = Some(read_vertices(bytes, header, &vertex_chunks)));
+        s.spawn(|_| {
+            elements = Some(section(bytes, starts[0], |r| {
+                ElementIndices::read_checked(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            textures = Some(section(bytes, starts[1], |r| {
+                Textures::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            materials = Some(section(bytes, starts[2], |r| {
+                Materials::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            bones = Some(section(bytes, starts[3], |r| {
+                Bones::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            morphs = Some(section(bytes, starts[4], |r| {
+                Morphs::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            display_frames = Some(section(bytes, starts[5], |r| {
+                DisplayFrames::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            rigid_bodies = Some(section(bytes, starts[6], |r| {
+                RigidBodies::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            joints = Some(section(bytes, starts[7], |r| {
+                Joints::read(header, options, r)
+            }))
+        });
+        s.spawn(|_| {
+            soft_bodies = Some(section(bytes, starts[8], |r| {
+                SoftBodies::read(header, options, r)
+            }))
+        });
+    });
+
+    // the scope has run every task, errors are reported in file order
+    let mut pmx = Pmx {
+        info,
+        vertices: vertices.unwrap()?,
+        elements: elements.unwrap()?,
+        textures: textures.unwrap()?,
+        materials: materials.unwrap()?,
+        bones: bones.unwrap()?,
+        morphs: morphs.unwrap()?,
+        display_frames: display_frames.unwrap()?,
+        rigid_bodies: rigid_bodies.unwrap()?,
+        joints: joints.unwrap()?,
+        soft_bodies: soft_bodies.unwrap()?,
+        trailing: Vec::new(),
+    };
+    if options.keep_trailing {
+        pmx.trailing = bytes[read.count() as usize..].to_vec();
+    }
+    if options.reject_non_finite {
+        pmx.reject_non_finite().map_err(|e| PmxError::At {
+            offset: read.count(),
+            source: Box::new(e),
+        })?;
+    }
+    Ok((header.clone(), pmx))
+}
+
+/// read the header and model info, then skip over the other sections to find where they start
+fn locate<'a>(read: &mut SliceRead<'a>, options: &ReadOptions) -> Result<Layout, PmxError> {
+    let header = Header::read(read).map_err(|e| e.in_section("header"))?;
+    let info = ModelInfo::read(&header, options, read).map_err(|e| e.in_section("model info"))?;
+    let vertex_chunks = locate_vertices(&header, read).map_err(|e| e.in_section("vertices"))?;
+    let skips: [(SkipSection<'a>, &str); 9] = [
+        (ElementIndices::skip, "elements"),
+        (Textures::skip, "textures"),
+        (Materials::skip, "materials"),
+        (Bones::skip, "bones"),
+        (Morphs::skip, "morphs"),
+        (DisplayFrames::skip, "display frames"),
+        (RigidBodies::skip, "rigid bodies"),
+        (Joints::skip, "joints"),
+        (SoftBodies::skip, "soft bodies"),
+    ];
+    let mut starts = [0; 9];
+    for (start, (skip, section)) in starts.iter_mut().zip(skips) {
+        *start = read.count() as usize;
+        skip(&header, read).map_err(|e| e.in_section(section))?;
+    }
+    Ok(Layout {
+        header,
+        info,
+        vertex_chunks,
+        starts,
+    })
+}
+
+/// skip over the vertex section, returning the start and vertex count of every vertex chunk
+fn locate_vertices(header: &Header, read: &mut SliceRead) -> Result<Vec<(usize, usize)>, PmxError> {
+    let vertex_count = read.read_u32::<LittleEndian>()? as usize;
+    let mut vertex_chunks = Vec::new();
+    for index in 0..vertex_count {
+        if index % VERTEX_CHUNK == 0 {
+            let count = VERTEX_CHUNK.min(vertex_count - index);
+            vertex_chunks.push((read.count() as usize, count));
+        }
+        Vertices::skip_one(header, read)?;
+    }
+    Ok(vertex_chunks)
+}
+
+/// the arrays of a chunk of vertices within the arrays of the whole section
+struct VertexChunk<'a> {
+    position3s: &'a mut [f32],
+    normal3s: &'a mut [f32],
+    uv2s: &'a mut [f32],
+    ext_vec4s: Vec<&'a mut [f32]>,
+    skins: &'a mut [Skin],
+    edges: &'a mut [f32],
+}
+
+impl VertexChunk<'_> {
+    /// decode the vertices of the chunk like [`Vertices::read`] into their place
+    fn read<R: Read>(mut self, header: &Header, read: &mut R) -> Result<(), PmxError> {
+        for index in 0..self.edges.len() {
+            // position, normal and uv are contiguous
+            let block: [f32; 8] = read_f32s(read)?;
+            self.position3s[index * 3..][..3].copy_from_slice(&block[0..3]);
+            self.normal3s[index * 3..][..3].copy_from_slice(&block[3..6]);
+            self.uv2s[index * 2..][..2].copy_from_slice(&block[6..8]);
+            for e in &mut self.ext_vec4s {
+                e[index * 4..][..4].copy_from_slice(&read_f32x4(read)?);
+            }
+            self.skins[index] = Skin::read(header, read)?;
+            self.edges[index] = read.read_f32::<LittleEndian>()?;
+        }
+        Ok(())
+    }
+}
+
+/// parse the vertex chunks in parallel, each one straight into its place in the section
+fn read_vertices(
+    bytes: &[u8],
+    header: &Header,
+    chunks: &[(usize, usize)],
+) -> Result<Vertices, PmxError> {
+    let count: usize = chunks.iter().map(|&(_, count)| count).sum();
+    let mut vertices = Vertices {
+        position3s: vec![0.0; count * 3],
+        normal3s: vec![0.0; count * 3],
+        uv2s: vec![0.0; count * 2],
+        skins: vec![Skin::BDEF1 { bone_index: -1 }; count],
+        ext_vec4s: vec![vec![0.0; count * 4]; header.vertex_ext_vec4 as usize],
+        edges: vec![0.0; count],
+    };
+    let mut ext_vec4s: Vec<_> = vertices
+        .ext_vec4s
+        .iter_mut()
+        .map(|e| e.chunks_mut(VERTEX_CHUNK * 4))
+        .collect();
+    let parts: Vec<_> = vertices
+        .position3s
+        .chunks_mut(VERTEX_CHUNK * 3)
+        .zip(vertices.normal3s.chunks_mut(VERTEX_CHUNK * 3))
+        .zip(vertices.uv2s.chunks_mut(VERTEX_CHUNK * 2))
+        .zip(vertices.skins.chunks_mut(VERTEX_CHUNK))
+        .zip(vertices.edges.chunks_mut(VERTEX_CHUNK))
+        .map(
+            |((((position3s, normal3s), uv2s), skins), edges)| VertexChunk {
+                position3s,
+                normal3s,
+                uv2s,
+                ext_vec4s: ext_vec4s.iter_mut().flat_map(|e| e.next()).collect(),
+                skins,
+                edges,
+            },
+        )
+        .zip(chunks)
+        .collect();
+    let results: Vec<_> = parts
+        .into_par_iter()
+        .map(|(part, &(start, _))| section(bytes, start, |r| part.read(header, r)))
+        .collect();
+    // every chunk is parsed, errors are reported in file order
+    results.into_iter().collect::<Result<(), _>>()?;
+    Ok(vertices)
+}
+
+impl Vertices {
+    /// read the vertex section from memory like [`Vertices::read`], decoding it in parallel.
+    ///
+    /// one pass skips over the vertices, each one having the size given by its skin kind,
+    /// to split them in chunks, which are then parsed on the rayon thread pool.
+    /// the slice is advanced past the section, errors are wrapped in [`PmxError::At`]
+    /// with their offset in the slice
+    pub fn read_parallel(header: &Header, read: &mut &[u8]) -> Result<Self, PmxError> {
+        let bytes: &[u8] = read;
+        let mut counted = CountingRead::new(bytes);
+        let chunks = locate_vertices(header, &mut counted).map_err(|e| PmxError::At {
+            offset: counted.count(),
+            source: Box::new(e),
+        })?;
+        let vertices = read_vertices(bytes, header, &chunks)?;
+        *read = &bytes[counted.count() as usize..];
+        Ok(vertices)
+    }
+}
+
+/// parse one section from its start, wrapping an error in [`PmxError::At`]
+fn section<T>(
+    bytes: &[u8],
+    start: usize,
+    f: impl FnOnce(&mut SliceRead) -> Result<T, PmxError>,
+) -> Result<T, PmxError> {
+    let mut read = CountingRead::new(&bytes[start..]);
+    f(&mut read).map_err(|e| PmxError::At {
+        offset: start as u64 + read.count(),
+        source: Box::new(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{large, sample};
+    use crate::{pmx_read, pmx_read_with, pmx_write_to_vec};
+
+    #[test]
+    fn reads_like_the_sequential_reader() {
+        for pmx in [sample(), large(3 * VERTEX_CHUNK + 5)] {
+            let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+            let parallel = pmx_from_slice_parallel(&bytes).unwrap();
+            assert_eq!(parallel, pmx_read(&mut bytes.as_slice()).unwrap());
+            assert_eq!(parallel.1, pmx);
+        }
+    }
+
+    #[test]
+    fn keeps_the_trailing_bytes_like_the_sequential_reader() {
+        let mut bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        bytes.extend_from_slice(b"after");
+        let options = ReadOptions {
+            keep_trailing: true,
+            ..ReadOptions::default()
+        };
+        let (_, pmx) = pmx_from_slice_parallel_with(&bytes, &options).unwrap();
+        assert_eq!(pmx.trailing, b"after");
+        assert_eq!(
+            pmx,
+            pmx_read_with(&mut bytes.as_slice(), &options).unwrap().1
+        );
+    }
+
+    #[test]
+    fn fails_at_the_offset_of_the_sequential_reader() {
+        let bytes = pmx_write_to_vec(&large(VERTEX_CHUNK + 5), 2.1).unwrap();
+        for cut in [3, 100, bytes.len() / 2, bytes.len() - 3] {
+            let parallel = pmx_from_slice_parallel(&bytes[..cut]).unwrap_err();
+            let sequential = pmx_read(&mut &bytes[..cut]).unwrap_err();
+            assert_eq!(parallel.offset(), sequential.offset(), "cut at {cut}");
+            assert_eq!(
+                parallel.root().to_string(),
+                sequential.root().to_string(),
+                "cut at {cut}"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_vertices_in_memory_like_the_sequential_reader() {
+        let pmx = large(2 * VERTEX_CHUNK + 7);
+        let header = Header::from_best(2.1, &pmx);
+        let mut bytes = Vec::new();
+        pmx.vertices.write(&header, &mut bytes).unwrap();
+        bytes.extend_from_slice(b"after");
+
+        let mut read = bytes.as_slice();
+        let vertices = Vertices::read_parallel(&header, &mut read).unwrap();
+        assert_eq!(read, b"after");
+        assert_eq!(vertices, pmx.vertices);
+        let mut read = bytes.as_slice();
+        assert_eq!(Vertices::read(&header, &mut read).unwrap(), vertices);
+        assert_eq!(read, b"after");
+
+        let cut = bytes.len() - 10;
+        let e = Vertices::read_parallel(&header, &mut &bytes[..cut]).unwrap_err();
+        assert_eq!(e.offset(), Some(cut as u64));
+    }
+}
@@ -0,0 +1,740 @@
+//! import the older '.pmd' models into the pmx structure
+
+use std::f32::consts::PI;
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use encoding_rs::SHIFT_JIS;
+
+use crate::bone::{
+    Bone, BoneConnection, Ik, IkLink, InheritRotateOrTranslation, RotateOrTranslation,
+};
+use crate::display_frame::{DisplayFrame, DisplayFrameItem};
+use crate::error::PmxError;
+use crate::header::IndexSize;
+use crate::joint::{Joint, JointType};
+use crate::kits::read_f32x3;
+use crate::material::{Material, MaterialFlags, Mix, ToonTexture};
+use crate::morph::{ControlPanel, Morph, MorphData, VertexMorph};
+use crate::pmx::Pmx;
+use crate::rigid_body::{RigidBody, RigidCalcMethod, RigidForm};
+use crate::texture::Textures;
+use crate::vertex::Skin;
+use crate::{BoneIndex, MorphIndex, TextureIndex};
+
+pub const PMD_MAGIC: &[u8; 3] = b"Pmd";
+
+/// read a '.pmd' model and convert it to the pmx structure.
+///
+/// the input is read to its end first, the english names, toon textures,
+/// rigid bodies and joints at the end of the file are optional.
+/// errors are wrapped in [`PmxError::At`] with the offset where parsing failed.
+///
+/// the conversion follows what pmx editors do:
+/// * two bone weights become `BDEF2`, or `BDEF1` when one bone takes everything
+/// * `tex.bmp*sphere.sph` texture names are split into a texture and a sphere map
+/// * the base morph is folded into the other morphs, which become vertex morphs
+/// * bones turning with another bone inherit its rotation, twist bones get a fixed axis
+///   and knee bones get the usual ik angle limit
+/// * rigid body positions are made absolute from the position of their bone
+/// * the "Root" and "表情" special display frames are created before the bone groups
+pub fn pmd_read<R: Read>(read: &mut R) -> Result<Pmx, PmxError> {
+    let mut bytes = Vec::new();
+    read.read_to_end(&mut bytes)?;
+    let mut read = bytes.as_slice();
+    pmd_read_inner(&mut read).map_err(|e| PmxError::At {
+        offset: (bytes.len() - read.len()) as u64,
+        source: Box::new(e),
+    })
+}
+
+fn pmd_read_inner(read: &mut &[u8]) -> Result<Pmx, PmxError> {
+    let mut magic = [0; 3];
+    read.read_exact(&mut magic)?;
+    if &magic != PMD_MAGIC {
+        return Err(PmxError::MagicError);
+    }
+    let _version = read.read_f32::<LittleEndian>()?;
+
+    let mut pmx = Pmx::default();
+    pmx.info.name = read_name(read, 20)?;
+    pmx.info.comment = read_name(read, 256)?;
+    read_vertices(read, &mut pmx)?;
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        let index = read.read_u16::<LittleEndian>()?;
+        pmx.elements.element_indices.push(index as u32);
+    }
+    let toons = read_materials(read, &mut pmx)?;
+    read_bones(read, &mut pmx)?;
+    read_iks(read, &mut pmx)?;
+    let morph_remap = read_morphs(read, &mut pmx)?;
+    read_display_frames(read, &mut pmx, &morph_remap)?;
+
+    if read.is_empty() {
+        return Ok(pmx);
+    }
+    if read.read_u8()? != 0 {
+        read_english_names(read, &mut pmx, &morph_remap)?;
+    }
+    if read.is_empty() {
+        return Ok(pmx);
+    }
+    read_toon_textures(read, &mut pmx, &toons)?;
+    if read.is_empty() {
+        return Ok(pmx);
+    }
+    read_rigid_bodies(read, &mut pmx)?;
+    if read.is_empty() {
+        return Ok(pmx);
+    }
+    read_joints(read, &mut pmx)?;
+    Ok(pmx)
+}
+
+/// a fixed size shift-jis string, cut at the first nul
+fn read_name(read: &mut &[u8], length: usize) -> Result<String, PmxError> {
+    let mut buffer = vec![0; length];
+    read.read_exact(&mut buffer)?;
+    let end = buffer.iter().position(|&i| i == 0).unwrap_or(length);
+    let (name, _) = SHIFT_JIS.decode_without_bom_handling(&buffer[..end]);
+    Ok(name.into_owned())
+}
+
+/// a pmd bone index, `0xFFFF` is none
+fn bone_index(index: u16) -> BoneIndex {
+    if IndexSize::Bit16.is_none(index.into()) {
+        return -1;
+    }
+    index as BoneIndex
+}
+
+fn read_vertices(read: &mut &[u8], pmx: &mut Pmx) -> Result<(), PmxError> {
+    let vertices = &mut pmx.vertices;
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        vertices.position3s.extend(read_f32x3(read)?);
+        vertices.normal3s.extend(read_f32x3(read)?);
+        vertices.uv2s.push(read.read_f32::<LittleEndian>()?);
+        vertices.uv2s.push(read.read_f32::<LittleEndian>()?);
+        let bone_index_1 = bone_index(read.read_u16::<LittleEndian>()?);
+        let bone_index_2 = bone_index(read.read_u16::<LittleEndian>()?);
+        let weight = read.read_u8()?;
+        let no_edge = read.read_u8()? != 0;
+        vertices.skins.push(match weight {
+            _ if weight >= 100 || bone_index_1 == bone_index_2 => Skin::BDEF1 {
+                bone_index: bone_index_1,
+            },
+            0 => Skin::BDEF1 {
+                bone_index: bone_index_2,
+            },
+            _ => Skin::BDEF2 {
+                bone_index_1,
+                bone_index_2,
+                bone_weight_1: weight as f32 / 100.0,
+            },
+        });
+        vertices.edges.push(if no_edge { 0.0 } else { 1.0 });
+    }
+    Ok(())
+}
+
+/// the index of a texture path, adding it if it is new
+fn texture_index(textures: &mut Textures, path: &str) -> TextureIndex {
+    match textures.iter().position(|i| i == path) {
+        Some(index) => index as TextureIndex,
+        None => {
+            textures.textures.push(path.to_owned());
+            textures.len() as TextureIndex - 1
+        }
+    }
+}
+
+/// read the materials, returning their toon numbers
+fn read_materials(read: &mut &[u8], pmx: &mut Pmx) -> Result<Vec<u8>, PmxError> {
+    let count = read.read_u32::<LittleEndian>()?;
+    let mut toons = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let [r, g, b] = read_f32x3(read)?;
+        let alpha = read.read_f32::<LittleEndian>()?;
+        let specular_power = read.read_f32::<LittleEndian>()?;
+        let [sr, sg, sb] = read_f32x3(read)?;
+        let ambient = read_f32x3(read)?;
+        let toon = read.read_u8()?;
+        let has_edge = read.read_u8()? != 0;
+        let element_count = read.read_u32::<LittleEndian>()?;
+        let file = read_name(read, 20)?;
+
+        let mut flags = MaterialFlags::GROUND_SHADOW
+            | MaterialFlags::DRAW_SHADOW
+            | MaterialFlags::RECEIVE_SHADOW;
+        if alpha < 1.0 {
+            flags |= MaterialFlags::DISABLE_CULLING;
+        }
+        if has_edge {
+            flags |= MaterialFlags::HAS_EDGE;
+        }
+        let mut material = Material {
+            diffuse: [r, g, b, alpha],
+            specular: [sr, sg, sb, specular_power],
+            ambient,
+            flags,
+            toon_texture: match toon {
+                0..=9 => ToonTexture::CommonIndex(toon),
+                _ => ToonTexture::TextureIndex(-1),
+            },
+            element_count,
+            ..Material::new(format!("材質{}", index + 1))
+        };
+        for path in file.split('*').filter(|i| !i.is_empty()) {
+            let extension = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+            let mix = match extension.as_str() {
+                "sph" => Mix::Mul,
+                "spa" => Mix::Add,
+                _ => {
+                    material.texture_index = texture_index(&mut pmx.textures, path);
+                    continue;
+                }
+            };
+            material.env_texture_index = texture_index(&mut pmx.textures, path);
+            material.mix = mix;
+        }
+        pmx.materials.materials.push(material);
+        toons.push(toon);
+    }
+    Ok(toons)
+}
+
+fn read_bones(read: &mut &[u8], pmx: &mut Pmx) -> Result<(), PmxError> {
+    let count = read.read_u16::<LittleEndian>()?;
+    let mut kinds = Vec::with_capacity(count as usize);
+    let mut tails = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_name(read, 20)?;
+        let parent = bone_index(read.read_u16::<LittleEndian>()?);
+        let tail = read.read_u16::<LittleEndian>()?;
+        let kind = read.read_u8()?;
+        let ik_parent = bone_index(read.read_u16::<LittleEndian>()?);
+        let position = read_f32x3(read)?;
+
+        // 5 turns with another bone, 9 turns with another bone by the tail percentage
+        let inherit = match kind {
+            5 => Some(1.0),
+            9 => Some(tail as f32 / 100.0),
+            _ => None,
+        };
+        let tail = match (kind, tail) {
+            (9, _) | (_, 0) | (_, 0xFFFF) => -1,
+            (_, i) => i as BoneIndex,
+        };
+        pmx.bones.bones.push(Bone {
+            name,
+            name_en: String::new(),
+            position,
+            parent_bone_index: parent,
+            priority: 0,
+            connect: if tail < 0 {
+                BoneConnection::Position([0.0; 3])
+            } else {
+                BoneConnection::BoneIndex(tail)
+            },
+            rotatable: true,
+            translatable: matches!(kind, 1 | 2),
+            is_visible: !matches!(kind, 6 | 7),
+            enable: true,
+            inherit_local: false,
+            inherit_rotate_or_translation: inherit.map(|weight| InheritRotateOrTranslation {
+                rotate_or_translation: RotateOrTranslation::Rotate,
+                bone_index: ik_parent,
+                weight,
+            }),
+            fixed_axis: None,
+            local_axis: None,
+            physics_after_deform: false,
+            external_parent_bone_index: None,
+            ik: None,
+            unknown_0040: false,
+            unknown_4000: false,
+            unknown_8000: false,
+        });
+        kinds.push(kind);
+        tails.push(tail);
+    }
+
+    // 8 twists around the direction of its tail
+    for (index, (&kind, &tail)) in kinds.iter().zip(&tails).enumerate() {
+        let Some(tail) = pmx.bones.get(tail as usize).filter(|_| kind == 8) else {
+            continue;
+        };
+        let head = pmx.bones[index].position;
+        let axis = [0, 1, 2].map(|i| tail.position[i] - head[i]);
+        let length = axis.iter().map(|i| i * i).sum::<f32>().sqrt();
+        if length > 0.0 {
+            pmx.bones[index].fixed_axis = Some(axis.map(|i| i / length));
+        }
+    }
+    Ok(())
+}
+
+fn read_iks(read: &mut &[u8], pmx: &mut Pmx) -> Result<(), PmxError> {
+    let count = read.read_u16::<LittleEndian>()?;
+    for _ in 0..count {
+        let bone = read.read_u16::<LittleEndian>()?;
+        let target_bone_index = bone_index(read.read_u16::<LittleEndian>()?);
+        let link_count = read.read_u8()?;
+        let iter_count = read.read_u16::<LittleEndian>()? as u32;
+        // pmd limits each iteration to a quarter of the pmx unit angle
+        let limit_angle = read.read_f32::<LittleEndian>()? * 4.0;
+        let mut links = Vec::with_capacity(link_count as usize);
+        for _ in 0..link_count {
+            let bone_index = bone_index(read.read_u16::<LittleEndian>()?);
+            let is_knee = usize::try_from(bone_index)
+                .ok()
+                .and_then(|i| pmx.bones.get(i))
+                .is_some_and(|i| i.name.contains("ひざ"));
+            links.push(IkLink {
+                bone_index,
+                angle_limit: is_knee.then(|| ([-PI, 0.0, 0.0], [-0.5f32.to_radians(), 0.0, 0.0])),
+            });
+        }
+        if let Some(bone) = pmx.bones.bones.get_mut(bone as usize) {
+            bone.ik = Some(Ik {
+                target_bone_index,
+                iter_count,
+                limit_angle,
+                links,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// read the morphs, returning the pmd to pmx morph index remap, `-1` for the base morph
+fn read_morphs(read: &mut &[u8], pmx: &mut Pmx) -> Result<Vec<MorphIndex>, PmxError> {
+    let count = read.read_u16::<LittleEndian>()?;
+    let mut base = Vec::new();
+    let mut remap = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_name(read, 20)?;
+        let offset_count = read.read_u32::<LittleEndian>()?;
+        let kind = read.read_u8()?;
+        let mut offsets = Vec::with_capacity(offset_count as usize);
+        for _ in 0..offset_count {
+            let vertex_index = read.read_u32::<LittleEndian>()?;
+            let offset = read_f32x3(read)?;
+            offsets.push(VertexMorph {
+                vertex_index,
+                offset,
+            });
+        }
+        // the base morph lists the vertices the others offset, by position in the list
+        if kind == 0 {
+            base = offsets;
+            remap.push(-1);
+            continue;
+        }
+        for offset in &mut offsets {
+            let base = base.get(offset.vertex_index as usize);
+            offset.vertex_index = base.ok_or(PmxError::MorphError)?.vertex_index;
+        }
+        remap.push(pmx.morphs.len() as MorphIndex);
+        pmx.morphs.morphs.push(Morph {
+            name,
+            name_en: String::new(),
+            control_panel: match kind {
+                1 => ControlPanel::BottomLeft,
+                2 => ControlPanel::TopLeft,
+                3 => ControlPanel::TopRight,
+                _ => ControlPanel::BottomRight,
+            },
+            morph_data: MorphData::Vertex(offsets),
+        });
+    }
+    Ok(remap)
+}
+
+fn read_display_frames(
+    read: &mut &[u8],
+    pmx: &mut Pmx,
+    morph_remap: &[MorphIndex],
+) -> Result<(), PmxError> {
+    let mut root = DisplayFrame::root();
+    if !pmx.bones.is_empty() {
+        root.items.push(DisplayFrameItem::BoneIndex(0));
+    }
+    let mut expression = DisplayFrame::expression();
+    let count = read.read_u8()?;
+    for _ in 0..count {
+        let index = read.read_u16::<LittleEndian>()? as usize;
+        match morph_remap.get(index) {
+            Some(&i) if i >= 0 => expression.items.push(DisplayFrameItem::MorphIndex(i)),
+            _ => {}
+        }
+    }
+    pmx.display_frames.display_frames.extend([root, expression]);
+
+    let count = read.read_u8()?;
+    for _ in 0..count {
+        let name = read_name(read, 50)?;
+        pmx.display_frames.display_frames.push(DisplayFrame {
+            name: name.trim_end().to_owned(),
+            name_en: String::new(),
+            is_special: false,
+            items: Vec::new(),
+        });
+    }
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        let bone = bone_index(read.read_u16::<LittleEndian>()?);
+        // the bone groups count from 1, after the two special frames
+        let group = read.read_u8()? as usize + 1;
+        if let Some(frame) = pmx
+            .display_frames
+            .display_frames
+            .get_mut(group)
+            .filter(|_| group > 1)
+        {
+            frame.items.push(DisplayFrameItem::BoneIndex(bone));
+        }
+    }
+    Ok(())
+}
+
+fn read_english_names(
+    read: &mut &[u8],
+    pmx: &mut Pmx,
+    morph_remap: &[MorphIndex],
+) -> Result<(), PmxError> {
+    pmx.info.name_en = read_name(read, 20)?;
+    pmx.info.comment_en = read_name(read, 256)?;
+    for bone in &mut pmx.bones {
+        bone.name_en = read_name(read, 20)?;
+    }
+    // every morph except the base one
+    for &index in morph_remap.iter().filter(|&&i| i >= 0) {
+        pmx.morphs[index as usize].name_en = read_name(read, 20)?;
+    }
+    for frame in pmx.display_frames.iter_mut().skip(2) {
+        frame.name_en = read_name(read, 50)?.trim_end().to_owned();
+    }
+    Ok(())
+}
+
+/// read the toon texture names, materials using a renamed toon get it as a texture
+fn read_toon_textures(read: &mut &[u8], pmx: &mut Pmx, toons: &[u8]) -> Result<(), PmxError> {
+    let mut names = Vec::with_capacity(10);
+    for _ in 0..10 {
+        names.push(read_name(read, 100)?);
+    }
+    for (material, &toon) in pmx.materials.materials.iter_mut().zip(toons) {
+        let Some(name) = names.get(toon as usize) else {
+            continue;
+        };
+        if !name.eq_ignore_ascii_case(&format!("toon{:02}.bmp", toon + 1)) {
+            let index = texture_index(&mut pmx.textures, name);
+            material.toon_texture = ToonTexture::TextureIndex(index);
+        }
+    }
+    Ok(())
+}
+
+fn read_rigid_bodies(read: &mut &[u8], pmx: &mut Pmx) -> Result<(), PmxError> {
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        let name = read_name(read, 20)?;
+        let bone_index = bone_index(read.read_u16::<LittleEndian>()?);
+        let group = read.read_u8()?;
+        let un_collision_group_flag = read.read_u16::<LittleEndian>()?;
+        let form = RigidForm::try_from(read.read_u8()?)?;
+        let size = read_f32x3(read)?;
+        let mut position = read_f32x3(read)?;
+        let rotation = read_f32x3(read)?;
+        let mass = read.read_f32::<LittleEndian>()?;
+        let move_resist = read.read_f32::<LittleEndian>()?;
+        let rotation_resist = read.read_f32::<LittleEndian>()?;
+        let repulsion = read.read_f32::<LittleEndian>()?;
+        let friction = read.read_f32::<LittleEndian>()?;
+        let calc_method = RigidCalcMethod::try_from(read.read_u8()?)?;
+
+        // pmd positions are relative to the bone
+        if let Some(bone) = usize::try_from(bone_index)
+            .ok()
+            .and_then(|i| pmx.bones.get(i))
+        {
+            position = [0, 1, 2].map(|i| position[i] + bone.position[i]);
+        }
+        pmx.rigid_bodies.rigid_bodies.push(RigidBody {
+            name,
+            name_en: String::new(),
+            bone_index,
+            group,
+            un_collision_group_flag,
+            form,
+            size,
+            position,
+            rotation,
+            mass,
+            move_resist,
+            rotation_resist,
+            repulsion,
+            friction,
+            calc_method,
+        });
+    }
+    Ok(())
+}
+
+fn read_joints(read: &mut &[u8], pmx: &mut Pmx) -> Result<(), PmxError> {
+    let count = read.read_u32::<LittleEndian>()?;
+    for _ in 0..count {
+        pmx.joints.joints.push(Joint {
+            name: read_name(read, 20)?,
+            name_en: String::new(),
+            joint_type: JointType::Spring6DOF,
+            a_rigid_index: read.read_u32::<LittleEndian>()? as i32,
+            b_rigid_index: read.read_u32::<LittleEndian>()? as i32,
+            position: read_f32x3(read)?,
+            rotation: read_f32x3(read)?,
+            move_limit_down: read_f32x3(read)?,
+            move_limit_up: read_f32x3(read)?,
+            rotation_limit_down: read_f32x3(read)?,
+            rotation_limit_up: read_f32x3(read)?,
+            spring_const_move: read_f32x3(read)?,
+            spring_const_rotation: read_f32x3(read)?,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    /// the bytes of a pmd model, written field by field
+    #[derive(Default)]
+    struct Blob(Vec<u8>);
+
+    impl Blob {
+        fn name(&mut self, name: &str, length: usize) -> &mut Self {
+            let (bytes, _, _) = SHIFT_JIS.encode(name);
+            let mut bytes = bytes.into_owned();
+            bytes.resize(length, 0);
+            self.0.extend(bytes);
+            self
+        }
+        fn u8(&mut self, value: u8) -> &mut Self {
+            self.0.push(value);
+            self
+        }
+        fn u16(&mut self, value: u16) -> &mut Self {
+            self.0.write_u16::<LittleEndian>(value).unwrap();
+            self
+        }
+        fn u32(&mut self, value: u32) -> &mut Self {
+            self.0.write_u32::<LittleEndian>(value).unwrap();
+            self
+        }
+        fn f32s(&mut self, values: &[f32]) -> &mut Self {
+            for &value in values {
+                self.0.write_f32::<LittleEndian>(value).unwrap();
+            }
+            self
+        }
+    }
+
+    fn blob() -> Vec<u8> {
+        let mut b = Blob::default();
+        b.0.extend(PMD_MAGIC);
+        b.f32s(&[1.0]).name("テスト", 20).name("コメント", 256);
+        // vertices: position, normal, uv, two bones, the weight of the first, no edge
+        b.u32(3);
+        b.f32s(&[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+            .u16(0)
+            .u16(1)
+            .u8(100)
+            .u8(0);
+        b.f32s(&[1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0])
+            .u16(0)
+            .u16(1)
+            .u8(40)
+            .u8(0);
+        b.f32s(&[0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0])
+            .u16(1)
+            .u16(0xFFFF)
+            .u8(0)
+            .u8(1);
+        // faces
+        b.u32(3).u16(0).u16(1).u16(2);
+        // a material: diffuse, alpha, specular power, specular, ambient, toon, edge,
+        // element count and texture names
+        b.u32(1)
+            .f32s(&[0.8, 0.7, 0.6, 0.5, 5.0, 0.1, 0.2, 0.3, 0.4, 0.4, 0.4])
+            .u8(0)
+            .u8(1)
+            .u32(3)
+            .name("tex.bmp*sphere.sph", 20);
+        // bones: name, parent, tail, kind, ik or inherit parent, position
+        b.u16(4);
+        b.name("センター", 20)
+            .u16(0xFFFF)
+            .u16(1)
+            .u8(1)
+            .u16(0)
+            .f32s(&[0.0, 10.0, 0.0]);
+        b.name("右ひざ", 20)
+            .u16(0)
+            .u16(0)
+            .u8(0)
+            .u16(0)
+            .f32s(&[0.0, 5.0, 0.0]);
+        b.name("右足ＩＫ", 20)
+            .u16(0)
+            .u16(0)
+            .u8(2)
+            .u16(0)
+            .f32s(&[0.0, 0.0, 0.0]);
+        b.name("右足首", 20)
+            .u16(1)
+            .u16(0)
+            .u8(0)
+            .u16(0)
+            .f32s(&[0.0, 0.0, 0.0]);
+        // an ik: bone, target, link count, iterations, limit angle, links
+        b.u16(1).u16(2).u16(3).u8(1).u16(40).f32s(&[0.5]).u16(1);
+        // morphs: the base lists vertices 2 and 1, the other one moves the second of them
+        b.u16(2);
+        b.name("base", 20).u32(2).u8(0);
+        b.u32(2).f32s(&[0.0, 1.0, 0.0]);
+        b.u32(1).f32s(&[1.0, 0.0, 0.0]);
+        b.name("まばたき", 20).u32(1).u8(1);
+        b.u32(1).f32s(&[0.0, 0.0, 0.5]);
+        // the expression frame, the bone groups and their bones
+        b.u8(1).u16(1);
+        b.u8(1).name("足", 50);
+        b.u32(1).u16(1).u8(1);
+        // english names
+        b.u8(1).name("test", 20).name("comment", 256);
+        b.name("center", 20).name("right knee", 20);
+        b.name("right leg IK", 20).name("right ankle", 20);
+        b.name("blink", 20).name("leg", 50);
+        // toon textures, the first one renamed
+        b.name("mytoon.bmp", 100);
+        for i in 2..=10 {
+            b.name(&format!("toon{i:02}.bmp"), 100);
+        }
+        // a rigid body relative to its bone
+        b.u32(1)
+            .name("剛体", 20)
+            .u16(0)
+            .u8(1)
+            .u16(0xFFFE)
+            .u8(0)
+            .f32s(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0])
+            .f32s(&[1.0, 0.5, 0.5, 0.0, 0.5])
+            .u8(1);
+        // a joint anchored to the world
+        b.u32(1).name("ジョイント", 20).u32(0).u32(u32::MAX);
+        b.f32s(&[0.0; 3 * 8]);
+        b.0
+    }
+
+    #[test]
+    fn reads_a_hand_crafted_pmd() {
+        let pmx = pmd_read(&mut blob().as_slice()).unwrap();
+        assert_eq!(pmx.info.name, "テスト");
+        assert_eq!(pmx.info.comment, "コメント");
+        assert_eq!(pmx.info.name_en, "test");
+        assert_eq!(pmx.info.comment_en, "comment");
+
+        assert_eq!(pmx.vertices.len(), 3);
+        assert_eq!(pmx.vertices.uv2s, [0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pmx.vertices.skins[0], Skin::BDEF1 { bone_index: 0 });
+        assert_eq!(
+            pmx.vertices.skins[1],
+            Skin::BDEF2 {
+                bone_index_1: 0,
+                bone_index_2: 1,
+                bone_weight_1: 0.4,
+            }
+        );
+        assert_eq!(pmx.vertices.skins[2], Skin::BDEF1 { bone_index: -1 });
+        assert_eq!(pmx.vertices.edges, [1.0, 1.0, 0.0]);
+        assert_eq!(pmx.elements.element_indices, [0, 1, 2]);
+
+        assert_eq!(
+            pmx.textures.textures,
+            ["tex.bmp", "sphere.sph", "mytoon.bmp"]
+        );
+        let material = &pmx.materials[0];
+        assert_eq!(material.name, "材質1");
+        assert_eq!(material.diffuse, [0.8, 0.7, 0.6, 0.5]);
+        assert_eq!(material.specular, [0.1, 0.2, 0.3, 5.0]);
+        assert!(material
+            .flags
+            .contains(MaterialFlags::DISABLE_CULLING | MaterialFlags::HAS_EDGE));
+        assert_eq!((material.texture_index, material.env_texture_index), (0, 1));
+        assert_eq!(material.mix, Mix::Mul);
+        assert_eq!(material.toon_texture, ToonTexture::TextureIndex(2));
+        assert_eq!(material.element_count, 3);
+
+        let names: Vec<_> = pmx.bones.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["センター", "右ひざ", "右足ＩＫ", "右足首"]);
+        assert_eq!(pmx.bones[1].name_en, "right knee");
+        assert_eq!(pmx.bones[0].parent_bone_index, -1);
+        assert_eq!(pmx.bones[0].connect, BoneConnection::BoneIndex(1));
+        assert!(pmx.bones[0].translatable);
+        assert_eq!(pmx.bones[1].connect, BoneConnection::Position([0.0; 3]));
+        let ik = pmx.bones[2].ik.as_ref().unwrap();
+        assert_eq!(ik.target_bone_index, 3);
+        assert_eq!((ik.iter_count, ik.limit_angle), (40, 2.0));
+        assert_eq!(ik.links[0].bone_index, 1);
+        assert!(ik.links[0].angle_limit.is_some());
+
+        assert_eq!(pmx.morphs.len(), 1);
+        assert_eq!(pmx.morphs[0].name, "まばたき");
+        assert_eq!(pmx.morphs[0].name_en, "blink");
+        assert_eq!(pmx.morphs[0].control_panel, ControlPanel::BottomLeft);
+        assert_eq!(
+            pmx.morphs[0].morph_data,
+            MorphData::Vertex(vec![VertexMorph {
+                vertex_index: 1,
+                offset: [0.0, 0.0, 0.5],
+            }])
+        );
+
+        let frames = &pmx.display_frames;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].items, [DisplayFrameItem::BoneIndex(0)]);
+        assert_eq!(frames[1].items, [DisplayFrameItem::MorphIndex(0)]);
+        assert_eq!(
+            (frames[2].name.as_str(), frames[2].name_en.as_str()),
+            ("足", "leg")
+        );
+        assert_eq!(frames[2].items, [DisplayFrameItem::BoneIndex(1)]);
+
+        let rigid_body = &pmx.rigid_bodies[0];
+        assert_eq!(rigid_body.name, "剛体");
+        assert_eq!(rigid_body.position, [0.0, 11.0, 0.0]);
+        assert_eq!(rigid_body.calc_method, RigidCalcMethod::Dynamic);
+        assert_eq!(pmx.joints[0].name, "ジョイント");
+        assert_eq!(pmx.joints[0].b_rigid(), None);
+    }
+
+    #[test]
+    fn stops_after_the_display_frames_of_a_short_pmd() {
+        let bytes = blob();
+        // everything up to the english names flag
+        let end = bytes.len() - 1 - (20 + 256 + 4 * 20 + 20 + 50) - 10 * 100;
+        let end = end - (4 + 20 + 2 + 1 + 2 + 1 + 9 * 4 + 5 * 4 + 1) - (4 + 20 + 8 + 8 * 12);
+        let pmx = pmd_read(&mut &bytes[..end]).unwrap();
+        assert_eq!(pmx.morphs.len(), 1);
+        assert_eq!(pmx.info.name_en, "");
+        assert!(pmx.rigid_bodies.is_empty());
+
+        let e = pmd_read(&mut &bytes[..end - 1]).unwrap_err();
+        assert_eq!(e.offset(), Some(end as u64 - 1));
+        assert!(matches!(e.root(), PmxError::UnexpectedEof));
+    }
+}
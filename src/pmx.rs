@@ -1,19 +1,52 @@
+use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
 
-use crate::bone::Bones;
-use crate::display_frame::DisplayFrames;
+use crate::approx::ApproxEq;
+use crate::bone::{Bone, Bones};
+use crate::display_frame::{DisplayFrame, DisplayFrameItem, DisplayFrames};
 use crate::element_index::ElementIndices;
 use crate::error::PmxError;
-use crate::header::Header;
-use crate::joint::Joints;
-use crate::material::Materials;
+use crate::header::{Encoding, Header, IndexSize, PmxVersion};
+use crate::joint::{Joint, Joints};
+use crate::kits::{
+    impl_approx_eq, remap_index, retain_remapped, visit_vec, Affine, Fnv1a, Reachable,
+};
+use crate::material::{Material, Materials};
 use crate::model_info::ModelInfo;
-use crate::morph::Morphs;
-use crate::rigid_body::RigidBodies;
-use crate::soft_body::SoftBodies;
+use crate::morph::{ControlPanel, Morph, Morphs};
+use crate::options::ReadOptions;
+use crate::rigid_body::{RigidBodies, RigidBody};
+use crate::soft_body::{SoftBodies, SoftBody};
+use crate::stats::ModelStats;
 use crate::texture::Textures;
-use crate::vertex::Vertices;
+use crate::vertex::{Skin, Vertices};
+use crate::visitor::PmxVisitor;
+use crate::{BoneIndex, MaterialIndex, MorphIndex, TextureIndex, VertexIndex};
 
+/// a model, the default one being empty.
+///
+/// the empty model is a valid one, written by [`crate::pmx_write`] with 8 bit indices
+/// everywhere, see [`Header::from_best`], and read back as the empty model.
+///
+/// every section has `len` and `is_empty`, counting its items:
+///
+/// ```
+/// use pmx_parser::material::Material;
+/// use pmx_parser::pmx::Pmx;
+///
+/// let mut pmx = Pmx::default();
+/// pmx.textures.textures.push("body.png".into());
+/// pmx.materials.materials.push(Material::new("body"));
+/// let bytes = pmx_parser::pmx_write_to_vec(&pmx, 2.0)?;
+///
+/// let (_, pmx) = pmx_parser::pmx_read(&mut bytes.as_slice())?;
+/// assert_eq!(pmx.bones.len(), 0);
+/// assert!(pmx.bones.is_empty());
+/// assert_eq!(pmx.textures.len(), 1);
+/// assert_eq!(pmx.materials.len(), 1);
+/// assert_eq!((pmx.vertices.len(), pmx.elements.len()), (0, 0));
+/// # Ok::<(), pmx_parser::error::PmxError>(())
+/// ```
 #[derive(Default, Debug, PartialEq, Clone)]
 pub struct Pmx {
     pub info: ModelInfo,
@@ -27,26 +60,187 @@ pub struct Pmx {
     pub rigid_bodies: RigidBodies,
     pub joints: Joints,
     pub soft_bodies: SoftBodies,
+    /// the bytes after the last section, only kept with [`ReadOptions::keep_trailing`],
+    /// written back after the last section
+    pub trailing: Vec<u8>,
+}
+
+/// the model info and section counts, see [`Pmx::read_header_and_info`]
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PmxSummary {
+    pub info: ModelInfo,
+    pub vertex_count: u32,
+    pub element_count: u32,
+    pub texture_count: u32,
+    pub material_count: u32,
+    pub bone_count: u32,
+    pub morph_count: u32,
+    pub display_frame_count: u32,
+    pub rigid_body_count: u32,
+    pub joint_count: u32,
+    pub soft_body_count: u32,
 }
 
 impl Pmx {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
-        Ok(Self {
-            info: ModelInfo::read(header, read)?,
-            vertices: Vertices::read(header, read)?,
-            elements: ElementIndices::read(header, read)?,
-            textures: Textures::read(header, read)?,
-            materials: Materials::read(header, read)?,
-            bones: Bones::read(header, read)?,
-            morphs: Morphs::read(header, read)?,
-            display_frames: DisplayFrames::read(header, read)?,
-            rigid_bodies: RigidBodies::read(header, read)?,
-            joints: Joints::read(header, read)?,
-            soft_bodies: SoftBodies::read(header, read)?,
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        let mut pmx = Self {
+            info: ModelInfo::read(header, options, read).map_err(|e| e.in_section("model info"))?,
+            vertices: Self::read_vertices(header, options, read)
+                .map_err(|e| e.in_section("vertices"))?,
+            elements: ElementIndices::read_checked(header, options, read)
+                .map_err(|e| e.in_section("elements"))?,
+            textures: Textures::read(header, options, read)
+                .map_err(|e| e.in_section("textures"))?,
+            materials: Materials::read(header, options, read)
+                .map_err(|e| e.in_section("materials"))?,
+            bones: Bones::read(header, options, read).map_err(|e| e.in_section("bones"))?,
+            morphs: Morphs::read(header, options, read).map_err(|e| e.in_section("morphs"))?,
+            display_frames: DisplayFrames::read(header, options, read)
+                .map_err(|e| e.in_section("display frames"))?,
+            rigid_bodies: RigidBodies::read(header, options, read)
+                .map_err(|e| e.in_section("rigid bodies"))?,
+            joints: Joints::read(header, options, read).map_err(|e| e.in_section("joints"))?,
+            soft_bodies: SoftBodies::read(header, options, read)
+                .map_err(|e| e.in_section("soft bodies"))?,
+            trailing: Vec::new(),
+        };
+        if options.keep_trailing {
+            read.read_to_end(&mut pmx.trailing)?;
+        }
+        if options.reject_non_finite {
+            pmx.reject_non_finite()?;
+        }
+        Ok(pmx)
+    }
+
+    /// read the vertices, with the skins of version 2.1 if [`ReadOptions::lenient_versions`]
+    fn read_vertices<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Vertices, PmxError> {
+        if options.lenient_versions && !header.supports_qdef() {
+            let lenient = Header {
+                version: PmxVersion::V2_1.to_f32(),
+                unknown_data: Vec::new(),
+                ..*header
+            };
+            Vertices::read(&lenient, read)
+        } else {
+            Vertices::read(header, read)
+        }
+    }
+
+    /// read the model info and count the other sections without building them.
+    ///
+    /// the sections are skipped by consuming their bytes, so any reader works,
+    /// only the names are decoded and nothing else is allocated.
+    pub fn read_header_and_info<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<PmxSummary, PmxError> {
+        Ok(PmxSummary {
+            info: ModelInfo::read(header, options, read).map_err(|e| e.in_section("model info"))?,
+            vertex_count: Vertices::skip(header, read).map_err(|e| e.in_section("vertices"))?,
+            element_count: ElementIndices::skip(header, read)
+                .map_err(|e| e.in_section("elements"))?,
+            texture_count: Textures::skip(header, read).map_err(|e| e.in_section("textures"))?,
+            material_count: Materials::skip(header, read).map_err(|e| e.in_section("materials"))?,
+            bone_count: Bones::skip(header, read).map_err(|e| e.in_section("bones"))?,
+            morph_count: Morphs::skip(header, read).map_err(|e| e.in_section("morphs"))?,
+            display_frame_count: DisplayFrames::skip(header, read)
+                .map_err(|e| e.in_section("display frames"))?,
+            rigid_body_count: RigidBodies::skip(header, read)
+                .map_err(|e| e.in_section("rigid bodies"))?,
+            joint_count: Joints::skip(header, read).map_err(|e| e.in_section("joints"))?,
+            soft_body_count: SoftBodies::skip(header, read)
+                .map_err(|e| e.in_section("soft bodies"))?,
         })
     }
 
+    /// read a model passing each part to `visitor` as soon as it is read, in file order,
+    /// instead of building a [`Pmx`], so only what the visitor keeps stays in memory.
+    ///
+    /// [`ReadOptions::reject_non_finite`] is not applied, the visitor sees the floats as read.
+    pub fn read_visited<R: Read, V: PmxVisitor>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+        visitor: &mut V,
+    ) -> Result<(), PmxError> {
+        visitor.on_info(ModelInfo::read(header, options, read)?);
+        visit_vec(
+            read,
+            |read| Vertices::read_count(header, 1, read),
+            |i, vertex| visitor.on_vertex(i, vertex),
+        )?;
+        visit_vec(
+            read,
+            |read| header.vertex_index.read(read),
+            |i, element| visitor.on_element(i, element),
+        )?;
+        visit_vec(
+            read,
+            |read| header.encoding.read_with(read, options),
+            |i, texture| visitor.on_texture(i, texture),
+        )?;
+        visit_vec(
+            read,
+            |read| Material::read(header, options, read),
+            |i, material| visitor.on_material(i, material),
+        )?;
+        visit_vec(
+            read,
+            |read| Bone::read(header, options, read),
+            |i, bone| visitor.on_bone(i, bone),
+        )?;
+        visit_vec(
+            read,
+            |read| Morph::read(header, options, read),
+            |i, morph| visitor.on_morph(i, morph),
+        )?;
+        visit_vec(
+            read,
+            |read| DisplayFrame::read(header, options, read),
+            |i, display_frame| visitor.on_display_frame(i, display_frame),
+        )?;
+        visit_vec(
+            read,
+            |read| RigidBody::read(header, options, read),
+            |i, rigid_body| visitor.on_rigid_body(i, rigid_body),
+        )?;
+        visit_vec(
+            read,
+            |read| Joint::read(header, options, read),
+            |i, joint| visitor.on_joint(i, joint),
+        )?;
+        if header.supports_soft_bodies() {
+            visit_vec(
+                read,
+                |read| SoftBody::read(header, options, read),
+                |i, soft_body| visitor.on_soft_body(i, soft_body),
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
+        self.write_sections(header, write)?;
+        write.write_all(&self.trailing)?;
+        Ok(())
+    }
+
+    /// write the sections without [`Pmx::trailing`]
+    pub(crate) fn write_sections<W: Write>(
+        &self,
+        header: &Header,
+        write: &mut W,
+    ) -> Result<(), PmxError> {
         self.info.write(header, write)?;
         self.vertices.write(header, write)?;
         self.elements.write(header, write)?;
@@ -60,4 +254,1588 @@ impl Pmx {
         self.soft_bodies.write(header, write)?;
         Ok(())
     }
+
+    /// the name of the first section differing from the one of `other`, in file order
+    pub(crate) fn first_difference(&self, other: &Pmx) -> Option<&'static str> {
+        [
+            ("model info", self.info == other.info),
+            ("vertices", self.vertices == other.vertices),
+            ("elements", self.elements == other.elements),
+            ("textures", self.textures == other.textures),
+            ("materials", self.materials == other.materials),
+            ("bones", self.bones == other.bones),
+            ("morphs", self.morphs == other.morphs),
+            (
+                "display frames",
+                self.display_frames == other.display_frames,
+            ),
+            ("rigid bodies", self.rigid_bodies == other.rigid_bodies),
+            ("joints", self.joints == other.joints),
+            ("soft bodies", self.soft_bodies == other.soft_bodies),
+            ("trailing bytes", self.trailing == other.trailing),
+        ]
+        .into_iter()
+        .find_map(|(section, equal)| (!equal).then_some(section))
+    }
+
+    /// compare the float fields within `epsilon` and everything else exactly, see [`ApproxEq`]
+    pub fn approx_eq(&self, other: &Pmx, epsilon: f32) -> bool {
+        ApproxEq::approx_eq(self, other, epsilon)
+    }
+
+    /// compare like `==` except for the english names and comment,
+    /// which many tools leave empty or fill in differently.
+    ///
+    /// the fields are compared one by one, nothing is copied
+    pub fn eq_ignoring_english(&self, other: &Pmx) -> bool {
+        fn all<T>(a: &[T], b: &[T], eq: fn(&T, &T) -> bool) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| eq(a, b))
+        }
+        let Pmx {
+            info,
+            vertices,
+            elements,
+            textures,
+            materials,
+            bones,
+            morphs,
+            display_frames,
+            rigid_bodies,
+            joints,
+            soft_bodies,
+            trailing,
+        } = self;
+        info.name == other.info.name
+            && info.comment == other.info.comment
+            && *vertices == other.vertices
+            && *elements == other.elements
+            && *textures == other.textures
+            && all(
+                &materials.materials,
+                &other.materials.materials,
+                Material::eq_ignoring_english,
+            )
+            && all(&bones.bones, &other.bones.bones, Bone::eq_ignoring_english)
+            && all(
+                &morphs.morphs,
+                &other.morphs.morphs,
+                Morph::eq_ignoring_english,
+            )
+            && all(
+                &display_frames.display_frames,
+                &other.display_frames.display_frames,
+                DisplayFrame::eq_ignoring_english,
+            )
+            && all(
+                &rigid_bodies.rigid_bodies,
+                &other.rigid_bodies.rigid_bodies,
+                RigidBody::eq_ignoring_english,
+            )
+            && all(
+                &joints.joints,
+                &other.joints.joints,
+                Joint::eq_ignoring_english,
+            )
+            && all(
+                &soft_bodies.soft_bodies,
+                &other.soft_bodies.soft_bodies,
+                SoftBody::eq_ignoring_english,
+            )
+            && *trailing == other.trailing
+    }
+
+    /// empty every `name_en` and the `comment_en`, for tools that only show the japanese ones
+    pub fn clear_english_names(&mut self) {
+        self.for_each_english_mut(|_, english| english.clear());
+    }
+
+    /// give every empty `name_en` the japanese name and an empty `comment_en` the comment,
+    /// the english names already filled in being kept
+    pub fn fill_english_from_japanese(&mut self) {
+        self.for_each_english_mut(|japanese, english| {
+            if english.is_empty() {
+                english.push_str(japanese);
+            }
+        });
+    }
+
+    /// every japanese name and comment with its english counterpart
+    fn for_each_english_mut(&mut self, mut f: impl FnMut(&str, &mut String)) {
+        f(&self.info.name, &mut self.info.name_en);
+        f(&self.info.comment, &mut self.info.comment_en);
+        self.materials
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.bones
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.morphs
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.display_frames
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.rigid_bodies
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.joints
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+        self.soft_bodies
+            .iter_mut()
+            .for_each(|i| f(&i.name, &mut i.name_en));
+    }
+
+    /// the size in bytes of the file [`crate::pmx_write`] would write with this header,
+    /// header and [`Pmx::trailing`] included.
+    ///
+    /// the size is added up from the counts, the index sizes of the header and the lengths
+    /// of the strings in its encoding, without writing anything.
+    /// it is only the size of the file if the model can be written, see [`Pmx::write`]
+    pub fn serialized_size(&self, header: &Header) -> usize {
+        header.serialized_size()
+            + self.info.serialized_size(header)
+            + self.vertices.serialized_size(header)
+            + self.elements.serialized_size(header)
+            + self.textures.serialized_size(header)
+            + self.materials.serialized_size(header)
+            + self.bones.serialized_size(header)
+            + self.morphs.serialized_size(header)
+            + self.display_frames.serialized_size(header)
+            + self.rigid_bodies.serialized_size(header)
+            + self.joints.serialized_size(header)
+            + self.soft_bodies.serialized_size(header)
+            + self.trailing.len()
+    }
+
+    /// a hash of the content, the same on every platform as long as the pmx format
+    /// written by this crate does not change.
+    /// the sections holding floats can not implement [`Hash`], use this as their cache key.
+    ///
+    /// the hashed fields are [`Pmx::info`], [`Pmx::vertices`], [`Pmx::elements`],
+    /// [`Pmx::textures`], [`Pmx::materials`], [`Pmx::bones`], [`Pmx::morphs`],
+    /// [`Pmx::display_frames`], [`Pmx::rigid_bodies`], [`Pmx::joints`]
+    /// and [`Pmx::soft_bodies`], every field of their items included.
+    /// [`Pmx::trailing`] is left out, like the string encoding, index sizes and version
+    /// of the file the model came from: the sections are hashed as [`Pmx::write`] writes them
+    /// under a fixed header of utf-8 strings, 32 bit indices and version 2.1.
+    /// floats are hashed by their bits, `0.0` and `-0.0` hash differently.
+    ///
+    /// fails with the error of writing a model that can not be written,
+    /// like one with uneven vertex arrays
+    pub fn content_hash(&self) -> Result<u64, PmxError> {
+        let mut hash = Fnv1a::new();
+        self.write_sections(&self.content_header(), &mut hash)?;
+        Ok(hash.finish())
+    }
+
+    /// the fixed header [`Pmx::content_hash`] writes the model with
+    fn content_header(&self) -> Header {
+        Header::builder()
+            .version(2.1)
+            .encoding(Encoding::Utf8)
+            .vertex_ext_vec4(self.vertices.ext_vec4s.len() as u8)
+            .index_size(IndexSize::Bit32)
+            .build()
+    }
+
+    /// the bytes [`Pmx::content_hash`] hashes
+    fn content_bytes(&self) -> Result<Vec<u8>, PmxError> {
+        let mut bytes = Vec::new();
+        self.write_sections(&self.content_header(), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// the header [`crate::pmx_write`] writes the model with, see [`Header::from_best`]
+    pub fn optimal_header(&self, version: f32) -> Header {
+        Header::from_best(version, self)
+    }
+
+    /// `header` with the narrowest index sizes for the model, keeping its version, encoding,
+    /// additional uvs and unknown data, to write the model smaller after removing parts of it.
+    ///
+    /// the index values do not change, only their width, so the model is checked by writing
+    /// it to nowhere, failing with [`PmxError::IndexError`] if an index out of the range of
+    /// its section does not fit the narrower width
+    pub fn rewrite_index_sizes(&self, header: &Header) -> Result<Header, PmxError> {
+        let best = self.optimal_header(header.version);
+        let header = Header {
+            vertex_index: best.vertex_index,
+            texture_index: best.texture_index,
+            material_index: best.material_index,
+            bone_index: best.bone_index,
+            morph_index: best.morph_index,
+            rigid_body_index: best.rigid_body_index,
+            ..header.clone()
+        };
+        self.write(&header, &mut std::io::sink())?;
+        Ok(header)
+    }
+
+    /// count the vertices, faces and other elements of the model,
+    /// with the morphs by kind and the vertices by skin kind
+    pub fn stats(&self) -> ModelStats {
+        ModelStats::new(self)
+    }
+
+    /// remove repeated texture paths and point the materials at the remaining ones
+    pub fn dedup_textures(&mut self) {
+        let remap = self.textures.dedup_by_key(str::to_owned);
+        self.materials.remap_textures(&remap);
+    }
+
+    /// append the vertices, faces, textures, materials, bones, morphs, display frames,
+    /// rigid bodies, joints and soft bodies of `other` to this model.
+    ///
+    /// the indices inside the appended parts are shifted past the ones already here,
+    /// negative "none" or "all" indices are kept.
+    /// with `parent_bone` the root bones of `other` are parented to that bone of this model.
+    /// the special display frames of `other` are joined into the special frames here
+    /// with the same name, the other frames are appended.
+    pub fn merge(&mut self, other: &Pmx, parent_bone: Option<BoneIndex>) {
+        let mut other = other.clone();
+        let shift = |offset: usize| {
+            move |index: &mut i32| {
+                if *index >= 0 {
+                    *index += offset as i32;
+                }
+            }
+        };
+
+        let vertex_offset = self.vertices.count();
+        let mut shift_vertex = |index: &mut VertexIndex| *index += vertex_offset;
+        other
+            .elements
+            .element_indices
+            .iter_mut()
+            .for_each(&mut shift_vertex);
+        other.morphs.for_each_vertex_index_mut(&mut shift_vertex);
+        other
+            .soft_bodies
+            .for_each_vertex_index_mut(&mut shift_vertex);
+
+        let textures: Vec<_> = (self.textures.len()..)
+            .take(other.textures.len())
+            .map(|i| i as TextureIndex)
+            .collect();
+        other.materials.remap_textures(&textures);
+
+        let mut shift_material = shift(self.materials.len());
+        other
+            .morphs
+            .for_each_material_index_mut(&mut shift_material);
+        other
+            .soft_bodies
+            .for_each_material_index_mut(&mut shift_material);
+
+        // the roots are found before the parents are shifted
+        let roots = other.bones.roots();
+        let mut shift_bone = shift(self.bones.len());
+        other.vertices.for_each_bone_index_mut(&mut shift_bone);
+        other.bones.for_each_bone_index_mut(&mut shift_bone);
+        other.morphs.for_each_bone_index_mut(&mut shift_bone);
+        other.rigid_bodies.for_each_bone_index_mut(&mut shift_bone);
+        other
+            .display_frames
+            .for_each_bone_index_mut(&mut shift_bone);
+        if let Some(parent_bone) = parent_bone {
+            for root in roots {
+                other.bones[root].parent_bone_index = parent_bone;
+            }
+        }
+
+        let mut shift_morph = shift(self.morphs.len());
+        other.morphs.for_each_morph_index_mut(&mut shift_morph);
+        other
+            .display_frames
+            .for_each_morph_index_mut(&mut shift_morph);
+
+        let mut shift_rigid_body = shift(self.rigid_bodies.len());
+        other
+            .morphs
+            .for_each_rigid_body_index_mut(&mut shift_rigid_body);
+        other
+            .joints
+            .for_each_rigid_body_index_mut(&mut shift_rigid_body);
+        other
+            .soft_bodies
+            .for_each_rigid_body_index_mut(&mut shift_rigid_body);
+
+        self.vertices.append(other.vertices);
+        self.elements
+            .element_indices
+            .append(&mut other.elements.element_indices);
+        self.textures.textures.append(&mut other.textures.textures);
+        self.materials
+            .materials
+            .append(&mut other.materials.materials);
+        self.bones.bones.append(&mut other.bones.bones);
+        self.morphs.morphs.append(&mut other.morphs.morphs);
+        for frame in other.display_frames {
+            let special = self
+                .display_frames
+                .iter_mut()
+                .find(|i| frame.is_special && i.is_special && i.name == frame.name);
+            match special {
+                Some(special) => special.items.extend(frame.items),
+                None => self.display_frames.display_frames.push(frame),
+            }
+        }
+        self.rigid_bodies
+            .rigid_bodies
+            .append(&mut other.rigid_bodies.rigid_bodies);
+        self.joints.joints.append(&mut other.joints.joints);
+        self.soft_bodies
+            .soft_bodies
+            .append(&mut other.soft_bodies.soft_bodies);
+    }
+
+    /// copy the bone at `root` and all of its descendants as a standalone rig fragment,
+    /// see [`Bones::extract_subtree`]
+    pub fn extract_bone_subtree(&self, root: BoneIndex) -> Bones {
+        self.bones.extract_subtree(root)
+    }
+
+    /// remove the bones nothing uses and renumber the references to the others.
+    ///
+    /// a bone is used when a vertex weight, a bone morph, a rigid body or a display frame
+    /// names it, or when a used bone names it as its parent, tail, inherit, ik target or ik link.
+    /// display frame items all count as uses, so every frame keeps its items.
+    pub fn remove_unused_bones(&mut self) {
+        let mut reachable = Reachable::new(self.bones.len());
+        let mut mark = |i: &mut BoneIndex| reachable.mark(*i);
+        self.vertices.for_each_bone_index_mut(&mut mark);
+        self.morphs.for_each_bone_index_mut(&mut mark);
+        self.rigid_bodies.for_each_bone_index_mut(&mut mark);
+        self.display_frames.for_each_bone_index_mut(&mut mark);
+        while let Some(index) = reachable.pop() {
+            self.bones[index].for_each_bone_index_mut(&mut |i| reachable.mark(*i));
+        }
+
+        let remap = reachable.remap();
+        retain_remapped(&mut self.bones.bones, &remap);
+        let mut map = |i: &mut BoneIndex| remap_index(i, &remap);
+        self.vertices.for_each_bone_index_mut(&mut map);
+        self.bones.for_each_bone_index_mut(&mut map);
+        self.morphs.for_each_bone_index_mut(&mut map);
+        self.rigid_bodies.for_each_bone_index_mut(&mut map);
+        self.display_frames.for_each_bone_index_mut(&mut map);
+    }
+
+    /// reorder the bones so that every parent comes before its children and renumber
+    /// the references to them, see [`Bones::is_topologically_sorted`].
+    ///
+    /// the bones keep their relative order where the parents allow it. the deform layers
+    /// (`priority`) are kept, mmd deforming layer by layer, so only the order inside a layer
+    /// changes. bones on a parent cycle and their descendants are moved last
+    pub fn sort_bones_topologically(&mut self) {
+        if self.bones.is_topologically_sorted() {
+            return;
+        }
+        let order = self.bones.topological_order();
+        let mut remap = vec![-1; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            remap[old] = new as BoneIndex;
+        }
+        let mut bones: Vec<Option<Bone>> = self.bones.bones.drain(..).map(Some).collect();
+        self.bones.bones = order.iter().filter_map(|&i| bones[i].take()).collect();
+        let mut map = |i: &mut BoneIndex| remap_index(i, &remap);
+        self.vertices.for_each_bone_index_mut(&mut map);
+        self.bones.for_each_bone_index_mut(&mut map);
+        self.morphs.for_each_bone_index_mut(&mut map);
+        self.rigid_bodies.for_each_bone_index_mut(&mut map);
+        self.display_frames.for_each_bone_index_mut(&mut map);
+    }
+
+    /// remove the morphs nothing uses and renumber the references to the others.
+    ///
+    /// a morph is used when a display frame names it,
+    /// or when a used group or flip morph names it.
+    /// display frame items all count as uses, so every frame keeps its items.
+    pub fn remove_unused_morphs(&mut self) {
+        let mut reachable = Reachable::new(self.morphs.len());
+        self.display_frames
+            .for_each_morph_index_mut(&mut |i| reachable.mark(*i));
+        while let Some(index) = reachable.pop() {
+            self.morphs[index].for_each_morph_index_mut(&mut |i| reachable.mark(*i));
+        }
+
+        let remap = reachable.remap();
+        retain_remapped(&mut self.morphs.morphs, &remap);
+        let mut map = |i: &mut MorphIndex| remap_index(i, &remap);
+        self.morphs.for_each_morph_index_mut(&mut map);
+        self.display_frames.for_each_morph_index_mut(&mut map);
+    }
+
+    /// merge the vertices that match within the epsilons, returning how many were removed.
+    ///
+    /// two vertices match when their positions are within `position_epsilon` and their
+    /// normals within `normal_epsilon` on every axis, their uvs, additional uvs, edge scales
+    /// and skins are equal within `position_epsilon`, and the vertex and uv morphs move them
+    /// alike. a vertex is merged into the first one it matches, so matches do not chain.
+    /// the element indices, the morph offsets and the soft bodies are renumbered, the merged
+    /// vertices dropping their morph offsets, and the triangles keep their order and winding,
+    /// so the material face counts stay valid.
+    /// epsilons of 0 only merge vertices that are exactly equal
+    pub fn weld_vertices(&mut self, position_epsilon: f32, normal_epsilon: f32) -> usize {
+        let offsets = self.morphs.vertex_offsets(self.vertices.len());
+        let remap = self
+            .vertices
+            .weld_remap(position_epsilon, normal_epsilon, |a, b| {
+                offsets[a].len() == offsets[b].len()
+                    && offsets[a]
+                        .iter()
+                        .zip(&offsets[b])
+                        .all(|(a, b)| a.0 == b.0 && a.1.approx_eq(&b.1, position_epsilon))
+            });
+        // a vertex is kept when it is the first one mapped to its new index
+        let mut next = 0;
+        let keep: Vec<bool> = remap
+            .iter()
+            .map(|&i| {
+                let first = i == next;
+                next += first as VertexIndex;
+                first
+            })
+            .collect();
+        let removed = keep.iter().filter(|&&i| !i).count();
+        if removed == 0 {
+            return 0;
+        }
+        self.vertices.retain(&keep);
+        self.morphs
+            .retain_vertex_offsets(|i| keep.get(i as usize).copied().unwrap_or(true));
+        let mut map = |i: &mut VertexIndex| {
+            if let Some(&new) = remap.get(*i as usize) {
+                *i = new;
+            }
+        };
+        self.elements.element_indices.iter_mut().for_each(&mut map);
+        self.morphs.for_each_vertex_index_mut(&mut map);
+        self.soft_bodies.for_each_vertex_index_mut(&mut map);
+        removed
+    }
+
+    /// replace the NaN and infinite floats with 0.0, returning how many were replaced.
+    ///
+    /// covers the vertices (including weights and sdef parameters), the bones,
+    /// the morph offsets and the rigid body, joint and soft body parameters.
+    /// a bone morph rotation with any non-finite component becomes the identity quaternion,
+    /// counting each of those components.
+    pub fn sanitize_floats(&mut self) -> usize {
+        self.vertices.sanitize_floats()
+            + self.bones.sanitize_floats()
+            + self.morphs.sanitize_floats()
+            + self.rigid_bodies.sanitize_floats()
+            + self.joints.sanitize_floats()
+            + self.soft_bodies.sanitize_floats()
+    }
+
+    /// the first element with a float [`Pmx::sanitize_floats`] would replace,
+    /// like `"vertex 3"` or `"bone 0"`, `None` if every one of them is finite.
+    ///
+    /// the sections are checked in file order, elements other than vertices
+    /// are checked through a copy of one at a time
+    pub fn find_non_finite(&self) -> Option<String> {
+        fn first<T: Clone>(items: &[T], f: impl Fn(&mut T) -> usize) -> Option<usize> {
+            items.iter().position(|item| f(&mut item.clone()) > 0)
+        }
+        if let Some(i) = (0..self.vertices.len()).find(|&i| !self.vertices.is_vertex_finite(i)) {
+            Some(format!("vertex {i}"))
+        } else if let Some(i) = first(&self.bones, Bone::sanitize_floats) {
+            Some(format!("bone {i}"))
+        } else if let Some(i) = first(&self.morphs, Morph::sanitize_floats) {
+            Some(format!("morph {i}"))
+        } else if let Some(i) = first(&self.rigid_bodies, RigidBody::sanitize_floats) {
+            Some(format!("rigid body {i}"))
+        } else if let Some(i) = first(&self.joints, Joint::sanitize_floats) {
+            Some(format!("joint {i}"))
+        } else {
+            first(&self.soft_bodies, SoftBody::sanitize_floats).map(|i| format!("soft body {i}"))
+        }
+    }
+
+    /// fail with [`PmxError::NonFinite`] naming the element found by [`Pmx::find_non_finite`]
+    pub(crate) fn reject_non_finite(&self) -> Result<(), PmxError> {
+        match self.find_non_finite() {
+            Some(location) => Err(PmxError::NonFinite { location }),
+            None => Ok(()),
+        }
+    }
+
+    /// the errors writing the model as `version` would fail with for data of a later version,
+    /// a [`PmxError::QdefVersionError`] for a QDEF skin and a [`PmxError::SoftBodyVersionError`]
+    /// for soft bodies, empty if it writes. a model read with [`ReadOptions::lenient_versions`]
+    /// has its warnings here
+    pub fn version_warnings(&self, version: f32) -> Vec<PmxError> {
+        let version = PmxVersion::from_f32(version);
+        let mut warnings = Vec::new();
+        if !version.is_some_and(PmxVersion::supports_qdef)
+            && self
+                .vertices
+                .skins
+                .iter()
+                .any(|i| matches!(i, Skin::QDEF { .. }))
+        {
+            warnings.push(PmxError::QdefVersionError);
+        }
+        if !version.is_some_and(PmxVersion::supports_soft_bodies) && !self.soft_bodies.is_empty() {
+            warnings.push(PmxError::SoftBodyVersionError);
+        }
+        warnings
+    }
+
+    /// reverse the winding of every triangle, see [`ElementIndices::reverse_winding`]
+    pub fn reverse_winding(&mut self) -> Result<(), PmxError> {
+        self.elements.reverse_winding()
+    }
+
+    /// check that the material element counts are whole triangles summing to the number of
+    /// element indices, giving the difference to the last material if they do not sum up.
+    ///
+    /// the counts are positional: the materials take their element indices one after the
+    /// other, so a count can only be checked through the sum. adding or removing triangles
+    /// at the end of the index buffer is fixed, any other mismatch is an error,
+    /// see [`Pmx::set_material_element_counts`] to rebuild the counts
+    pub fn fix_material_element_counts(&mut self) -> Result<(), PmxError> {
+        if let Some(material) = self
+            .materials
+            .iter()
+            .find(|i| !i.element_count.is_multiple_of(3))
+        {
+            return Err(PmxError::ElementCountError(material.element_count));
+        }
+        let count = self.elements.len() as u64;
+        let sum: u64 = self.materials.iter().map(|i| i.element_count as u64).sum();
+        if sum == count {
+            return Ok(());
+        }
+        let Some(last) = self.materials.materials.last_mut() else {
+            return Err(PmxError::InvalidMaterialElementCounts("no material"));
+        };
+        let others = sum - last.element_count as u64;
+        match count
+            .checked_sub(others)
+            .and_then(|i| u32::try_from(i).ok())
+        {
+            Some(i) if i.is_multiple_of(3) => {
+                last.element_count = i;
+                Ok(())
+            }
+            Some(_) => Err(PmxError::ElementCountError(self.elements.count())),
+            None => Err(PmxError::InvalidMaterialElementCounts(
+                "the materials before the last one take more than every element index",
+            )),
+        }
+    }
+
+    /// set the material element counts from the material of every triangle,
+    /// which have to be in material order as the counts are positional,
+    /// see [`Pmx::fix_material_element_counts`]
+    pub fn set_material_element_counts(
+        &mut self,
+        triangle_materials: &[MaterialIndex],
+    ) -> Result<(), PmxError> {
+        if triangle_materials.len() * 3 != self.elements.len() {
+            return Err(PmxError::InvalidMaterialElementCounts(
+                "not one material per triangle",
+            ));
+        }
+        if !triangle_materials.is_sorted() {
+            return Err(PmxError::InvalidMaterialElementCounts(
+                "the triangles are not in material order",
+            ));
+        }
+        let mut counts = vec![0; self.materials.len()];
+        for &material in triangle_materials {
+            let Some(count) = usize::try_from(material)
+                .ok()
+                .and_then(|i| counts.get_mut(i))
+            else {
+                return Err(PmxError::InvalidMaterialElementCounts(
+                    "material index out of range",
+                ));
+            };
+            *count += 3;
+        }
+        for (material, count) in self.materials.materials.iter_mut().zip(counts) {
+            material.element_count = count;
+        }
+        Ok(())
+    }
+
+    /// remove the triangles that [`ElementIndices::degenerate_triangles`] reports,
+    /// returning how many were removed.
+    ///
+    /// the `element_count` of the material owning each removed triangle shrinks by 3,
+    /// indices past the last material are removed without touching the materials
+    pub fn remove_degenerate_triangles(&mut self, epsilon: f32) -> usize {
+        let degenerate = self.elements.degenerate_triangles(&self.vertices, epsilon);
+        if degenerate.is_empty() {
+            return 0;
+        }
+        let mut removed = vec![false; self.elements.len() / 3];
+        degenerate.iter().for_each(|&i| removed[i] = true);
+        let mut start = 0;
+        for material in &mut self.materials.materials {
+            let end = start + material.element_count as usize;
+            let count = removed
+                .get(start / 3..(end / 3).min(removed.len()))
+                .unwrap_or_default()
+                .iter()
+                .filter(|&&i| i)
+                .count();
+            material.element_count = material.element_count.saturating_sub(3 * count as u32);
+            start = end;
+        }
+        let mut index = 0;
+        self.elements.element_indices.retain(|_| {
+            index += 1;
+            !removed.get((index - 1) / 3).copied().unwrap_or(false)
+        });
+        degenerate.len()
+    }
+
+    /// convert the model from the left-handed coordinate system of mmd to a right-handed one
+    /// by mirroring the z axis, converting twice gives back the original model.
+    ///
+    /// positions, normals and offsets have their z component negated,
+    /// and the triangle winding is reversed so faces keep pointing outward.
+    ///
+    /// euler angles (rigid bodies, joints, ik limits) have their x and y angles negated
+    /// and the z angle kept, which is the mirrored rotation whatever the rotation order,
+    /// angle ranges swap their bounds to keep `min <= max`.
+    /// quaternions `[x, y, z, w]` in bone morphs become `[-x, -y, z, w]`.
+    pub fn to_right_handed(&mut self) {
+        self.vertices.flip_z();
+        self.elements.swap_winding();
+        self.bones.flip_z();
+        self.morphs.flip_z();
+        self.rigid_bodies.flip_z();
+        self.joints.flip_z();
+    }
+
+    /// flip the v texture coordinate of every vertex to `1.0 - v` for engines with the texture
+    /// origin at the bottom left, the v offsets of the uv morphs being negated to match.
+    /// with `additional` the additional uvs and their morphs are flipped the same way,
+    /// see [`Vertices::flip_ext_v`]
+    pub fn flip_uv_v(&mut self, additional: bool) {
+        self.vertices.flip_v();
+        if additional {
+            self.vertices.flip_ext_v();
+        }
+        self.morphs.flip_uv_v(additional);
+    }
+
+    /// apply an affine transform to the whole model, e.g. a uniform scale of 0.08
+    /// to convert mmd units to meters.
+    ///
+    /// the matrix is column-major, `matrix[3]` holding the translation, and its last row is ignored.
+    /// positions are fully transformed, offsets (bone connections, morph offsets)
+    /// only by the linear part, normals by the inverse transpose and bone axes are renormalized.
+    /// rigid body sizes and joint move limits are scaled by the mean scale factor,
+    /// and a mirroring transform reverses the triangle winding.
+    ///
+    /// euler angles (rigid bodies, joints, ik limits) and the bone morph quaternions are kept,
+    /// so the result is only exact for translations and uniform scales.
+    pub fn transform(&mut self, matrix: [[f32; 4]; 4]) {
+        let transform = Affine::new(matrix);
+        self.vertices.transform(&transform);
+        if transform.mirrors() {
+            self.elements.swap_winding();
+        }
+        self.bones.transform(&transform);
+        self.morphs.transform(&transform);
+        self.rigid_bodies.transform(&transform);
+        self.joints.transform(&transform);
+    }
+
+    /// replace the display frames with the default ones:
+    /// * the special "Root" frame holding the first bone
+    /// * the special "表情" frame holding every morph, grouped by control panel
+    ///   (eyebrow, eye, mouth, other) with the system panel morphs last
+    /// * a "ボーン" frame holding every other visible bone
+    pub fn generate_default_display_frames(&mut self) {
+        let mut root = DisplayFrame::root();
+        if !self.bones.is_empty() {
+            root.items.push(DisplayFrameItem::BoneIndex(0));
+        }
+        let mut morphs = (0..self.morphs.len()).collect::<Vec<_>>();
+        morphs.sort_by_key(|&i| {
+            let panel = self.morphs[i].control_panel;
+            (panel == ControlPanel::System, panel)
+        });
+        let expression = DisplayFrame {
+            items: morphs
+                .into_iter()
+                .map(|i| DisplayFrameItem::MorphIndex(i as MorphIndex))
+                .collect(),
+            ..DisplayFrame::expression()
+        };
+        let bones = DisplayFrame {
+            name: "ボーン".to_owned(),
+            name_en: "Bones".to_owned(),
+            is_special: false,
+            items: (1..self.bones.len())
+                .filter(|&i| self.bones[i].is_visible)
+                .map(|i| DisplayFrameItem::BoneIndex(i as BoneIndex))
+                .collect(),
+        };
+        self.display_frames.display_frames = vec![root, expression];
+        if !bones.items.is_empty() {
+            self.display_frames.display_frames.push(bones);
+        }
+    }
+}
+
+/// a model usable as a key of a hash map or set, equal to another one when their content
+/// is the same bit for bit, see [`Pmx::content_hash`] for the fields taking part.
+///
+/// floats are compared and hashed by their bits, unlike `==` on [`Pmx`]:
+/// a NaN equals a NaN of the same bits, and `0.0` differs from `-0.0`.
+/// the content is written into memory once, when the model is wrapped
+#[derive(Debug, Clone)]
+pub struct HashableModel {
+    pmx: Pmx,
+    content: Vec<u8>,
+}
+
+impl HashableModel {
+    /// wrap a model, failing with the error of writing it if it can not be written,
+    /// like one with uneven vertex arrays
+    pub fn new(pmx: Pmx) -> Result<Self, PmxError> {
+        let content = pmx.content_bytes()?;
+        Ok(Self { pmx, content })
+    }
+
+    pub fn model(&self) -> &Pmx {
+        &self.pmx
+    }
+
+    pub fn into_inner(self) -> Pmx {
+        self.pmx
+    }
+}
+
+impl PartialEq for HashableModel {
+    fn eq(&self, other: &Self) -> bool {
+        self.content == other.content
+    }
+}
+
+impl Eq for HashableModel {}
+
+impl Hash for HashableModel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.content.hash(state);
+    }
+}
+
+impl_approx_eq!(Pmx {
+    info,
+    vertices,
+    elements,
+    textures,
+    materials,
+    bones,
+    morphs,
+    display_frames,
+    rigid_bodies,
+    joints,
+    soft_bodies,
+    trailing,
+});
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::bone::BoneConnection;
+    use crate::material::ToonTexture;
+    use crate::options::WriteOptions;
+    use crate::testing::sample;
+    use crate::{
+        pmx_read, pmx_read_with, pmx_write_preserving_header, pmx_write_to_vec, pmx_write_with,
+        roundtrip,
+    };
+
+    #[test]
+    fn default_model_writes_and_reads_back() {
+        for version in [2.0, 2.1] {
+            let pmx = Pmx::default();
+            let header = Header::from_best(version, &pmx);
+            assert_eq!(
+                header,
+                Header::builder()
+                    .version(version)
+                    .index_size(IndexSize::Bit8)
+                    .build()
+            );
+
+            let bytes = pmx_write_to_vec(&pmx, version).unwrap();
+            assert_eq!(pmx_read(&mut bytes.as_slice()).unwrap(), (header, pmx));
+        }
+    }
+
+    /// a quad split into two triangles of three vertices each, sharing the 1-2 edge
+    fn split_quad() -> Pmx {
+        let mut pmx = Pmx::default();
+        for [x, y] in [
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [0.0, 1.0],
+            [1.0, 0.0],
+            [1.0, 1.0],
+        ] {
+            pmx.vertices.position3s.extend([x, y, 0.0]);
+            pmx.vertices.normal3s.extend([0.0, 0.0, -1.0]);
+            pmx.vertices.uv2s.extend([x, 1.0 - y]);
+            pmx.vertices.skins.push(Skin::BDEF1 { bone_index: 0 });
+            pmx.vertices.edges.push(1.0);
+        }
+        pmx.elements.element_indices = vec![0, 1, 2, 3, 4, 5];
+        pmx.materials.materials = vec![Material {
+            element_count: 6,
+            ..Material::new("quad")
+        }];
+        pmx
+    }
+
+    #[test]
+    fn weld_vertices_merges_the_shared_edge_of_a_quad() {
+        for epsilon in [0.0, 1e-4] {
+            let mut pmx = split_quad();
+            assert_eq!(pmx.weld_vertices(epsilon, epsilon), 2);
+            assert_eq!(pmx.vertices.len(), 4);
+            assert_eq!(pmx.elements.element_indices, [0, 1, 2, 2, 1, 3]);
+            assert_eq!(
+                pmx.vertices.position3s,
+                [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0]
+            );
+        }
+    }
+
+    #[test]
+    fn weld_vertices_with_zero_epsilon_only_merges_equal_vertices() {
+        let mut pmx = split_quad();
+        // -0.0 equals 0.0, the nudged vertex no longer matches
+        pmx.vertices.position3s[3 * 3] = -0.0;
+        pmx.vertices.position3s[3 * 4] = 1.0 + f32::EPSILON;
+        assert_eq!(pmx.weld_vertices(0.0, 0.0), 1);
+        assert_eq!(pmx.elements.element_indices, [0, 1, 2, 2, 3, 4]);
+
+        let mut pmx = Pmx::default();
+        for i in 0..1000 {
+            pmx.vertices.position3s.extend([i as f32 * 0.001, 0.0, 0.0]);
+            pmx.vertices.normal3s.extend([0.0, 1.0, 0.0]);
+            pmx.vertices.uv2s.extend([0.0, 0.0]);
+            pmx.vertices.skins.push(Skin::BDEF1 { bone_index: 0 });
+            pmx.vertices.edges.push(1.0);
+        }
+        assert_eq!(pmx.weld_vertices(0.0, 0.0), 0);
+        assert_eq!(pmx.weld_vertices(0.0015, 0.0), 500);
+    }
+
+    #[test]
+    fn finds_the_first_non_finite_float_without_changing_the_model() {
+        let mut pmx = sample();
+        assert_eq!(pmx.find_non_finite(), None);
+
+        pmx.soft_bodies[0].mass = f32::INFINITY;
+        assert_eq!(pmx.find_non_finite().as_deref(), Some("soft body 0"));
+        pmx.bones[3].position[1] = f32::NAN;
+        assert_eq!(pmx.find_non_finite().as_deref(), Some("bone 3"));
+        if let Skin::SDEF { sdef_r1, .. } = &mut pmx.vertices.skins[3] {
+            sdef_r1[2] = f32::NAN;
+        }
+        assert_eq!(pmx.find_non_finite().as_deref(), Some("vertex 3"));
+        pmx.vertices.ext_vec4s[1][4 * 2 + 1] = f32::NEG_INFINITY;
+        assert_eq!(pmx.find_non_finite().as_deref(), Some("vertex 2"));
+
+        assert!(pmx.bones[3].position[1].is_nan());
+        assert!(pmx.soft_bodies[0].mass.is_infinite());
+    }
+
+    #[test]
+    fn write_rejects_non_finite_floats_before_writing() {
+        let mut pmx = sample();
+        pmx.joints[0].rotation[0] = f32::NAN;
+        let options = WriteOptions {
+            reject_non_finite: true,
+            ..WriteOptions::default()
+        };
+        let mut bytes = Vec::new();
+        match pmx_write_with(&mut bytes, &pmx, 2.1, &options) {
+            Err(PmxError::NonFinite { location }) => assert_eq!(location, "joint 0"),
+            e => panic!("{e:?}"),
+        }
+        assert!(bytes.is_empty());
+        assert!(pmx.joints[0].rotation[0].is_nan());
+    }
+
+    #[test]
+    fn dedup_textures_points_materials_at_the_kept_path() {
+        let mut pmx = sample();
+        pmx.textures.textures = vec![
+            "a.png".into(),
+            "b.png".into(),
+            "a.png".into(),
+            "c.png".into(),
+        ];
+        pmx.materials[0].texture_index = 2;
+        pmx.materials[0].env_texture_index = 3;
+        pmx.materials[1].texture_index = 0;
+        pmx.materials[1].env_texture_index = -1;
+        pmx.materials[1].toon_texture = ToonTexture::TextureIndex(2);
+        pmx.dedup_textures();
+
+        assert_eq!(pmx.textures.textures, ["a.png", "b.png", "c.png"]);
+        assert_eq!(pmx.materials[0].texture_index, 0);
+        assert_eq!(pmx.materials[0].env_texture_index, 2);
+        assert_eq!(pmx.materials[1].texture_index, 0);
+        assert_eq!(pmx.materials[1].env_texture_index, -1);
+        assert_eq!(pmx.materials[1].toon_texture, ToonTexture::TextureIndex(0));
+    }
+
+    #[test]
+    fn content_hash_ignores_how_the_file_was_written() {
+        let pmx = sample();
+        let mut files = Vec::new();
+        for encoding in [Encoding::Utf16Le, Encoding::Utf8] {
+            let options = WriteOptions {
+                encoding,
+                ..WriteOptions::default()
+            };
+            let mut bytes = Vec::new();
+            pmx_write_with(&mut bytes, &pmx, 2.1, &options).unwrap();
+            files.push(bytes);
+        }
+        let mut bytes = Vec::new();
+        let wide = Header {
+            vertex_index: IndexSize::Bit32,
+            bone_index: IndexSize::Bit32,
+            morph_index: IndexSize::Bit32,
+            ..pmx.optimal_header(2.1)
+        };
+        pmx_write_preserving_header(&mut bytes, &wide, &pmx).unwrap();
+        files.push(bytes.clone());
+        bytes.extend_from_slice(b"editor padding");
+        files.push(bytes);
+
+        let options = ReadOptions {
+            keep_trailing: true,
+            ..ReadOptions::default()
+        };
+        let hashes: Vec<u64> = files
+            .iter()
+            .map(|bytes| {
+                let (_, read) = pmx_read_with(&mut bytes.as_slice(), &options).unwrap();
+                read.content_hash().unwrap()
+            })
+            .collect();
+        assert!(files.windows(2).all(|w| w[0] != w[1]));
+        assert!(hashes
+            .iter()
+            .all(|&hash| hash == pmx.content_hash().unwrap()));
+    }
+
+    #[test]
+    fn content_hash_fails_for_a_model_that_can_not_be_written() {
+        let mut pmx = sample();
+        pmx.vertices.skins.pop();
+        assert!(pmx.content_hash().is_err());
+    }
+
+    #[test]
+    fn hashable_models_of_the_same_content_are_one_key() {
+        let hash = |model: &HashableModel| {
+            let mut hasher = DefaultHasher::new();
+            model.hash(&mut hasher);
+            hasher.finish()
+        };
+        let model = HashableModel::new(sample()).unwrap();
+        let clone = model.clone();
+        assert_eq!(hash(&clone), hash(&model));
+
+        let mut moved = sample();
+        moved.bones[1].position[0] = -0.0;
+        let moved = HashableModel::new(moved).unwrap();
+        let set: HashSet<_> = [model, clone, moved].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn only_writable_models_can_be_wrapped() {
+        let mut uneven = sample();
+        uneven.vertices.skins.pop();
+        assert!(HashableModel::new(uneven).is_err());
+        let mut uneven = sample();
+        uneven.vertices.uv2s.pop();
+        assert!(HashableModel::new(uneven).is_err());
+    }
+
+    #[test]
+    fn serialized_size_is_the_written_length() {
+        let mut pmx = sample();
+        pmx.trailing = b"padding".to_vec();
+        pmx.materials[1].toon_texture = ToonTexture::TextureIndex(1);
+        for encoding in [Encoding::Utf16Le, Encoding::Utf8] {
+            for size in [IndexSize::Bit8, IndexSize::Bit16, IndexSize::Bit32] {
+                let header = Header {
+                    encoding,
+                    vertex_index: size,
+                    texture_index: size,
+                    material_index: size,
+                    bone_index: size,
+                    morph_index: size,
+                    rigid_body_index: size,
+                    unknown_data: vec![0; 3],
+                    ..pmx.optimal_header(2.1)
+                };
+                let mut bytes = Vec::new();
+                pmx_write_preserving_header(&mut bytes, &header, &pmx).unwrap();
+                assert_eq!(
+                    pmx.serialized_size(&header),
+                    bytes.len(),
+                    "{encoding:?} {size:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn later_version_data_fails_on_2_0_unless_read_leniently() {
+        let mut pmx = sample();
+        assert!(matches!(
+            pmx_write_to_vec(&pmx, 2.0),
+            Err(PmxError::QdefVersionError)
+        ));
+        assert!(matches!(
+            pmx.version_warnings(2.0)[..],
+            [PmxError::QdefVersionError, PmxError::SoftBodyVersionError]
+        ));
+        assert!(pmx.version_warnings(2.1).is_empty());
+
+        pmx.soft_bodies = SoftBodies::default();
+        let mut bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        // the same model as a 2.0 file holding a QDEF skin, as some exporters write it
+        bytes[4..8].copy_from_slice(&2.0_f32.to_le_bytes());
+        let e = pmx_read(&mut bytes.as_slice()).unwrap_err();
+        assert!(matches!(e.root(), PmxError::QdefVersionError));
+
+        let options = ReadOptions {
+            lenient_versions: true,
+            ..ReadOptions::default()
+        };
+        let (header, read) = pmx_read_with(&mut bytes.as_slice(), &options).unwrap();
+        assert_eq!((header.version, &read), (2.0, &pmx));
+        assert!(matches!(
+            read.version_warnings(header.version)[..],
+            [PmxError::QdefVersionError]
+        ));
+    }
+
+    #[test]
+    fn summary_matches_a_full_read() {
+        let pmx = sample();
+        let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        let mut read = bytes.as_slice();
+        let header = Header::read(&mut read).unwrap();
+        let summary = Pmx::read_header_and_info(&header, &ReadOptions::default(), &mut read);
+        assert!(read.is_empty());
+        let (_, full) = pmx_read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            summary.unwrap(),
+            PmxSummary {
+                info: full.info.clone(),
+                vertex_count: full.vertices.count(),
+                element_count: full.elements.count(),
+                texture_count: full.textures.count(),
+                material_count: full.materials.count(),
+                bone_count: full.bones.count(),
+                morph_count: full.morphs.count(),
+                display_frame_count: full.display_frames.count(),
+                rigid_body_count: full.rigid_bodies.count(),
+                joint_count: full.joints.count(),
+                soft_body_count: full.soft_bodies.count(),
+            }
+        );
+    }
+
+    #[test]
+    fn removing_degenerate_triangles_shrinks_their_materials() {
+        let mut pmx = split_quad();
+        // a flat triangle in each material and one past the last material
+        pmx.elements.element_indices = vec![0, 1, 2, 0, 2, 3, 3, 4, 5, 1, 1, 5, 0, 0, 0];
+        pmx.materials.materials = vec![
+            Material {
+                element_count: 6,
+                ..Material::new("first")
+            },
+            Material {
+                element_count: 6,
+                ..Material::new("second")
+            },
+        ];
+        assert_eq!(
+            pmx.elements.degenerate_triangles(&pmx.vertices, 1e-6),
+            [1, 3, 4]
+        );
+        assert_eq!(pmx.remove_degenerate_triangles(1e-6), 3);
+        assert_eq!(pmx.elements.element_indices, [0, 1, 2, 3, 4, 5]);
+        let counts: Vec<u32> = pmx.materials.iter().map(|i| i.element_count).collect();
+        assert_eq!(counts, [3, 3]);
+        assert_eq!(pmx.remove_degenerate_triangles(1e-6), 0);
+    }
+
+    #[test]
+    fn models_differing_in_english_names_only_are_equal_ignoring_english() {
+        let pmx = sample();
+        let mut english = sample();
+        english.fill_english_from_japanese();
+        english.info.comment_en = "another comment".into();
+        assert_ne!(english, pmx);
+        assert!(english.eq_ignoring_english(&pmx));
+        assert!(pmx.eq_ignoring_english(&english));
+        english.clear_english_names();
+        assert!(english.eq_ignoring_english(&pmx));
+
+        for change in [
+            |pmx: &mut Pmx| pmx.info.comment.push('x'),
+            |pmx: &mut Pmx| pmx.bones[1].name.push('x'),
+            |pmx: &mut Pmx| pmx.materials[0].comment.push('x'),
+            |pmx: &mut Pmx| pmx.joints[0].position[0] += 1.0,
+            |pmx: &mut Pmx| pmx.soft_bodies[0].mass += 1.0,
+            |pmx: &mut Pmx| pmx.morphs.morphs.truncate(1),
+            |pmx: &mut Pmx| pmx.trailing.push(0),
+        ] {
+            let mut changed = english.clone();
+            change(&mut changed);
+            assert!(!changed.eq_ignoring_english(&pmx));
+        }
+    }
+
+    #[test]
+    fn right_handed_model_has_its_z_and_winding_flipped() {
+        let pmx = sample();
+        let mut right = sample();
+        right.to_right_handed();
+        for i in 0..pmx.vertices.len() {
+            let z = 3 * i + 2;
+            assert_eq!(right.vertices.position3s[z], -pmx.vertices.position3s[z]);
+            assert_eq!(right.vertices.normal3s[z], -pmx.vertices.normal3s[z]);
+            assert_eq!(
+                right.vertices.position3s[z - 2],
+                pmx.vertices.position3s[z - 2]
+            );
+        }
+        for (a, b) in right
+            .elements
+            .as_u32()
+            .chunks(3)
+            .zip(pmx.elements.as_u32().chunks(3))
+        {
+            assert_eq!(a, [b[0], b[2], b[1]]);
+        }
+        let [x, y, z] = pmx.bones[2].position;
+        assert_eq!(right.bones[2].position, [x, y, -z]);
+        let [x, y, z] = pmx.rigid_bodies[0].position;
+        assert_eq!(right.rigid_bodies[0].position, [x, y, -z]);
+        let [x, y, z] = pmx.rigid_bodies[0].rotation;
+        assert_eq!(right.rigid_bodies[0].rotation, [-x, -y, z]);
+        let [x, y, z] = pmx.joints[0].position;
+        assert_eq!(right.joints[0].position, [x, y, -z]);
+
+        right.to_right_handed();
+        assert_eq!(right, pmx);
+    }
+
+    #[test]
+    fn removes_the_dangling_bone_and_morphs() {
+        let mut pmx = sample();
+        // nothing names the ik bone, the tip is kept by a rigid body
+        let mut tip = pmx.bones[2].clone();
+        tip.name = "tip".into();
+        pmx.bones.bones.push(tip);
+        pmx.rigid_bodies[1].bone_index = 5;
+        pmx.remove_unused_bones();
+        let names: Vec<&str> = pmx.bones.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["root", "body", "arm", "hand", "tip"]);
+        assert_eq!(pmx.rigid_bodies[1].bone_index, 4);
+        assert_eq!(pmx.bones[4].parent_bone_index, 1);
+        assert_eq!(pmx.display_frames[2].items.len(), 2);
+
+        // the expression frame shows the flip, which flips the vertex morph
+        pmx.display_frames[1].items = vec![DisplayFrameItem::MorphIndex(6)];
+        pmx.remove_unused_morphs();
+        let names: Vec<&str> = pmx.morphs.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["vertex", "flip"]);
+        assert_eq!(
+            pmx.display_frames[1].items,
+            [DisplayFrameItem::MorphIndex(1)]
+        );
+        assert!(
+            matches!(&pmx.morphs[1].morph_data, crate::morph::MorphData::Flip(f) if f[0].morph_index == 0)
+        );
+    }
+
+    #[test]
+    fn merged_model_references_its_own_parts() {
+        let mut pmx = sample();
+        pmx.merge(&sample(), Some(2));
+
+        assert_eq!(pmx.vertices.count(), 10);
+        assert_eq!(pmx.elements.element_indices[6..], [5, 6, 7, 7, 8, 9]);
+        assert_eq!(pmx.vertices.skins[5], Skin::BDEF1 { bone_index: 5 });
+        assert_eq!(pmx.textures.len(), 4);
+        assert_eq!(pmx.materials[2].texture_index, 2);
+        assert_eq!(pmx.materials[2].env_texture_index, 3);
+
+        let parents: Vec<BoneIndex> = pmx.bones.iter().map(|b| b.parent_bone_index).collect();
+        assert_eq!(parents, [-1, 0, 1, 2, 0, 2, 5, 6, 7, 5]);
+        assert_eq!(pmx.bones[9].ik.as_ref().unwrap().target_bone_index, 8);
+
+        assert!(matches!(
+            &pmx.morphs[9].morph_data,
+            crate::morph::MorphData::Group(g) if g[0].morph_index == 8
+        ));
+        assert_eq!(pmx.rigid_bodies[2].bone_index, 6);
+        assert_eq!(pmx.joints[1].a_rigid_index, 2);
+        assert_eq!(pmx.joints[1].b_rigid_index, 3);
+
+        // the special frames are joined, the arm frame is appended
+        assert_eq!(pmx.display_frames.len(), 4);
+        assert_eq!(
+            pmx.display_frames[0].items,
+            [
+                DisplayFrameItem::BoneIndex(0),
+                DisplayFrameItem::BoneIndex(5)
+            ]
+        );
+        assert_eq!(
+            pmx.display_frames[3].items,
+            [
+                DisplayFrameItem::BoneIndex(7),
+                DisplayFrameItem::BoneIndex(8)
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitize_floats_zeroes_and_counts_the_non_finite_ones() {
+        let mut pmx = sample();
+        assert_eq!(pmx.sanitize_floats(), 0);
+
+        pmx.vertices.position3s[4] = f32::NAN;
+        pmx.vertices.edges[0] = f32::INFINITY;
+        pmx.bones[1].position[2] = f32::NEG_INFINITY;
+        pmx.rigid_bodies[0].mass = f32::NAN;
+        if let crate::morph::MorphData::Bone(b) = &mut pmx.morphs[2].morph_data {
+            b[0].rotates[0] = f32::NAN;
+            b[0].rotates[3] = f32::NAN;
+        }
+        assert_eq!(pmx.sanitize_floats(), 6);
+        assert_eq!(pmx.find_non_finite(), None);
+        assert_eq!(pmx.vertices.position3s[4], 0.0);
+        assert_eq!(pmx.vertices.edges[0], 0.0);
+        assert_eq!(pmx.bones[1].position[2], 0.0);
+        assert_eq!(pmx.rigid_bodies[0].mass, 0.0);
+        if let crate::morph::MorphData::Bone(b) = &pmx.morphs[2].morph_data {
+            assert_eq!(b[0].rotates, [0.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn scaling_by_0_08_converts_to_meters() {
+        let mut pmx = sample();
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, column) in matrix.iter_mut().enumerate() {
+            column[i] = 0.08;
+        }
+        matrix[3] = [0.0, 0.0, 1.0, 1.0];
+        pmx.transform(matrix);
+
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6);
+        assert!(close(&pmx.vertices.position3s[6..9], &[0.16, 0.08, 1.16]));
+        assert_eq!(pmx.vertices.normal3s[6..9], [0.0, 0.0, 1.0]);
+        assert!(close(&pmx.bones[2].position, &[0.0, 0.16, 1.0]));
+        assert!(matches!(
+            pmx.bones[2].connect,
+            BoneConnection::Position(p) if close(&p, &[0.0, 0.08, 0.0])
+        ));
+        assert!(close(&pmx.rigid_bodies[0].position, &[0.08, 0.16, 1.24]));
+        assert!(close(&pmx.rigid_bodies[0].size, &[0.04, 0.16, 0.0]));
+        assert_eq!(pmx.rigid_bodies[0].rotation, [0.1, 0.2, 0.3]);
+        assert!(close(&pmx.joints[0].move_limit_up, &[0.08; 3]));
+        assert_eq!(pmx.elements.element_indices, [0, 1, 2, 2, 3, 4]);
+    }
+
+    #[test]
+    fn default_display_frames_cover_every_visible_bone_and_morph() {
+        let mut pmx = sample();
+        pmx.bones[3].is_visible = false;
+        pmx.morphs[0].control_panel = ControlPanel::System;
+        pmx.generate_default_display_frames();
+
+        let frames = &pmx.display_frames;
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].is_special && frames[1].is_special && !frames[2].is_special);
+        assert_eq!(frames[0].items, [DisplayFrameItem::BoneIndex(0)]);
+        let morphs: Vec<_> = [1, 2, 3, 4, 5, 6, 7, 0]
+            .map(DisplayFrameItem::MorphIndex)
+            .into();
+        assert_eq!(frames[1].items, morphs);
+        assert_eq!(frames[2].items, [1, 2, 4].map(DisplayFrameItem::BoneIndex));
+        // written and read back, every index is in range
+        assert_eq!(roundtrip(&pmx, 2.1).unwrap(), pmx);
+
+        let mut empty = Pmx::default();
+        empty.generate_default_display_frames();
+        assert_eq!(empty.display_frames.len(), 2);
+        assert!(empty.display_frames.iter().all(|f| f.items.is_empty()));
+    }
+
+    #[test]
+    fn every_section_has_a_len_and_is_empty() {
+        let pmx = sample();
+        let lens = [
+            pmx.vertices.len(),
+            pmx.elements.len(),
+            pmx.textures.len(),
+            pmx.materials.len(),
+            pmx.bones.len(),
+            pmx.morphs.len(),
+            pmx.display_frames.len(),
+            pmx.rigid_bodies.len(),
+            pmx.joints.len(),
+            pmx.soft_bodies.len(),
+        ];
+        assert_eq!(lens, [5, 6, 2, 2, 5, 8, 3, 2, 1, 1]);
+        assert_eq!(pmx.vertices.count(), 5);
+        assert_eq!(pmx.bones.count(), 5);
+        assert_eq!(pmx.bones[4].name, "ik");
+        assert_eq!((&pmx.morphs).into_iter().count(), 8);
+
+        let empty = Pmx::default();
+        assert!(empty.vertices.is_empty() && empty.elements.is_empty());
+        assert!(empty.textures.is_empty() && empty.materials.is_empty());
+        assert!(empty.bones.is_empty() && empty.morphs.is_empty());
+        assert!(empty.display_frames.is_empty() && empty.rigid_bodies.is_empty());
+        assert!(empty.joints.is_empty() && empty.soft_bodies.is_empty());
+        assert!(!pmx.vertices.is_empty() && !pmx.soft_bodies.is_empty());
+    }
+
+    #[test]
+    fn content_hash_changes_with_any_mutation() {
+        let pmx = sample();
+        let hash = pmx.content_hash().unwrap();
+        assert_eq!(pmx.clone().content_hash().unwrap(), hash);
+
+        let mutations: [fn(&mut Pmx); 5] = [
+            |pmx| pmx.vertices.position3s[0] = -0.0,
+            |pmx| pmx.info.comment_en.push('!'),
+            |pmx| pmx.materials[1].diffuse[3] = 0.5,
+            |pmx| pmx.joints[0].b_rigid_index = 0,
+            |pmx| pmx.soft_bodies[0].pin_vertex_index.clear(),
+        ];
+        for mutate in mutations {
+            let mut mutated = pmx.clone();
+            mutate(&mut mutated);
+            assert_ne!(mutated.content_hash().unwrap(), hash);
+        }
+
+        // floats are hashed by their bits, so a NaN model has a stable hash
+        let mut nan = pmx.clone();
+        nan.bones[0].position[0] = f32::NAN;
+        assert_ne!(nan, nan.clone());
+        assert_eq!(
+            nan.content_hash().unwrap(),
+            nan.clone().content_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn sorting_puts_parents_first_and_keeps_every_reference() {
+        let sorted = sample();
+        let mut pmx = sample();
+        // reverse the bones so every child comes before its parent
+        pmx.bones.bones.reverse();
+        let mut reverse = |i: &mut BoneIndex| {
+            if *i >= 0 {
+                *i = 4 - *i;
+            }
+        };
+        pmx.vertices.for_each_bone_index_mut(&mut reverse);
+        pmx.bones.for_each_bone_index_mut(&mut reverse);
+        pmx.morphs.for_each_bone_index_mut(&mut reverse);
+        pmx.rigid_bodies.for_each_bone_index_mut(&mut reverse);
+        pmx.display_frames.for_each_bone_index_mut(&mut reverse);
+        assert!(!pmx.bones.is_topologically_sorted());
+
+        pmx.sort_bones_topologically();
+        assert!(pmx.bones.is_topologically_sorted());
+        let names: Vec<&str> = pmx.bones.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["root", "ik", "body", "arm", "hand"]);
+
+        let name = |pmx: &Pmx, i: BoneIndex| pmx.bones[i as usize].name.clone();
+        let ik = pmx.bones[1].ik.as_ref().unwrap();
+        assert_eq!(name(&pmx, ik.target_bone_index), "hand");
+        assert_eq!(name(&pmx, ik.links[0].bone_index), "arm");
+        assert_eq!(name(&pmx, pmx.rigid_bodies[1].bone_index), "arm");
+        for (skin, before) in pmx.vertices.skins.iter().zip(&sorted.vertices.skins) {
+            let names = skin
+                .normalized_weights()
+                .map(|(i, _)| (i >= 0).then(|| name(&pmx, i)));
+            let before = before
+                .normalized_weights()
+                .map(|(i, _)| (i >= 0).then(|| name(&sorted, i)));
+            assert_eq!(names, before);
+        }
+        assert_eq!(
+            pmx.display_frames[2].items,
+            [3, 4].map(DisplayFrameItem::BoneIndex)
+        );
+
+        // an already sorted model is left as it is
+        let mut unchanged = sorted.clone();
+        unchanged.sort_bones_topologically();
+        assert_eq!(unchanged, sorted);
+    }
+
+    #[test]
+    fn material_counts_off_by_3_are_fixed_through_the_last_one() {
+        let counts = |pmx: &Pmx| {
+            pmx.materials
+                .iter()
+                .map(|m| m.element_count)
+                .collect::<Vec<_>>()
+        };
+        let mut pmx = sample();
+        pmx.fix_material_element_counts().unwrap();
+        assert_eq!(counts(&pmx), [3, 3]);
+
+        pmx.materials[1].element_count = 6;
+        pmx.fix_material_element_counts().unwrap();
+        assert_eq!(counts(&pmx), [3, 3]);
+
+        pmx.materials[0].element_count = 9;
+        let e = pmx.fix_material_element_counts().unwrap_err();
+        assert!(matches!(e, PmxError::InvalidMaterialElementCounts(_)));
+        pmx.materials[0].element_count = 4;
+        let e = pmx.fix_material_element_counts().unwrap_err();
+        assert!(matches!(e, PmxError::ElementCountError(4)));
+
+        pmx.set_material_element_counts(&[0, 0]).unwrap();
+        assert_eq!(counts(&pmx), [6, 0]);
+        let e = pmx.set_material_element_counts(&[1, 0]).unwrap_err();
+        assert!(matches!(e, PmxError::InvalidMaterialElementCounts(_)));
+        assert_eq!(counts(&pmx), [6, 0]);
+    }
+
+    #[test]
+    fn small_model_shrinks_from_32_to_8_bit_indices() {
+        let pmx = sample();
+        let wide = Header {
+            vertex_index: IndexSize::Bit32,
+            texture_index: IndexSize::Bit32,
+            material_index: IndexSize::Bit32,
+            bone_index: IndexSize::Bit32,
+            morph_index: IndexSize::Bit32,
+            rigid_body_index: IndexSize::Bit32,
+            unknown_data: vec![7],
+            ..pmx.optimal_header(2.1)
+        };
+        let mut wide_bytes = Vec::new();
+        pmx_write_preserving_header(&mut wide_bytes, &wide, &pmx).unwrap();
+
+        let narrow = pmx.rewrite_index_sizes(&wide).unwrap();
+        let sizes = [
+            narrow.vertex_index,
+            narrow.texture_index,
+            narrow.material_index,
+            narrow.bone_index,
+            narrow.morph_index,
+            narrow.rigid_body_index,
+        ];
+        assert_eq!(sizes, [IndexSize::Bit8; 6]);
+        assert_eq!(narrow.unknown_data, [7]);
+        assert_eq!(narrow.encoding, wide.encoding);
+        let mut bytes = Vec::new();
+        pmx_write_preserving_header(&mut bytes, &narrow, &pmx).unwrap();
+        assert!(bytes.len() < wide_bytes.len());
+        assert_eq!(
+            pmx_read(&mut bytes.as_slice()).unwrap(),
+            (narrow, pmx.clone())
+        );
+
+        // a dangling index that only fits the wide header
+        let mut dangling = pmx;
+        dangling.rigid_bodies[0].bone_index = 300;
+        let e = dangling.rewrite_index_sizes(&wide).unwrap_err();
+        assert!(matches!(e.root(), PmxError::IndexError));
+    }
+
+    #[test]
+    fn english_names_are_cleared_or_filled_from_the_japanese_ones() {
+        let pairs = |pmx: &mut Pmx| {
+            let mut pairs = Vec::new();
+            pmx.for_each_english_mut(|japanese, english| {
+                pairs.push((japanese.to_owned(), english.clone()))
+            });
+            pairs
+        };
+        let mut pmx = sample();
+        assert!(pairs(&mut pmx)
+            .iter()
+            .any(|(_, english)| !english.is_empty()));
+
+        pmx.clear_english_names();
+        let cleared = pairs(&mut pmx);
+        assert_eq!(cleared.len(), 2 + 2 + 5 + 8 + 3 + 2 + 1 + 1);
+        assert!(cleared.iter().all(|(_, english)| english.is_empty()));
+        assert!(pmx.eq_ignoring_english(&sample()));
+
+        pmx.bones[1].name_en = "Body".into();
+        pmx.fill_english_from_japanese();
+        assert_eq!(pmx.info.name_en, "モデル");
+        assert_eq!(pmx.info.comment_en, "コメント");
+        assert_eq!(pmx.materials[0].name_en, "材質1");
+        assert_eq!(pmx.bones[1].name_en, "Body");
+        let filled = pairs(&mut pmx);
+        let unfilled: Vec<_> = filled.iter().filter(|(j, e)| j != e).collect();
+        assert_eq!(unfilled, [&("body".to_owned(), "Body".to_owned())]);
+    }
+
+    #[test]
+    fn flipping_v_mirrors_the_uvs_and_their_morphs() {
+        let uv_offset = |pmx: &Pmx, index: usize| match &pmx.morphs[index].morph_data {
+            crate::morph::MorphData::UV(i) | crate::morph::MorphData::UV1(i) => i[0].offset,
+            _ => unreachable!(),
+        };
+        let mut pmx = sample();
+        pmx.vertices.uv2s[2..4].copy_from_slice(&[0.25, 0.25]);
+        // v values that flip back exactly
+        for uv in pmx.vertices.ext_vec4s[0].chunks_exact_mut(4) {
+            uv[1] = 0.75;
+        }
+
+        let mut flipped = pmx.clone();
+        flipped.flip_uv_v(false);
+        assert_eq!(flipped.vertices.uv2s[2..4], [0.25, 0.75]);
+        assert_eq!(flipped.vertices.ext_vec4s, pmx.vertices.ext_vec4s);
+        assert_eq!(uv_offset(&flipped, 3), [0.1, -0.2, 0.0, 0.0]);
+        assert_eq!(uv_offset(&flipped, 4), [0.1, 0.2, 0.3, 0.4]);
+
+        let mut flipped = pmx.clone();
+        flipped.flip_uv_v(true);
+        assert_eq!(flipped.vertices.uv2s[2..4], [0.25, 0.75]);
+        assert_eq!(flipped.vertices.ext_vec4s[0][4..8], [1.0, 0.25, 0.2, 0.3]);
+        assert_eq!(uv_offset(&flipped, 4), [0.1, -0.2, 0.3, 0.4]);
+
+        flipped.flip_uv_v(true);
+        assert_eq!(flipped, pmx);
+    }
 }
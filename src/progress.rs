@@ -0,0 +1,253 @@
+use std::io::{Chain, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::bone::Bones;
+use crate::display_frame::DisplayFrames;
+use crate::element_index::ElementIndices;
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::joint::Joints;
+use crate::material::Materials;
+use crate::model_info::ModelInfo;
+use crate::morph::Morphs;
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::rigid_body::RigidBodies;
+use crate::soft_body::SoftBodies;
+use crate::texture::Textures;
+use crate::vertex::Vertices;
+
+/// the progress of [`crate::pmx_read_progress`], reported in file order.
+///
+/// every counted section is reported when it starts, with `done` 0 and the count
+/// of the section as `total`, and again when it is read, with `done` equal to `total`.
+/// the elements are counted as vertex indices, 3 per triangle.
+/// the soft bodies are only reported for versions having them
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ReadStage {
+    Header,
+    ModelInfo,
+    Vertices {
+        done: u32,
+        total: u32,
+    },
+    Elements {
+        done: u32,
+        total: u32,
+    },
+    Textures {
+        done: u32,
+        total: u32,
+    },
+    Materials {
+        done: u32,
+        total: u32,
+    },
+    Bones {
+        done: u32,
+        total: u32,
+    },
+    Morphs {
+        done: u32,
+        total: u32,
+    },
+    DisplayFrames {
+        done: u32,
+        total: u32,
+    },
+    RigidBodies {
+        done: u32,
+        total: u32,
+    },
+    Joints {
+        done: u32,
+        total: u32,
+    },
+    SoftBodies {
+        done: u32,
+        total: u32,
+    },
+    /// the whole model is read
+    Done,
+}
+
+impl Pmx {
+    /// read a model like [`Pmx::read`], reporting every section to `progress`
+    pub(crate) fn read_progress<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+        mut progress: impl FnMut(ReadStage),
+    ) -> Result<Self, PmxError> {
+        let info =
+            ModelInfo::read(header, options, read).map_err(|e| e.in_section("model info"))?;
+        progress(ReadStage::ModelInfo);
+        let mut pmx = Self {
+            info,
+            vertices: section(read, "vertices", &mut progress, stage_vertices, |read| {
+                Vertices::read(header, read)
+            })?,
+            elements: section(read, "elements", &mut progress, stage_elements, |read| {
+                ElementIndices::read_checked(header, options, read)
+            })?,
+            textures: section(read, "textures", &mut progress, stage_textures, |read| {
+                Textures::read(header, options, read)
+            })?,
+            materials: section(read, "materials", &mut progress, stage_materials, |read| {
+                Materials::read(header, options, read)
+            })?,
+            bones: section(read, "bones", &mut progress, stage_bones, |read| {
+                Bones::read(header, options, read)
+            })?,
+            morphs: section(read, "morphs", &mut progress, stage_morphs, |read| {
+                Morphs::read(header, options, read)
+            })?,
+            display_frames: section(
+                read,
+                "display frames",
+                &mut progress,
+                stage_frames,
+                |read| DisplayFrames::read(header, options, read),
+            )?,
+            rigid_bodies: section(read, "rigid bodies", &mut progress, stage_rigid, |read| {
+                RigidBodies::read(header, options, read)
+            })?,
+            joints: section(read, "joints", &mut progress, stage_joints, |read| {
+                Joints::read(header, options, read)
+            })?,
+            soft_bodies: SoftBodies::default(),
+            trailing: Vec::new(),
+        };
+        if header.supports_soft_bodies() {
+            pmx.soft_bodies = section(read, "soft bodies", &mut progress, stage_soft, |read| {
+                SoftBodies::read(header, options, read)
+            })?;
+        }
+        if options.keep_trailing {
+            read.read_to_end(&mut pmx.trailing)?;
+        }
+        if options.reject_non_finite {
+            pmx.reject_non_finite()?;
+        }
+        progress(ReadStage::Done);
+        Ok(pmx)
+    }
+}
+
+/// read a counted section, reporting it before and after with the count read ahead of it
+fn section<R: Read, T>(
+    read: &mut R,
+    name: &'static str,
+    progress: &mut impl FnMut(ReadStage),
+    stage: fn(u32, u32) -> ReadStage,
+    f: impl FnOnce(&mut Chain<&[u8], &mut R>) -> Result<T, PmxError>,
+) -> Result<T, PmxError> {
+    let total = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| PmxError::from(e).in_section(name))?;
+    progress(stage(0, total));
+    // give the count back to the section reader
+    let count = total.to_le_bytes();
+    let mut read = count.as_slice().chain(read);
+    let section = f(&mut read).map_err(|e| e.in_section(name))?;
+    progress(stage(total, total));
+    Ok(section)
+}
+
+fn stage_vertices(done: u32, total: u32) -> ReadStage {
+    ReadStage::Vertices { done, total }
+}
+
+fn stage_elements(done: u32, total: u32) -> ReadStage {
+    ReadStage::Elements { done, total }
+}
+
+fn stage_textures(done: u32, total: u32) -> ReadStage {
+    ReadStage::Textures { done, total }
+}
+
+fn stage_materials(done: u32, total: u32) -> ReadStage {
+    ReadStage::Materials { done, total }
+}
+
+fn stage_bones(done: u32, total: u32) -> ReadStage {
+    ReadStage::Bones { done, total }
+}
+
+fn stage_morphs(done: u32, total: u32) -> ReadStage {
+    ReadStage::Morphs { done, total }
+}
+
+fn stage_frames(done: u32, total: u32) -> ReadStage {
+    ReadStage::DisplayFrames { done, total }
+}
+
+fn stage_rigid(done: u32, total: u32) -> ReadStage {
+    ReadStage::RigidBodies { done, total }
+}
+
+fn stage_joints(done: u32, total: u32) -> ReadStage {
+    ReadStage::Joints { done, total }
+}
+
+fn stage_soft(done: u32, total: u32) -> ReadStage {
+    ReadStage::SoftBodies { done, total }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sample;
+    use crate::vertex::Skin;
+    use crate::{pmx_read_progress, pmx_write_to_vec};
+
+    type Stage = fn(u32, u32) -> ReadStage;
+
+    fn stages(pmx: &Pmx, version: f32) -> Vec<ReadStage> {
+        let bytes = pmx_write_to_vec(pmx, version).unwrap();
+        let mut stages = Vec::new();
+        let (_, read) =
+            pmx_read_progress(&mut bytes.as_slice(), |stage| stages.push(stage)).unwrap();
+        assert_eq!(&read, pmx);
+        stages
+    }
+
+    fn expected(pmx: &Pmx, soft_bodies: bool) -> Vec<ReadStage> {
+        let mut counted: Vec<(Stage, u32)> = vec![
+            (stage_vertices, pmx.vertices.count()),
+            (stage_elements, pmx.elements.element_indices.len() as u32),
+            (stage_textures, pmx.textures.count()),
+            (stage_materials, pmx.materials.count()),
+            (stage_bones, pmx.bones.count()),
+            (stage_morphs, pmx.morphs.count()),
+            (stage_frames, pmx.display_frames.count()),
+            (stage_rigid, pmx.rigid_bodies.count()),
+            (stage_joints, pmx.joints.count()),
+        ];
+        if soft_bodies {
+            counted.push((stage_soft, pmx.soft_bodies.count()));
+        }
+        let mut expected = vec![ReadStage::Header, ReadStage::ModelInfo];
+        for (stage, total) in counted {
+            expected.extend([stage(0, total), stage(total, total)]);
+        }
+        expected.push(ReadStage::Done);
+        expected
+    }
+
+    #[test]
+    fn reports_every_section_in_file_order() {
+        let pmx = sample();
+        assert_eq!(stages(&pmx, 2.1), expected(&pmx, true));
+        assert!(expected(&pmx, true).contains(&ReadStage::Vertices { done: 5, total: 5 }));
+    }
+
+    #[test]
+    fn leaves_out_the_soft_bodies_of_version_2_0() {
+        let mut pmx = sample();
+        pmx.vertices.skins[4] = Skin::BDEF1 { bone_index: 0 };
+        pmx.soft_bodies.soft_bodies.clear();
+        assert_eq!(stages(&pmx, 2.0), expected(&pmx, false));
+    }
+}
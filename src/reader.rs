@@ -0,0 +1,127 @@
+use std::io::Read;
+
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::kits::CountingRead;
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::pmx_read_inner;
+
+/// read the models of a stream holding several of them back to back.
+///
+/// every model has to start with the pmx magic right after the previous one ends.
+/// the iteration ends with `None` when the stream ends between two models,
+/// a stream ending inside a model or anything else than the magic gives an error,
+/// wrapped in [`PmxError::At`] with the offset from the start of the stream,
+/// after which the iteration ends.
+pub struct PmxReader<R> {
+    read: CountingRead<R>,
+    options: ReadOptions,
+    done: bool,
+}
+
+impl<R: Read> PmxReader<R> {
+    pub fn new(read: R) -> Self {
+        Self::with_options(read, ReadOptions::default())
+    }
+
+    pub fn with_options(read: R, options: ReadOptions) -> Self {
+        Self {
+            read: CountingRead::new(read),
+            options,
+            done: false,
+        }
+    }
+
+    /// the number of bytes consumed from the stream
+    pub fn offset(&self) -> u64 {
+        self.read.count()
+    }
+
+    /// whether the stream ends here, consuming the first byte of the next model otherwise
+    fn at_end(&mut self, first: &mut [u8; 1]) -> Result<bool, PmxError> {
+        loop {
+            match self.read.read(first) {
+                Ok(0) => return Ok(true),
+                Ok(_) => return Ok(false),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<(Header, Pmx)>, PmxError> {
+        let mut first = [0];
+        if self.at_end(&mut first)? {
+            return Ok(None);
+        }
+        let mut read = first.chain(&mut self.read);
+        pmx_read_inner(&mut read, &self.options).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for PmxReader<R> {
+    type Item = Result<(Header, Pmx), PmxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_next() {
+            Ok(Some(model)) => Some(Ok(model)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(PmxError::At {
+                    offset: self.read.count(),
+                    source: Box::new(e),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmx_write_to_vec;
+    use crate::testing::sample;
+
+    #[test]
+    fn reads_two_models_back_to_back() {
+        let first = sample();
+        let mut second = Pmx::default();
+        second.info.name = "second".into();
+        let mut bytes = pmx_write_to_vec(&first, 2.1).unwrap();
+        let first_length = bytes.len() as u64;
+        bytes.extend(pmx_write_to_vec(&second, 2.0).unwrap());
+
+        let mut reader = PmxReader::new(bytes.as_slice());
+        let (header, pmx) = reader.next().unwrap().unwrap();
+        assert_eq!((header.version, pmx), (2.1, first));
+        assert_eq!(reader.offset(), first_length);
+        let (header, pmx) = reader.next().unwrap().unwrap();
+        assert_eq!((header.version, pmx), (2.0, second));
+        assert_eq!(reader.offset(), bytes.len() as u64);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn stops_after_a_model_cut_short() {
+        let mut bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        let first_length = bytes.len();
+        let cut = bytes[..20].to_vec();
+        bytes.extend(cut);
+
+        let mut reader = PmxReader::new(bytes.as_slice());
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(PmxError::At { offset, .. })) => assert_eq!(offset, first_length as u64 + 20),
+            e => panic!("{e:?}"),
+        }
+        assert!(reader.next().is_none());
+    }
+}
@@ -1,4 +1,5 @@
-use std::fmt::{Debug, Formatter};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -6,7 +7,12 @@ use crate::BoneIndex;
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_f32x3, read_vec, write_f32x3};
+use crate::kits::{
+    flip_z, flip_z_euler, impl_approx_eq, impl_approx_eq_exact, impl_collection,
+    impl_eq_ignoring_english, read_f32x3, read_vec, sanitize, skip, skip_vec, vec_size,
+    write_f32x3, Affine,
+};
+use crate::options::ReadOptions;
 
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct RigidBodies {
@@ -17,11 +23,18 @@ impl RigidBodies {
     pub fn count(&self) -> u32 {
         self.rigid_bodies.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            rigid_bodies: read_vec(read, |read| RigidBody::read(header, read))?,
+            rigid_bodies: read_vec(read, |read| RigidBody::read(header, options, read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| RigidBody::skip(header, read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.rigid_bodies {
@@ -29,8 +42,53 @@ impl RigidBodies {
         }
         Ok(())
     }
+    /// the size [`RigidBodies::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.rigid_bodies, |i| i.serialized_size(header))
+    }
+
+    /// the rigid bodies attached to every bone that has some, in rigid body order,
+    /// the ones without a bone (`-1`) being left out
+    pub fn by_bone(&self) -> HashMap<u32, Vec<usize>> {
+        let mut bones: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (index, rigid_body) in self.rigid_bodies.iter().enumerate() {
+            if let Ok(bone) = u32::try_from(rigid_body.bone_index) {
+                bones.entry(bone).or_default().push(index);
+            }
+        }
+        bones
+    }
+
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        for rigid_body in &mut self.rigid_bodies {
+            f(&mut rigid_body.bone_index);
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        self.rigid_bodies
+            .iter_mut()
+            .map(RigidBody::sanitize_floats)
+            .sum()
+    }
+
+    pub(crate) fn flip_z(&mut self) {
+        for rigid_body in &mut self.rigid_bodies {
+            flip_z(&mut rigid_body.position);
+            flip_z_euler(&mut rigid_body.rotation);
+        }
+    }
+
+    pub(crate) fn transform(&mut self, transform: &Affine) {
+        for rigid_body in &mut self.rigid_bodies {
+            transform.point(&mut rigid_body.position);
+            rigid_body.size.iter_mut().for_each(|i| transform.length(i));
+        }
+    }
 }
 
+impl_collection!(RigidBodies, rigid_bodies, RigidBody);
+
 #[derive(Clone, PartialEq)]
 pub struct RigidBody {
     pub name: String,
@@ -59,10 +117,14 @@ impl Debug for RigidBody {
 }
 
 impl RigidBody {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             bone_index: header.bone_index.read(read)?,
             group: read.read_u8()?,
             un_collision_group_flag: read.read_u16::<LittleEndian>()?,
@@ -78,6 +140,12 @@ impl RigidBody {
             calc_method: RigidCalcMethod::try_from(read.read_u8()?)?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        // group, collision mask, form, size, position, rotation, physics parameters and method
+        skip(read, header.bone_index.bytes() + 1 + 2 + 1 + 36 + 20 + 1)
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -96,9 +164,70 @@ impl RigidBody {
         write.write_u8(self.calc_method as u8)?;
         Ok(())
     }
+
+    /// the size [`RigidBody::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            // like in the skip: the bone, then the fixed size fields
+            + header.bone_index.bytes() as usize
+            + 1 + 2 + 1 + 36 + 20 + 1
+    }
+
+    /// the bit of the own collision group in a collision group mask,
+    /// `0` if `group` is not one of the 16 groups
+    pub fn group_bit(&self) -> u16 {
+        group_bit(self.group)
+    }
+
+    /// whether the rigid body collides with the rigid bodies of `group` (0-15),
+    /// that is whether the bit of the group is cleared in `un_collision_group_flag`.
+    ///
+    /// named for the group as [`RigidBody::collides_with`] takes the other rigid body,
+    /// which is what deciding whether a pair collides needs
+    pub fn collides_with_group(&self, group: u8) -> bool {
+        self.un_collision_group_flag & group_bit(group) == 0
+    }
+
+    /// whether the two rigid bodies collide, which they do unless either one
+    /// masks the group of the other, so the answer is the same both ways
+    pub fn collides_with(&self, other: &RigidBody) -> bool {
+        self.collides_with_group(other.group) && other.collides_with_group(self.group)
+    }
+
+    /// set or clear the bit of `group` (0-15) in `un_collision_group_flag`,
+    /// `enabled` meaning the rigid body does not collide with that group.
+    /// groups out of range are ignored
+    pub fn set_no_collision(&mut self, group: u8, enabled: bool) {
+        if enabled {
+            self.un_collision_group_flag |= group_bit(group);
+        } else {
+            self.un_collision_group_flag &= !group_bit(group);
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        sanitize(
+            self.size
+                .iter_mut()
+                .chain(&mut self.position)
+                .chain(&mut self.rotation)
+                .chain([
+                    &mut self.mass,
+                    &mut self.move_resist,
+                    &mut self.rotation_resist,
+                    &mut self.repulsion,
+                    &mut self.friction,
+                ]),
+        )
+    }
+}
+
+fn group_bit(group: u8) -> u16 {
+    1u16.checked_shl(group as u32).unwrap_or(0)
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum RigidForm {
     Sphere = 0x00,
@@ -119,7 +248,17 @@ impl TryFrom<u8> for RigidForm {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+impl Display for RigidForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sphere => "Sphere",
+            Self::Box => "Box",
+            Self::Capsule => "Capsule",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum RigidCalcMethod {
     Static = 0x00,
@@ -139,3 +278,144 @@ impl TryFrom<u8> for RigidCalcMethod {
         }
     }
 }
+
+impl Display for RigidCalcMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Static => "Static",
+            Self::Dynamic => "Dynamic",
+            Self::DynamicWithBonePosition => "Dynamic with Bone Position",
+        })
+    }
+}
+
+impl_approx_eq!(RigidBodies { rigid_bodies });
+impl_approx_eq!(RigidBody {
+    name,
+    name_en,
+    bone_index,
+    group,
+    un_collision_group_flag,
+    form,
+    size,
+    position,
+    rotation,
+    mass,
+    move_resist,
+    rotation_resist,
+    repulsion,
+    friction,
+    calc_method,
+});
+impl_eq_ignoring_english!(RigidBody {
+    name,
+    bone_index,
+    group,
+    un_collision_group_flag,
+    form,
+    size,
+    position,
+    rotation,
+    mass,
+    move_resist,
+    rotation_resist,
+    repulsion,
+    friction,
+    calc_method,
+});
+impl_approx_eq_exact!(RigidForm, RigidCalcMethod);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joint::Joints;
+    use crate::testing::{joint, rigid_body};
+
+    #[test]
+    fn ragdoll_rigid_bodies_group_by_bone_and_joints_by_rigid_body() {
+        // hips, chest and head bones with two rigid bodies on the chest and a loose one
+        let rigid_bodies = RigidBodies {
+            rigid_bodies: vec![
+                rigid_body("hips", 0),
+                rigid_body("chest", 1),
+                rigid_body("head", 2),
+                rigid_body("chest2", 1),
+                rigid_body("loose", -1),
+            ],
+        };
+        let by_bone = rigid_bodies.by_bone();
+        assert_eq!(by_bone.len(), 3);
+        assert_eq!(by_bone[&0], [0]);
+        assert_eq!(by_bone[&1], [1, 3]);
+        assert_eq!(by_bone[&2], [2]);
+
+        let joints = Joints {
+            joints: vec![
+                joint("hips-chest", 0, 1),
+                joint("chest-head", 1, 2),
+                joint("chest-chest2", 3, 1),
+                joint("loose", 4, -1),
+            ],
+        };
+        assert_eq!(joints.for_rigid_body(0), [0]);
+        assert_eq!(joints.for_rigid_body(1), [0, 1, 2]);
+        assert_eq!(joints.for_rigid_body(4), [3]);
+        assert!(joints.for_rigid_body(5).is_empty());
+    }
+
+    #[test]
+    fn set_bits_are_the_groups_not_collided_with() {
+        let mut body = rigid_body("body", 0);
+        body.group = 3;
+        body.un_collision_group_flag = 0;
+        assert_eq!(body.group_bit(), 0b1000);
+        assert!((0..16).all(|i| body.collides_with_group(i)));
+
+        body.set_no_collision(0, true);
+        body.set_no_collision(15, true);
+        assert_eq!(body.un_collision_group_flag, 0x8001);
+        assert!(!body.collides_with_group(0) && !body.collides_with_group(15));
+        assert!(body.collides_with_group(1));
+
+        // setting a bit twice keeps it, clearing it leaves the other bits alone
+        body.set_no_collision(15, true);
+        body.set_no_collision(0, false);
+        assert_eq!(body.un_collision_group_flag, 0x8000);
+        assert!(body.collides_with_group(0));
+
+        // there are no groups past 15
+        body.set_no_collision(16, true);
+        assert_eq!(body.un_collision_group_flag, 0x8000);
+        body.group = 16;
+        assert_eq!(body.group_bit(), 0);
+        assert!(body.collides_with_group(16));
+    }
+
+    #[test]
+    fn collision_is_the_same_both_ways() {
+        let mut a = rigid_body("a", 0);
+        let mut b = rigid_body("b", 1);
+        (a.group, b.group) = (2, 5);
+        (a.un_collision_group_flag, b.un_collision_group_flag) = (0, 0);
+        assert!(a.collides_with(&b) && b.collides_with(&a));
+
+        // a masks the group of b, b does not mask the group of a
+        a.set_no_collision(5, true);
+        assert!(b.collides_with_group(a.group));
+        assert!(!a.collides_with(&b));
+        assert!(!b.collides_with(&a));
+
+        a.set_no_collision(5, false);
+        b.set_no_collision(2, true);
+        assert!(!a.collides_with(&b) && !b.collides_with(&a));
+    }
+
+    #[test]
+    fn rigid_body_enums_display_their_english_names() {
+        assert_eq!(RigidForm::Capsule.to_string(), "Capsule");
+        assert_eq!(
+            RigidCalcMethod::DynamicWithBonePosition.to_string(),
+            "Dynamic with Bone Position"
+        );
+    }
+}
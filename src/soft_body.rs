@@ -1,10 +1,15 @@
+use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_bool, read_vec};
+use crate::kits::{
+    impl_approx_eq, impl_approx_eq_exact, impl_collection, impl_eq_ignoring_english, read_bool,
+    read_vec, sanitize, skip, skip_vec, vec_size,
+};
+use crate::options::ReadOptions;
 use crate::{MaterialIndex, RigidBodyIndex, VertexIndex};
 
 #[derive(Default, Debug, PartialEq, Clone)]
@@ -19,26 +24,82 @@ impl SoftBodies {
     pub fn count(&self) -> u32 {
         self.soft_bodies.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
-        Ok(if header.version >= 2.1 * (1.0 - f32::EPSILON) {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
+        Ok(if header.supports_soft_bodies() {
             Self {
-                soft_bodies: read_vec(read, |read| SoftBody::read(header, read))?,
+                soft_bodies: read_vec(read, |read| SoftBody::read(header, options, read))?,
             }
         } else {
             Self::default()
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        if header.supports_soft_bodies() {
+            skip_vec(read, |read| SoftBody::skip(header, read))
+        } else {
+            Ok(0)
+        }
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
-        if header.version >= 2.1 * (1.0 - f32::EPSILON) {
+        if header.supports_soft_bodies() {
             write.write_u32::<LittleEndian>(self.count())?;
             for i in &self.soft_bodies {
                 i.write(header, write)?;
             }
+        } else if !self.is_empty() {
+            return Err(PmxError::SoftBodyVersionError);
         }
         Ok(())
     }
+    /// the size [`SoftBodies::write`] writes, nothing for versions without soft bodies
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        if header.supports_soft_bodies() {
+            vec_size(&self.soft_bodies, |i| i.serialized_size(header))
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        self.soft_bodies
+            .iter_mut()
+            .map(SoftBody::sanitize_floats)
+            .sum()
+    }
+
+    pub(crate) fn for_each_vertex_index_mut(&mut self, f: &mut impl FnMut(&mut VertexIndex)) {
+        for soft_body in &mut self.soft_bodies {
+            for anchor in &mut soft_body.anchor_rigid {
+                f(&mut anchor.vertex_index);
+            }
+            soft_body.pin_vertex_index.iter_mut().for_each(&mut *f);
+        }
+    }
+
+    pub(crate) fn for_each_material_index_mut(&mut self, f: &mut impl FnMut(&mut MaterialIndex)) {
+        for soft_body in &mut self.soft_bodies {
+            f(&mut soft_body.material_index);
+        }
+    }
+
+    pub(crate) fn for_each_rigid_body_index_mut(
+        &mut self,
+        f: &mut impl FnMut(&mut RigidBodyIndex),
+    ) {
+        for soft_body in &mut self.soft_bodies {
+            for anchor in &mut soft_body.anchor_rigid {
+                f(&mut anchor.rigid_index);
+            }
+        }
+    }
 }
 
+impl_collection!(SoftBodies, soft_bodies, SoftBody);
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct SoftBody {
     pub name: String,
@@ -47,7 +108,7 @@ pub struct SoftBody {
     pub material_index: MaterialIndex,
     pub group: u8,
     pub un_collision_group_flag: u16,
-    pub bit_flag: u8,
+    pub bit_flag: SoftBodyConfigFlags,
     pub b_link_create_distance: i32,
     pub clusters: u32,
     pub mass: f32,
@@ -87,15 +148,19 @@ pub struct SoftBody {
 }
 
 impl SoftBody {
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            name: header.encoding.read(read)?,
-            name_en: header.encoding.read(read)?,
+            name: header.encoding.read_with(read, options)?,
+            name_en: header.encoding.read_with(read, options)?,
             form: SoftBodyForm::try_from(read.read_u8()?)?,
             material_index: header.material_index.read(read)?,
             group: read.read_u8()?,
             un_collision_group_flag: read.read_u16::<LittleEndian>()?,
-            bit_flag: read.read_u8()?,
+            bit_flag: SoftBodyConfigFlags::from_bits_retain(read.read_u8()?),
             b_link_create_distance: read.read_i32::<LittleEndian>()?,
             clusters: read.read_u32::<LittleEndian>()?,
             mass: read.read_f32::<LittleEndian>()?,
@@ -130,6 +195,18 @@ impl SoftBody {
             pin_vertex_index: read_vec(read, |read| header.vertex_index.read(read))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<(), PmxError> {
+        header.encoding.skip(read)?;
+        header.encoding.skip(read)?;
+        skip(read, 1 + header.material_index.bytes())?;
+        // the fixed size fields from group to vst
+        skip(read, 1 + 2 + 1 + 4 + 4 + 4 + 4 + 4 + 18 * 4 + 4 * 4 + 3 * 4)?;
+        let anchor = header.rigid_body_index.bytes() + header.vertex_index.bytes() + 1;
+        let count = read.read_u32::<LittleEndian>()?;
+        skip(read, count as u64 * anchor)?;
+        let count = read.read_u32::<LittleEndian>()?;
+        skip(read, count as u64 * header.vertex_index.bytes())
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         header.encoding.write(write, self.name.as_str())?;
         header.encoding.write(write, self.name_en.as_str())?;
@@ -137,7 +214,8 @@ impl SoftBody {
         header.material_index.write(write, self.material_index)?;
         write.write_u8(self.group)?;
         write.write_u16::<LittleEndian>(self.un_collision_group_flag)?;
-        write.write_u8(self.bit_flag)?;
+        write.write_u8(self.bit_flag.bits())?;
+        write.write_i32::<LittleEndian>(self.b_link_create_distance)?;
         write.write_u32::<LittleEndian>(self.clusters)?;
         write.write_f32::<LittleEndian>(self.mass)?;
         write.write_f32::<LittleEndian>(self.collision_margin)?;
@@ -177,9 +255,60 @@ impl SoftBody {
         }
         Ok(())
     }
+
+    /// the size [`SoftBody::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let vertex = header.vertex_index.bytes() as usize;
+        let anchor = header.rigid_body_index.bytes() as usize + vertex + 1;
+        header.encoding.serialized_size(&self.name)
+            + header.encoding.serialized_size(&self.name_en)
+            + 1
+            + header.material_index.bytes() as usize
+            // like in the skip: the fixed size fields from group to vst
+            + 1 + 2 + 1 + 4 + 4 + 4 + 4 + 4 + 18 * 4 + 4 * 4 + 3 * 4
+            + vec_size(&self.anchor_rigid, |_| anchor)
+            + vec_size(&self.pin_vertex_index, |_| vertex)
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        sanitize([
+            &mut self.mass,
+            &mut self.collision_margin,
+            &mut self.vcf,
+            &mut self.dp,
+            &mut self.dg,
+            &mut self.lf,
+            &mut self.pr,
+            &mut self.vc,
+            &mut self.df,
+            &mut self.mt,
+            &mut self.chr,
+            &mut self.khr,
+            &mut self.shr,
+            &mut self.ahr,
+            &mut self.srhr_cl,
+            &mut self.skhr_cl,
+            &mut self.sshr_cl,
+            &mut self.sr_splt_cl,
+            &mut self.sk_splt_cl,
+            &mut self.ss_splt_cl,
+            &mut self.lst,
+            &mut self.ast,
+            &mut self.vst,
+        ])
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+    pub struct SoftBodyConfigFlags: u8 {
+        const B_LINK = 0x01;
+        const CLUSTER = 0x02;
+        const LINK_CROSSING = 0x04;
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum SoftBodyForm {
     TriMesh = 0x00,
@@ -198,7 +327,16 @@ impl TryFrom<u8> for SoftBodyForm {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl Display for SoftBodyForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::TriMesh => "Triangle Mesh",
+            Self::Rope => "Rope",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u32)]
 pub enum SoftBodyAeroModel {
     VPoint = 0x00,
@@ -223,7 +361,19 @@ impl TryFrom<u32> for SoftBodyAeroModel {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl Display for SoftBodyAeroModel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::VPoint => "Vertex Point",
+            Self::VTwoSide => "Vertex Two-Sided",
+            Self::VOneSided => "Vertex One-Sided",
+            Self::FTwoSided => "Face Two-Sided",
+            Self::FOneSided => "Face One-Sided",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct SoftBodyAnchorRigid {
     pub rigid_index: RigidBodyIndex,
     pub vertex_index: VertexIndex,
@@ -245,3 +395,153 @@ impl SoftBodyAnchorRigid {
         Ok(())
     }
 }
+
+impl_approx_eq!(SoftBodies { soft_bodies });
+impl_approx_eq!(SoftBody {
+    name,
+    name_en,
+    form,
+    material_index,
+    group,
+    un_collision_group_flag,
+    bit_flag,
+    b_link_create_distance,
+    clusters,
+    mass,
+    collision_margin,
+    aero_model,
+    vcf,
+    dp,
+    dg,
+    lf,
+    pr,
+    vc,
+    df,
+    mt,
+    chr,
+    khr,
+    shr,
+    ahr,
+    srhr_cl,
+    skhr_cl,
+    sshr_cl,
+    sr_splt_cl,
+    sk_splt_cl,
+    ss_splt_cl,
+    v_it,
+    p_it,
+    d_it,
+    c_it,
+    lst,
+    ast,
+    vst,
+    anchor_rigid,
+    pin_vertex_index,
+});
+impl_eq_ignoring_english!(SoftBody {
+    name,
+    form,
+    material_index,
+    group,
+    un_collision_group_flag,
+    bit_flag,
+    b_link_create_distance,
+    clusters,
+    mass,
+    collision_margin,
+    aero_model,
+    vcf,
+    dp,
+    dg,
+    lf,
+    pr,
+    vc,
+    df,
+    mt,
+    chr,
+    khr,
+    shr,
+    ahr,
+    srhr_cl,
+    skhr_cl,
+    sshr_cl,
+    sr_splt_cl,
+    sk_splt_cl,
+    ss_splt_cl,
+    v_it,
+    p_it,
+    d_it,
+    c_it,
+    lst,
+    ast,
+    vst,
+    anchor_rigid,
+    pin_vertex_index,
+});
+impl_approx_eq_exact!(
+    SoftBodyConfigFlags,
+    SoftBodyForm,
+    SoftBodyAeroModel,
+    SoftBodyAnchorRigid
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{Encoding, IndexSize};
+    use crate::testing::soft_body;
+
+    #[test]
+    fn writes_the_b_link_create_distance_after_the_flags() {
+        let header = Header::builder()
+            .version(2.1)
+            .encoding(Encoding::Utf8)
+            .index_size(IndexSize::Bit8)
+            .build();
+        let soft_body = soft_body("soft");
+        let mut bytes = Vec::new();
+        soft_body.write(&header, &mut bytes).unwrap();
+
+        // "soft", "", form, material index, group, un-collision flags
+        let flags = 4 + 4 + 4 + 1 + 1 + 1 + 2;
+        assert_eq!(bytes[flags], 0x81);
+        assert_eq!(bytes[flags + 1..flags + 5], 3_i32.to_le_bytes());
+        assert_eq!(bytes[flags + 5..flags + 9], 4_u32.to_le_bytes());
+        assert_eq!(bytes[flags + 9..flags + 13], 1.0_f32.to_le_bytes());
+        // the fixed size fields up to vst, then one anchor and two pinned vertices
+        let fixed = 1 + 4 + 4 + 4 + 4 + 4 + 18 * 4 + 4 * 4 + 3 * 4;
+        assert_eq!(bytes.len(), flags + fixed + (4 + 3) + (4 + 2));
+
+        let mut read = bytes.as_slice();
+        let back = SoftBody::read(&header, &ReadOptions::default(), &mut read).unwrap();
+        assert!(read.is_empty());
+        assert_eq!(back, soft_body);
+
+        let mut read = bytes.as_slice();
+        SoftBody::skip(&header, &mut read).unwrap();
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn soft_bodies_fail_to_write_as_version_2_0() {
+        let header = Header::builder().version(2.0).build();
+        let mut bytes = Vec::new();
+        SoftBodies::default().write(&header, &mut bytes).unwrap();
+        assert!(bytes.is_empty());
+
+        let soft_bodies = SoftBodies {
+            soft_bodies: vec![soft_body("soft")],
+        };
+        assert!(matches!(
+            soft_bodies.write(&header, &mut bytes),
+            Err(PmxError::SoftBodyVersionError)
+        ));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn soft_body_enums_display_their_english_names() {
+        assert_eq!(SoftBodyForm::TriMesh.to_string(), "Triangle Mesh");
+        assert_eq!(SoftBodyAeroModel::FOneSided.to_string(), "Face One-Sided");
+    }
+}
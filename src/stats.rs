@@ -0,0 +1,214 @@
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::bone::Bones;
+use crate::display_frame::DisplayFrames;
+use crate::element_index::ElementIndices;
+use crate::error::PmxError;
+use crate::header::Header;
+use crate::joint::Joints;
+use crate::material::Materials;
+use crate::model_info::ModelInfo;
+use crate::morph::{Morph, MorphKind};
+use crate::options::ReadOptions;
+use crate::pmx::Pmx;
+use crate::rigid_body::RigidBodies;
+use crate::soft_body::SoftBodies;
+use crate::texture::Textures;
+use crate::vertex::{Skin, Vertices};
+
+/// element counts of a model, see [`Pmx::stats`]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ModelStats {
+    pub vertex_count: u32,
+    pub face_count: u32,
+    pub material_count: u32,
+    pub bone_count: u32,
+    pub morph_count: u32,
+    pub rigid_body_count: u32,
+    pub joint_count: u32,
+    pub soft_body_count: u32,
+    pub texture_count: u32,
+    pub group_morph_count: u32,
+    pub vertex_morph_count: u32,
+    pub bone_morph_count: u32,
+    pub uv_morph_count: u32,
+    /// the morphs of the additional uvs 1 to 4
+    pub additional_uv_morph_counts: [u32; 4],
+    pub material_morph_count: u32,
+    pub flip_morph_count: u32,
+    pub impulse_morph_count: u32,
+    pub bdef1_count: u32,
+    pub bdef2_count: u32,
+    pub bdef4_count: u32,
+    pub sdef_count: u32,
+    pub qdef_count: u32,
+    /// the bone slots of every skin summed, see [`crate::vertex::Skin::bone_count`]
+    pub influence_count: u32,
+}
+
+impl ModelStats {
+    pub fn new(pmx: &Pmx) -> Self {
+        let mut stats = Self {
+            vertex_count: pmx.vertices.count(),
+            face_count: pmx.elements.count() / 3,
+            material_count: pmx.materials.count(),
+            bone_count: pmx.bones.count(),
+            morph_count: pmx.morphs.count(),
+            rigid_body_count: pmx.rigid_bodies.count(),
+            joint_count: pmx.joints.count(),
+            soft_body_count: pmx.soft_bodies.count(),
+            texture_count: pmx.textures.count(),
+            ..Self::default()
+        };
+        for morph in &pmx.morphs {
+            *stats.morph_kind_count(morph.kind()) += 1;
+        }
+        for skin in &pmx.vertices.skins {
+            let count = match skin {
+                Skin::BDEF1 { .. } => &mut stats.bdef1_count,
+                Skin::BDEF2 { .. } => &mut stats.bdef2_count,
+                Skin::BDEF4 { .. } => &mut stats.bdef4_count,
+                Skin::SDEF { .. } => &mut stats.sdef_count,
+                Skin::QDEF { .. } => &mut stats.qdef_count,
+            };
+            *count += 1;
+            stats.influence_count += skin.bone_count() as u32;
+        }
+        stats
+    }
+
+    /// the stats of the model read from `read`, header included, without building it,
+    /// `Pmx::stats_from_path` does this on a memory-mapped file (feature `mmap`).
+    ///
+    /// the sections are skipped over, only the kinds of the skins and morphs are looked at
+    /// and nothing but the model names and comments is allocated.
+    pub fn read<R: Read>(read: &mut R) -> Result<Self, PmxError> {
+        let header = Header::read(read)?;
+        ModelInfo::read(&header, &ReadOptions::default(), read)?;
+        let mut stats = Self {
+            vertex_count: read.read_u32::<LittleEndian>()?,
+            ..Self::default()
+        };
+        for _ in 0..stats.vertex_count {
+            let (count, bones) = match Vertices::skip_one(&header, read)? {
+                0 => (&mut stats.bdef1_count, 1),
+                1 => (&mut stats.bdef2_count, 2),
+                2 => (&mut stats.bdef4_count, 4),
+                3 => (&mut stats.sdef_count, 2),
+                _ => (&mut stats.qdef_count, 4),
+            };
+            *count += 1;
+            stats.influence_count += bones;
+        }
+        stats.face_count = ElementIndices::skip(&header, read)? / 3;
+        stats.texture_count = Textures::skip(&header, read)?;
+        stats.material_count = Materials::skip(&header, read)?;
+        stats.bone_count = Bones::skip(&header, read)?;
+        stats.morph_count = read.read_u32::<LittleEndian>()?;
+        for _ in 0..stats.morph_count {
+            *stats.morph_kind_count(Morph::skip(&header, read)?) += 1;
+        }
+        DisplayFrames::skip(&header, read)?;
+        stats.rigid_body_count = RigidBodies::skip(&header, read)?;
+        stats.joint_count = Joints::skip(&header, read)?;
+        stats.soft_body_count = SoftBodies::skip(&header, read)?;
+        Ok(stats)
+    }
+
+    /// the counter of a morph kind
+    fn morph_kind_count(&mut self, kind: MorphKind) -> &mut u32 {
+        match kind {
+            MorphKind::Group => &mut self.group_morph_count,
+            MorphKind::Vertex => &mut self.vertex_morph_count,
+            MorphKind::Bone => &mut self.bone_morph_count,
+            MorphKind::UV => &mut self.uv_morph_count,
+            MorphKind::UV1 => &mut self.additional_uv_morph_counts[0],
+            MorphKind::UV2 => &mut self.additional_uv_morph_counts[1],
+            MorphKind::UV3 => &mut self.additional_uv_morph_counts[2],
+            MorphKind::UV4 => &mut self.additional_uv_morph_counts[3],
+            MorphKind::Material => &mut self.material_morph_count,
+            MorphKind::Flip => &mut self.flip_morph_count,
+            MorphKind::Impulse => &mut self.impulse_morph_count,
+        }
+    }
+}
+
+impl Display for ModelStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "vertices:     {}", self.vertex_count)?;
+        writeln!(f, "  bdef1:      {}", self.bdef1_count)?;
+        writeln!(f, "  bdef2:      {}", self.bdef2_count)?;
+        writeln!(f, "  bdef4:      {}", self.bdef4_count)?;
+        writeln!(f, "  sdef:       {}", self.sdef_count)?;
+        writeln!(f, "  qdef:       {}", self.qdef_count)?;
+        writeln!(f, "influences:   {}", self.influence_count)?;
+        writeln!(f, "faces:        {}", self.face_count)?;
+        writeln!(f, "textures:     {}", self.texture_count)?;
+        writeln!(f, "materials:    {}", self.material_count)?;
+        writeln!(f, "bones:        {}", self.bone_count)?;
+        writeln!(f, "morphs:       {}", self.morph_count)?;
+        writeln!(f, "  group:      {}", self.group_morph_count)?;
+        writeln!(f, "  vertex:     {}", self.vertex_morph_count)?;
+        writeln!(f, "  bone:       {}", self.bone_morph_count)?;
+        writeln!(f, "  uv:         {}", self.uv_morph_count)?;
+        for (i, count) in self.additional_uv_morph_counts.iter().enumerate() {
+            writeln!(f, "  uv{}:        {}", i + 1, count)?;
+        }
+        writeln!(f, "  material:   {}", self.material_morph_count)?;
+        writeln!(f, "  flip:       {}", self.flip_morph_count)?;
+        writeln!(f, "  impulse:    {}", self.impulse_morph_count)?;
+        writeln!(f, "rigid bodies: {}", self.rigid_body_count)?;
+        writeln!(f, "joints:       {}", self.joint_count)?;
+        write!(f, "soft bodies:  {}", self.soft_body_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmx_write_to_vec;
+    use crate::testing::sample;
+
+    #[test]
+    fn counts_the_sample_model() {
+        let stats = sample().stats();
+        assert_eq!(
+            stats,
+            ModelStats {
+                vertex_count: 5,
+                face_count: 2,
+                material_count: 2,
+                bone_count: 5,
+                morph_count: 8,
+                rigid_body_count: 2,
+                joint_count: 1,
+                soft_body_count: 1,
+                texture_count: 2,
+                group_morph_count: 1,
+                vertex_morph_count: 1,
+                bone_morph_count: 1,
+                uv_morph_count: 1,
+                additional_uv_morph_counts: [1, 0, 0, 0],
+                material_morph_count: 1,
+                flip_morph_count: 1,
+                impulse_morph_count: 1,
+                bdef1_count: 1,
+                bdef2_count: 1,
+                bdef4_count: 1,
+                sdef_count: 1,
+                qdef_count: 1,
+                influence_count: 1 + 2 + 4 + 2 + 4,
+            }
+        );
+
+        let bytes = pmx_write_to_vec(&sample(), 2.1).unwrap();
+        assert_eq!(ModelStats::read(&mut bytes.as_slice()).unwrap(), stats);
+        let display = stats.to_string();
+        assert!(display.starts_with("vertices:     5\n  bdef1:      1\n"));
+        assert!(display.contains("\n  uv:         1\n  uv1:        1\n  uv2:        0\n"));
+        assert!(display.ends_with("\nsoft bodies:  1"));
+    }
+}
@@ -0,0 +1,383 @@
+//! fixtures shared by the unit tests
+
+use crate::bone::{
+    Bone, BoneConnection, Ik, IkLink, InheritRotateOrTranslation, RotateOrTranslation,
+};
+use crate::display_frame::{DisplayFrame, DisplayFrameItem};
+use crate::joint::{Joint, JointType};
+use crate::material::{Material, MaterialFlags, Mix, ToonTexture};
+use crate::morph::{
+    BoneMorph, ControlPanel, FlipMorph, GroupMorph, ImpulseMorph, MaterialMorph, Morph, MorphData,
+    UVMorph, VertexMorph,
+};
+use crate::pmx::Pmx;
+use crate::rigid_body::{RigidBody, RigidCalcMethod, RigidForm};
+use crate::soft_body::{
+    SoftBody, SoftBodyAeroModel, SoftBodyAnchorRigid, SoftBodyConfigFlags, SoftBodyForm,
+};
+use crate::vertex::Skin;
+use crate::BoneIndex;
+
+pub(crate) fn bone(name: &str, parent_bone_index: BoneIndex) -> Bone {
+    Bone {
+        name: name.into(),
+        name_en: String::new(),
+        position: [0.0, parent_bone_index as f32 + 1.0, 0.0],
+        parent_bone_index,
+        priority: 0,
+        connect: BoneConnection::BoneIndex(-1),
+        rotatable: true,
+        translatable: false,
+        is_visible: true,
+        enable: true,
+        inherit_local: false,
+        inherit_rotate_or_translation: None,
+        fixed_axis: None,
+        local_axis: None,
+        physics_after_deform: false,
+        external_parent_bone_index: None,
+        ik: None,
+        unknown_0040: false,
+        unknown_4000: false,
+        unknown_8000: false,
+    }
+}
+
+pub(crate) fn material(name: &str, element_count: u32) -> Material {
+    Material {
+        name_en: "material".into(),
+        diffuse: [0.8, 0.7, 0.6, 1.0],
+        specular: [0.5, 0.5, 0.5, 10.0],
+        ambient: [0.2, 0.2, 0.2],
+        flags: MaterialFlags::HAS_EDGE,
+        texture_index: 0,
+        env_texture_index: 1,
+        mix: Mix::Mul,
+        toon_texture: ToonTexture::CommonIndex(3),
+        comment: "comment".into(),
+        element_count,
+        ..Material::new(name)
+    }
+}
+
+pub(crate) fn morph(name: &str, control_panel: ControlPanel, morph_data: MorphData) -> Morph {
+    Morph {
+        name: name.into(),
+        name_en: String::new(),
+        control_panel,
+        morph_data,
+    }
+}
+
+pub(crate) fn rigid_body(name: &str, bone_index: BoneIndex) -> RigidBody {
+    RigidBody {
+        name: name.into(),
+        name_en: String::new(),
+        bone_index,
+        group: 1,
+        un_collision_group_flag: 0x0002,
+        form: RigidForm::Capsule,
+        size: [0.5, 2.0, 0.0],
+        position: [1.0, 2.0, 3.0],
+        rotation: [0.1, 0.2, 0.3],
+        mass: 1.0,
+        move_resist: 0.5,
+        rotation_resist: 0.5,
+        repulsion: 0.0,
+        friction: 0.5,
+        calc_method: RigidCalcMethod::Dynamic,
+    }
+}
+
+pub(crate) fn joint(name: &str, a_rigid_index: i32, b_rigid_index: i32) -> Joint {
+    Joint {
+        name: name.into(),
+        name_en: String::new(),
+        joint_type: JointType::Spring6DOF,
+        a_rigid_index,
+        b_rigid_index,
+        position: [0.0, 1.0, 0.0],
+        rotation: [0.0; 3],
+        move_limit_down: [-1.0; 3],
+        move_limit_up: [1.0; 3],
+        rotation_limit_down: [-0.5; 3],
+        rotation_limit_up: [0.5; 3],
+        spring_const_move: [0.0; 3],
+        spring_const_rotation: [10.0; 3],
+    }
+}
+
+pub(crate) fn soft_body(name: &str) -> SoftBody {
+    SoftBody {
+        name: name.into(),
+        name_en: String::new(),
+        form: SoftBodyForm::Rope,
+        material_index: 0,
+        group: 1,
+        un_collision_group_flag: 0xFFFE,
+        bit_flag: SoftBodyConfigFlags::B_LINK | SoftBodyConfigFlags::from_bits_retain(0x80),
+        b_link_create_distance: 3,
+        clusters: 4,
+        mass: 1.0,
+        collision_margin: 0.1,
+        aero_model: SoftBodyAeroModel::FOneSided,
+        vcf: 1.0,
+        dp: 2.0,
+        dg: 3.0,
+        lf: 4.0,
+        pr: 5.0,
+        vc: 6.0,
+        df: 7.0,
+        mt: 8.0,
+        chr: 9.0,
+        khr: 10.0,
+        shr: 11.0,
+        ahr: 12.0,
+        srhr_cl: 13.0,
+        skhr_cl: 14.0,
+        sshr_cl: 15.0,
+        sr_splt_cl: 16.0,
+        sk_splt_cl: 17.0,
+        ss_splt_cl: 18.0,
+        v_it: 1,
+        p_it: 2,
+        d_it: 3,
+        c_it: 4,
+        lst: 0.1,
+        ast: 0.2,
+        vst: 0.3,
+        anchor_rigid: vec![SoftBodyAnchorRigid {
+            rigid_index: 0,
+            vertex_index: 1,
+            near_mode: true,
+        }],
+        pin_vertex_index: vec![1, 2],
+    }
+}
+
+/// a version 2.1 model with every section populated, every skin kind,
+/// two additional uvs and every morph kind
+pub(crate) fn sample() -> Pmx {
+    let mut pmx = Pmx::default();
+    pmx.info.name = "モデル".into();
+    pmx.info.name_en = "model".into();
+    pmx.info.comment = "コメント".into();
+    pmx.info.comment_en = "comment".into();
+
+    pmx.vertices.ext_vec4s = vec![Vec::new(), Vec::new()];
+    for i in 0..5 {
+        let i = i as f32;
+        pmx.vertices.position3s.extend([i, 1.0, 2.0]);
+        pmx.vertices.normal3s.extend([0.0, 0.0, 1.0]);
+        pmx.vertices.uv2s.extend([i / 4.0, 0.5]);
+        pmx.vertices.ext_vec4s[0].extend([i, 0.1, 0.2, 0.3]);
+        pmx.vertices.ext_vec4s[1].extend([0.4, i, 0.6, 0.7]);
+        pmx.vertices.edges.push(1.0 + i / 2.0);
+    }
+    pmx.vertices.skins = vec![
+        Skin::BDEF1 { bone_index: 0 },
+        Skin::BDEF2 {
+            bone_index_1: 0,
+            bone_index_2: 1,
+            bone_weight_1: 0.3,
+        },
+        Skin::BDEF4 {
+            bone_index_1: 0,
+            bone_index_2: 1,
+            bone_index_3: 2,
+            bone_index_4: 3,
+            bone_weight_1: 0.1,
+            bone_weight_2: 0.2,
+            bone_weight_3: 0.3,
+            bone_weight_4: 0.4,
+        },
+        Skin::SDEF {
+            bone_index_1: 1,
+            bone_index_2: 2,
+            bone_weight_1: 0.5,
+            sdef_c: [1.0; 3],
+            sdef_r0: [2.0; 3],
+            sdef_r1: [3.0; 3],
+        },
+        Skin::QDEF {
+            bone_index_1: 0,
+            bone_index_2: 1,
+            bone_index_3: 2,
+            bone_index_4: 3,
+            bone_weight_1: 0.25,
+            bone_weight_2: 0.25,
+            bone_weight_3: 0.25,
+            bone_weight_4: 0.25,
+        },
+    ];
+    pmx.elements.element_indices = vec![0, 1, 2, 2, 3, 4];
+    pmx.textures.textures = vec!["tex\\body.png".into(), "sphere.spa".into()];
+    pmx.materials.materials = vec![material("材質1", 3), material("材質2", 3)];
+
+    let mut arm = bone("arm", 1);
+    arm.connect = BoneConnection::Position([0.0, 1.0, 0.0]);
+    arm.fixed_axis = Some([1.0, 0.0, 0.0]);
+    arm.local_axis = Some(([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]));
+    let mut hand = bone("hand", 2);
+    hand.inherit_rotate_or_translation = Some(InheritRotateOrTranslation {
+        rotate_or_translation: RotateOrTranslation::Rotate,
+        bone_index: 1,
+        weight: 0.5,
+    });
+    hand.external_parent_bone_index = Some(2);
+    let mut ik = bone("ik", 0);
+    ik.ik = Some(Ik {
+        target_bone_index: 3,
+        iter_count: 10,
+        limit_angle: 1.0,
+        links: vec![
+            IkLink {
+                bone_index: 2,
+                angle_limit: Some(([-1.0; 3], [1.0; 3])),
+            },
+            IkLink {
+                bone_index: 1,
+                angle_limit: None,
+            },
+        ],
+    });
+    pmx.bones.bones = vec![bone("root", -1), bone("body", 0), arm, hand, ik];
+
+    pmx.morphs.morphs = vec![
+        morph(
+            "vertex",
+            ControlPanel::BottomLeft,
+            MorphData::Vertex(vec![VertexMorph {
+                vertex_index: 1,
+                offset: [0.0, 0.0, 1.0],
+            }]),
+        ),
+        morph(
+            "group",
+            ControlPanel::TopLeft,
+            MorphData::Group(vec![GroupMorph {
+                morph_index: 0,
+                morph_factor: 0.5,
+            }]),
+        ),
+        morph(
+            "bone",
+            ControlPanel::TopRight,
+            MorphData::Bone(vec![BoneMorph {
+                bone_index: 1,
+                translates: [0.0, 1.0, 0.0],
+                rotates: [0.0, 0.0, 0.0, 1.0],
+            }]),
+        ),
+        morph(
+            "uv",
+            ControlPanel::BottomRight,
+            MorphData::UV(vec![UVMorph {
+                vertex_index: 2,
+                offset: [0.1, 0.2, 0.0, 0.0],
+            }]),
+        ),
+        morph(
+            "uv1",
+            ControlPanel::BottomRight,
+            MorphData::UV1(vec![UVMorph {
+                vertex_index: 3,
+                offset: [0.1, 0.2, 0.3, 0.4],
+            }]),
+        ),
+        morph(
+            "material",
+            ControlPanel::BottomRight,
+            MorphData::Material(vec![MaterialMorph {
+                material_index: 1,
+                formula: 1,
+                diffuse: [1.0; 4],
+                specular: [0.5; 3],
+                specular_factor: 2.0,
+                ambient: [0.25; 3],
+                edge_color: [0.0, 0.0, 0.0, 1.0],
+                edge_size: 3.0,
+                texture_factor: [1.0; 4],
+                sphere_texture_factor: [0.5; 4],
+                toon_texture_factor: [0.25; 4],
+            }]),
+        ),
+        morph(
+            "flip",
+            ControlPanel::BottomRight,
+            MorphData::Flip(vec![FlipMorph {
+                morph_index: 0,
+                morph_factor: 1.0,
+            }]),
+        ),
+        morph(
+            "impulse",
+            ControlPanel::BottomRight,
+            MorphData::Impulse(vec![ImpulseMorph {
+                rigid_index: 0,
+                is_local: true,
+                velocity: [1.0; 3],
+                torque: [0.0, 0.5, 0.0],
+            }]),
+        ),
+    ];
+
+    pmx.display_frames.display_frames = vec![
+        DisplayFrame {
+            name: "Root".into(),
+            name_en: "Root".into(),
+            is_special: true,
+            items: vec![DisplayFrameItem::BoneIndex(0)],
+        },
+        DisplayFrame {
+            name: "表情".into(),
+            name_en: "Exp".into(),
+            is_special: true,
+            items: vec![DisplayFrameItem::MorphIndex(0)],
+        },
+        DisplayFrame {
+            name: "腕".into(),
+            name_en: "arm".into(),
+            is_special: false,
+            items: vec![
+                DisplayFrameItem::BoneIndex(2),
+                DisplayFrameItem::BoneIndex(3),
+            ],
+        },
+    ];
+    pmx.rigid_bodies.rigid_bodies = vec![rigid_body("r0", 1), rigid_body("r1", 2)];
+    pmx.joints.joints = vec![joint("j", 0, 1)];
+    pmx.soft_bodies.soft_bodies = vec![soft_body("soft")];
+    pmx
+}
+
+/// [`sample`] with `vertex_count` vertices cycling through its five,
+/// and a triangle for every three of them
+#[cfg(feature = "rayon")]
+pub(crate) fn large(vertex_count: usize) -> Pmx {
+    let mut pmx = sample();
+    let vertices = pmx.vertices.clone();
+    pmx.vertices = Default::default();
+    pmx.vertices.ext_vec4s = vec![Vec::new(); vertices.ext_vec4s.len()];
+    for i in 0..vertex_count {
+        let v = i % 5;
+        let x = i as f32;
+        pmx.vertices.position3s.extend([x, 1.0, 2.0]);
+        pmx.vertices
+            .normal3s
+            .extend_from_slice(&vertices.normal3s[v * 3..v * 3 + 3]);
+        pmx.vertices
+            .uv2s
+            .extend_from_slice(&vertices.uv2s[v * 2..v * 2 + 2]);
+        for (e, from) in pmx.vertices.ext_vec4s.iter_mut().zip(&vertices.ext_vec4s) {
+            e.extend_from_slice(&from[v * 4..v * 4 + 4]);
+        }
+        pmx.vertices.skins.push(vertices.skins[v]);
+        pmx.vertices.edges.push(vertices.edges[v]);
+    }
+    let triangles = vertex_count as u32 / 3;
+    pmx.elements.element_indices = (0..triangles * 3).collect();
+    pmx.materials.materials[0].element_count = triangles * 3;
+    pmx.materials.materials[1].element_count = 0;
+    pmx
+}
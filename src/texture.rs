@@ -1,12 +1,17 @@
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::read_vec;
+use crate::kits::{impl_approx_eq, impl_collection, read_vec, skip_vec, vec_size};
+use crate::options::ReadOptions;
+use crate::TextureIndex;
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Eq, Hash)]
 pub struct Textures {
     pub textures: Vec<String>,
 }
@@ -15,11 +20,18 @@ impl Textures {
     pub fn count(&self) -> u32 {
         self.textures.len() as u32
     }
-    pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
+    pub fn read<R: Read>(
+        header: &Header,
+        options: &ReadOptions,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         Ok(Self {
-            textures: read_vec(read, |read| header.encoding.read(read))?,
+            textures: read_vec(read, |read| header.encoding.read_with(read, options))?,
         })
     }
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        skip_vec(read, |read| header.encoding.skip(read))
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         write.write_u32::<LittleEndian>(self.count())?;
         for i in &self.textures {
@@ -27,4 +39,127 @@ impl Textures {
         }
         Ok(())
     }
+    /// the size [`Textures::write`] writes
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        vec_size(&self.textures, |i| header.encoding.serialized_size(i))
+    }
+}
+
+impl Textures {
+    /// convert windows `\\` separators to `/`
+    pub fn normalize_paths(&mut self) {
+        for i in &mut self.textures {
+            if i.contains('\\') {
+                *i = i.replace('\\', "/");
+            }
+        }
+    }
+
+    /// the `(first, duplicate)` index pairs of paths that name the same file,
+    /// comparing with `/` separators and, if asked, ignoring ascii case
+    pub fn duplicates(&self, ignore_case: bool) -> Vec<(usize, usize)> {
+        let mut first = HashMap::new();
+        let mut pairs = Vec::new();
+        for (index, texture) in self.textures.iter().enumerate() {
+            let first = *first.entry(path_key(texture, ignore_case)).or_insert(index);
+            if first != index {
+                pairs.push((first, index));
+            }
+        }
+        pairs
+    }
+
+    /// every path with the file it names under `base_dir` if that exists,
+    /// joined with `/` separators and canonicalized, else `None`
+    pub fn resolve(&self, base_dir: &Path) -> Vec<(String, Option<PathBuf>)> {
+        self.textures
+            .iter()
+            .map(|i| {
+                let path = base_dir.join(path_key(i, false)).canonicalize().ok();
+                (i.clone(), path)
+            })
+            .collect()
+    }
+
+    /// collapse the paths that [`Textures::duplicates`] reports, keeping the first spelling,
+    /// and return the old to new index remap for [`crate::material::Materials::remap_textures`]
+    pub fn dedup(&mut self, ignore_case: bool) -> Vec<TextureIndex> {
+        self.dedup_by_key(|i| path_key(i, ignore_case))
+    }
+
+    /// keep the first texture of every key, returning the old to new index remap
+    pub(crate) fn dedup_by_key<K: Eq + Hash, F: FnMut(&str) -> K>(
+        &mut self,
+        mut key: F,
+    ) -> Vec<TextureIndex> {
+        let mut first = HashMap::new();
+        let mut remap = Vec::with_capacity(self.textures.len());
+        let mut textures = Vec::new();
+        for texture in self.textures.drain(..) {
+            let next = textures.len() as TextureIndex;
+            let index = *first.entry(key(&texture)).or_insert(next);
+            if index == next {
+                textures.push(texture);
+            }
+            remap.push(index);
+        }
+        self.textures = textures;
+        remap
+    }
+}
+
+impl_collection!(Textures, textures, String);
+
+fn path_key(path: &str, ignore_case: bool) -> String {
+    let path = path.replace('\\', "/");
+    if ignore_case {
+        path.to_ascii_lowercase()
+    } else {
+        path
+    }
+}
+
+impl_approx_eq!(Textures { textures });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn textures(paths: &[&str]) -> Textures {
+        Textures {
+            textures: paths.iter().map(|&i| i.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn duplicates_match_across_separators_and_optionally_case() {
+        let textures = textures(&["tex\\Body.png", "tex/body.png", "tex/Body.png", "face.png"]);
+        assert_eq!(textures.duplicates(false), [(0, 2)]);
+        assert_eq!(textures.duplicates(true), [(0, 1), (0, 2)]);
+
+        let mut deduped = textures.clone();
+        assert_eq!(deduped.dedup(true), [0, 0, 0, 1]);
+        assert_eq!(deduped.textures, ["tex\\Body.png", "face.png"]);
+        let mut deduped = textures.clone();
+        assert_eq!(deduped.dedup(false), [0, 1, 0, 2]);
+        assert_eq!(deduped.count(), 3);
+    }
+
+    #[test]
+    fn resolve_finds_the_files_that_exist() {
+        let dir = std::env::temp_dir().join(format!("pmx_parser_resolve_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("tex")).unwrap();
+        std::fs::write(dir.join("tex").join("body.png"), b"png").unwrap();
+
+        let resolved = textures(&["tex\\body.png", "missing.png"]).resolve(&dir);
+        let body = dir.join("tex").join("body.png").canonicalize().unwrap();
+        assert_eq!(
+            resolved,
+            [
+                ("tex\\body.png".to_owned(), Some(body)),
+                ("missing.png".to_owned(), None)
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,12 +1,17 @@
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use crate::BoneIndex;
 
+use crate::approx::ApproxEq;
 use crate::error::PmxError;
 use crate::header::Header;
-use crate::kits::{read_f32x3, write_f32x3};
+use crate::kits::{
+    flip_z, grid_cell, impl_approx_eq, read_f32s, read_f32x3, read_f32x4, sanitize, skip,
+    write_f32x3, Affine,
+};
+use crate::{BoneIndex, VertexIndex};
 
 #[derive(Default, Clone, PartialEq)]
 pub struct Vertices {
@@ -31,8 +36,27 @@ impl Vertices {
         (self.position3s.len() / 3) as u32
     }
 
+    /// the number of vertices, vertices are stored as parallel arrays so there is no slice access
+    pub fn len(&self) -> usize {
+        self.position3s.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position3s.is_empty()
+    }
+
+    /// read the vertex section, see [`Vertices::read_parallel`] for a section held in memory
     pub fn read<R: Read>(header: &Header, read: &mut R) -> Result<Self, PmxError> {
         let count = read.read_u32::<LittleEndian>()? as usize;
+        Self::read_count(header, count, read)
+    }
+
+    /// read `count` vertices without the leading count
+    pub(crate) fn read_count<R: Read>(
+        header: &Header,
+        count: usize,
+        read: &mut R,
+    ) -> Result<Self, PmxError> {
         let mut position3s = Vec::with_capacity(count * 3);
         let mut normal3s = Vec::with_capacity(count * 3);
         let mut uv2s = Vec::with_capacity(count * 2);
@@ -44,19 +68,13 @@ impl Vertices {
         let mut edges = Vec::with_capacity(count);
 
         for _ in 0..count {
-            for _ in 0..3 {
-                position3s.push(read.read_f32::<LittleEndian>()?);
-            }
-            for _ in 0..3 {
-                normal3s.push(read.read_f32::<LittleEndian>()?);
-            }
-            for _ in 0..2 {
-                uv2s.push(read.read_f32::<LittleEndian>()?);
-            }
+            // position, normal and uv are contiguous
+            let block: [f32; 8] = read_f32s(read)?;
+            position3s.extend_from_slice(&block[0..3]);
+            normal3s.extend_from_slice(&block[3..6]);
+            uv2s.extend_from_slice(&block[6..8]);
             for e in &mut ext_vec4s {
-                for _ in 0..4 {
-                    e.push(read.read_f32::<LittleEndian>()?);
-                }
+                e.extend(read_f32x4(read)?);
             }
             skins.push(Skin::read(header, read)?);
             edges.push(read.read_f32::<LittleEndian>()?);
@@ -72,9 +90,50 @@ impl Vertices {
         })
     }
 
+    /// skip the vertex section, returning the vertex count
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u32, PmxError> {
+        let count = read.read_u32::<LittleEndian>()?;
+        for _ in 0..count {
+            Self::skip_one(header, read)?;
+        }
+        Ok(count)
+    }
+
+    /// skip one vertex, returning the kind of its skin
+    pub(crate) fn skip_one<R: Read>(header: &Header, read: &mut R) -> Result<u8, PmxError> {
+        // position, normal, uv and the extra uvs
+        skip(read, (3 + 3 + 2 + 4 * header.vertex_ext_vec4 as u64) * 4)?;
+        let kind = Skin::skip(header, read)?;
+        skip(read, 4)?;
+        Ok(kind)
+    }
+
+    /// move the vertices of `other` to the end of these,
+    /// the side with fewer extra uvs gets zero filled ones
+    pub(crate) fn append(&mut self, mut other: Self) {
+        let (count, other_count) = (self.count() as usize, other.count() as usize);
+        let ext_vec4 = self.ext_vec4s.len().max(other.ext_vec4s.len());
+        self.ext_vec4s
+            .resize_with(ext_vec4, || vec![0.0; count * 4]);
+        other
+            .ext_vec4s
+            .resize_with(ext_vec4, || vec![0.0; other_count * 4]);
+        self.position3s.append(&mut other.position3s);
+        self.normal3s.append(&mut other.normal3s);
+        self.uv2s.append(&mut other.uv2s);
+        for (e, other) in self.ext_vec4s.iter_mut().zip(&mut other.ext_vec4s) {
+            e.append(other);
+        }
+        self.skins.append(&mut other.skins);
+        self.edges.append(&mut other.edges);
+    }
+
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         let count = self.count() as usize;
-        let ext_vec4s = &self.ext_vec4s[..header.vertex_ext_vec4 as usize];
+        let ext_vec4s = self
+            .ext_vec4s
+            .get(..header.vertex_ext_vec4 as usize)
+            .ok_or(PmxError::VertexCountError)?;
         if self.position3s.len() != count * 3
             || self.normal3s.len() != count * 3
             || self.uv2s.len() != count * 2
@@ -95,13 +154,298 @@ impl Vertices {
             for i in 0..2 {
                 write.write_f32::<LittleEndian>(self.uv2s[index * 2 + i])?;
             }
+            for e in ext_vec4s {
+                for i in 0..4 {
+                    write.write_f32::<LittleEndian>(e[index * 4 + i])?;
+                }
+            }
             self.skins[index].write(header, write)?;
-            write.write_f32::<LittleEndian>(self.uv2s[index])?;
+            write.write_f32::<LittleEndian>(self.edges[index])?;
         }
         Ok(())
     }
+
+    /// the size [`Vertices::write`] writes, for vertex arrays of the same length
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        // position, normal, uv, the extra uvs and the edge
+        let fixed = (3 + 3 + 2 + 4 * header.vertex_ext_vec4 as usize + 1) * 4;
+        let skins: usize = self.skins.iter().map(|i| i.serialized_size(header)).sum();
+        4 + self.len() * fixed + skins
+    }
+
+    /// the skins of the vertices, to rebind them in place with [`Skin::map_bone_indices`]
+    pub fn skins_mut(&mut self) -> &mut [Skin] {
+        &mut self.skins
+    }
+
+    /// set the edge scale of every vertex
+    pub fn set_all_edges(&mut self, value: f32) {
+        self.edges.fill(value);
+    }
+
+    /// flip the v coordinate of every uv to `1.0 - v`, for a texture origin at the bottom left
+    pub fn flip_v(&mut self) {
+        for i in self.uv2s.chunks_exact_mut(2) {
+            i[1] = 1.0 - i[1];
+        }
+    }
+
+    /// flip the second component of every additional uv to `1.0 - y` like [`Vertices::flip_v`],
+    /// for additional uvs holding texture coordinates in their first two components
+    pub fn flip_ext_v(&mut self) {
+        for e in &mut self.ext_vec4s {
+            for i in e.chunks_exact_mut(4) {
+                i[1] = 1.0 - i[1];
+            }
+        }
+    }
+
+    /// the smallest and largest edge scale, NaN ones being skipped,
+    /// `None` without an edge scale that is a number
+    pub fn edge_range(&self) -> Option<(f32, f32)> {
+        self.edges
+            .iter()
+            .copied()
+            .filter(|i| !i.is_nan())
+            .fold(None, |range, i| match range {
+                Some((min, max)) => Some((i.min(min), i.max(max))),
+                None => Some((i, i)),
+            })
+    }
+
+    /// the largest [`Skin::bone_count`] of the vertices, 0 without vertices
+    pub fn max_influences(&self) -> usize {
+        self.skins.iter().map(Skin::bone_count).max().unwrap_or(0)
+    }
+
+    pub fn uses_sdef(&self) -> bool {
+        self.skins.iter().any(|i| matches!(i, Skin::SDEF { .. }))
+    }
+
+    pub fn uses_qdef(&self) -> bool {
+        self.skins.iter().any(|i| matches!(i, Skin::QDEF { .. }))
+    }
+
+    /// the indices of the vertices grouped by uv, every vertex being in exactly one group.
+    ///
+    /// a vertex joins the first group whose first vertex has both uv coordinates within
+    /// `epsilon` of its own, so groups are in the order of their first vertex and a group
+    /// is not widened by a chain of close uvs. vertices with a NaN uv are alone in a group,
+    /// an `epsilon` of 0 groups the exactly equal uvs
+    pub fn uv_equal_groups(&self, epsilon: f32) -> Vec<Vec<u32>> {
+        let cell = |uv: f32| grid_cell(uv, epsilon);
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        let mut groups: Vec<Vec<u32>> = Vec::new();
+        let mut firsts: Vec<[f32; 2]> = Vec::new();
+        for (index, uv) in self.uv2s.chunks_exact(2).enumerate() {
+            let (u, v) = (cell(uv[0]), cell(uv[1]));
+            let group = (-1..=1)
+                .flat_map(|du| (-1..=1).map(move |dv| (u.saturating_add(du), v.saturating_add(dv))))
+                .filter_map(|key| cells.get(&key))
+                .flatten()
+                .copied()
+                .filter(|&group| {
+                    let first = firsts[group];
+                    (first[0] - uv[0]).abs() <= epsilon && (first[1] - uv[1]).abs() <= epsilon
+                })
+                .min();
+            match group {
+                Some(group) => groups[group].push(index as u32),
+                None => {
+                    cells.entry((u, v)).or_default().push(groups.len());
+                    groups.push(vec![index as u32]);
+                    firsts.push([uv[0], uv[1]]);
+                }
+            }
+        }
+        groups
+    }
+
+    /// the old to new index remap merging every vertex into the first one it matches,
+    /// see [`crate::pmx::Pmx::weld_vertices`], `same` adding a condition of the caller
+    pub(crate) fn weld_remap(
+        &self,
+        position_epsilon: f32,
+        normal_epsilon: f32,
+        same: impl Fn(usize, usize) -> bool,
+    ) -> Vec<VertexIndex> {
+        let cell = |i: usize| {
+            let p = &self.position3s[3 * i..3 * i + 3];
+            [0, 1, 2].map(|axis| grid_cell(p[axis], position_epsilon))
+        };
+        let near = |a: &[f32], b: &[f32], epsilon: f32| a.approx_eq(b, epsilon);
+        let matches = |a: usize, b: usize| {
+            let range = |size: usize, i: usize| size * i..size * (i + 1);
+            let pair = |items: &[f32], size: usize, epsilon: f32| {
+                near(
+                    items.get(range(size, a)).unwrap_or_default(),
+                    items.get(range(size, b)).unwrap_or_default(),
+                    epsilon,
+                )
+            };
+            pair(&self.position3s, 3, position_epsilon)
+                && pair(&self.normal3s, 3, normal_epsilon)
+                && pair(&self.uv2s, 2, position_epsilon)
+                && self.ext_vec4s.iter().all(|i| pair(i, 4, position_epsilon))
+                && pair(&self.edges, 1, position_epsilon)
+                && self
+                    .skins
+                    .get(a)
+                    .approx_eq(&self.skins.get(b), position_epsilon)
+                && same(a, b)
+        };
+        let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.len());
+        let mut kept: Vec<usize> = Vec::new();
+        for index in 0..self.len() {
+            let [x, y, z] = cell(index);
+            let mut first = None;
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let key = [
+                            x.saturating_add(dx),
+                            y.saturating_add(dy),
+                            z.saturating_add(dz),
+                        ];
+                        for &target in cells.get(&key).into_iter().flatten() {
+                            if first.is_none_or(|i| target < i) && matches(kept[target], index) {
+                                first = Some(target);
+                            }
+                        }
+                    }
+                }
+            }
+            match first {
+                Some(target) => remap.push(target as VertexIndex),
+                None => {
+                    cells.entry([x, y, z]).or_default().push(kept.len());
+                    remap.push(kept.len() as VertexIndex);
+                    kept.push(index);
+                }
+            }
+        }
+        remap
+    }
+
+    /// keep the vertices that `keep` holds true for
+    pub(crate) fn retain(&mut self, keep: &[bool]) {
+        fn retain_strided<T>(items: &mut Vec<T>, size: usize, keep: &[bool]) {
+            let mut index = 0;
+            items.retain(|_| {
+                index += 1;
+                keep.get((index - 1) / size).copied().unwrap_or(true)
+            });
+        }
+        retain_strided(&mut self.position3s, 3, keep);
+        retain_strided(&mut self.normal3s, 3, keep);
+        retain_strided(&mut self.uv2s, 2, keep);
+        for i in &mut self.ext_vec4s {
+            retain_strided(i, 4, keep);
+        }
+        retain_strided(&mut self.skins, 1, keep);
+        retain_strided(&mut self.edges, 1, keep);
+    }
+
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        for skin in &mut self.skins {
+            skin.for_each_bone_index_mut(f);
+        }
+    }
+
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        (0..self.len())
+            .map(|i| self.sanitize_vertex_floats(i))
+            .sum()
+    }
+
+    pub(crate) fn sanitize_vertex_floats(&mut self, index: usize) -> usize {
+        let range = |size: usize| size * index..size * (index + 1);
+        let mut count = sanitize(self.position3s.get_mut(range(3)).into_iter().flatten())
+            + sanitize(self.normal3s.get_mut(range(3)).into_iter().flatten())
+            + sanitize(self.uv2s.get_mut(range(2)).into_iter().flatten())
+            + sanitize(
+                self.ext_vec4s
+                    .iter_mut()
+                    .filter_map(|i| i.get_mut(range(4)))
+                    .flatten(),
+            )
+            + sanitize(self.edges.get_mut(index));
+        if let Some(skin) = self.skins.get_mut(index) {
+            count += skin.sanitize_floats();
+        }
+        count
+    }
+
+    /// whether every float [`Vertices::sanitize_vertex_floats`] would replace is finite
+    pub(crate) fn is_vertex_finite(&self, index: usize) -> bool {
+        let range = |size: usize| size * index..size * (index + 1);
+        let finite = |values: Option<&[f32]>| values.into_iter().flatten().all(|i| i.is_finite());
+        finite(self.position3s.get(range(3)))
+            && finite(self.normal3s.get(range(3)))
+            && finite(self.uv2s.get(range(2)))
+            && self.ext_vec4s.iter().all(|i| finite(i.get(range(4))))
+            && self.edges.get(index).is_none_or(|i| i.is_finite())
+            && self
+                .skins
+                .get(index)
+                .copied()
+                .is_none_or(|mut skin| skin.sanitize_floats() == 0)
+    }
+
+    pub(crate) fn flip_z(&mut self) {
+        for i in self.position3s.chunks_exact_mut(3) {
+            i[2] = -i[2];
+        }
+        for i in self.normal3s.chunks_exact_mut(3) {
+            i[2] = -i[2];
+        }
+        for i in &mut self.skins {
+            if let Skin::SDEF {
+                sdef_c,
+                sdef_r0,
+                sdef_r1,
+                ..
+            } = i
+            {
+                flip_z(sdef_c);
+                flip_z(sdef_r0);
+                flip_z(sdef_r1);
+            }
+        }
+    }
+
+    pub(crate) fn transform(&mut self, transform: &Affine) {
+        for i in self.position3s.chunks_exact_mut(3) {
+            let mut position = [i[0], i[1], i[2]];
+            transform.point(&mut position);
+            i.copy_from_slice(&position);
+        }
+        for i in self.normal3s.chunks_exact_mut(3) {
+            let mut normal = [i[0], i[1], i[2]];
+            transform.normal(&mut normal);
+            i.copy_from_slice(&normal);
+        }
+        for i in &mut self.skins {
+            if let Skin::SDEF {
+                sdef_c,
+                sdef_r0,
+                sdef_r1,
+                ..
+            } = i
+            {
+                transform.point(sdef_c);
+                transform.point(sdef_r0);
+                transform.point(sdef_r1);
+            }
+        }
+    }
 }
 
+/// the bone weights of a vertex.
+///
+/// bone indices are signed like every bone reference, `-1` being the "no bone" sentinel
+/// some exporters put in the unused slots, and it is kept through reading and writing.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Skin {
     /// a bone with weight 1.0
@@ -152,36 +496,37 @@ impl Skin {
         let t = read.read_u8()?;
         match t {
             0 => Ok(Skin::BDEF1 {
-                bone_index: header.bone_index.read(read)?,
+                bone_index: header.bone_index.read_i(read)?,
             }),
             1 => Ok(Skin::BDEF2 {
-                bone_index_1: header.bone_index.read(read)?,
-                bone_index_2: header.bone_index.read(read)?,
+                bone_index_1: header.bone_index.read_i(read)?,
+                bone_index_2: header.bone_index.read_i(read)?,
                 bone_weight_1: read.read_f32::<LittleEndian>()?,
             }),
             2 => Ok(Skin::BDEF4 {
-                bone_index_1: header.bone_index.read(read)?,
-                bone_index_2: header.bone_index.read(read)?,
-                bone_index_3: header.bone_index.read(read)?,
-                bone_index_4: header.bone_index.read(read)?,
+                bone_index_1: header.bone_index.read_i(read)?,
+                bone_index_2: header.bone_index.read_i(read)?,
+                bone_index_3: header.bone_index.read_i(read)?,
+                bone_index_4: header.bone_index.read_i(read)?,
                 bone_weight_1: read.read_f32::<LittleEndian>()?,
                 bone_weight_2: read.read_f32::<LittleEndian>()?,
                 bone_weight_3: read.read_f32::<LittleEndian>()?,
                 bone_weight_4: read.read_f32::<LittleEndian>()?,
             }),
             3 => Ok(Skin::SDEF {
-                bone_index_1: header.bone_index.read(read)?,
-                bone_index_2: header.bone_index.read(read)?,
+                bone_index_1: header.bone_index.read_i(read)?,
+                bone_index_2: header.bone_index.read_i(read)?,
                 bone_weight_1: read.read_f32::<LittleEndian>()?,
                 sdef_c: read_f32x3(read)?,
                 sdef_r0: read_f32x3(read)?,
                 sdef_r1: read_f32x3(read)?,
             }),
+            4 if !header.supports_qdef() => Err(PmxError::QdefVersionError),
             4 => Ok(Skin::QDEF {
-                bone_index_1: header.bone_index.read(read)?,
-                bone_index_2: header.bone_index.read(read)?,
-                bone_index_3: header.bone_index.read(read)?,
-                bone_index_4: header.bone_index.read(read)?,
+                bone_index_1: header.bone_index.read_i(read)?,
+                bone_index_2: header.bone_index.read_i(read)?,
+                bone_index_3: header.bone_index.read_i(read)?,
+                bone_index_4: header.bone_index.read_i(read)?,
                 bone_weight_1: read.read_f32::<LittleEndian>()?,
                 bone_weight_2: read.read_f32::<LittleEndian>()?,
                 bone_weight_3: read.read_f32::<LittleEndian>()?,
@@ -190,12 +535,195 @@ impl Skin {
             _ => Err(PmxError::SkinError),
         }
     }
+
+    /// the bone and weight pairs with the weights summing to 1.0.
+    ///
+    /// unused slots are `(-1, 0.0)`, four weights are divided by their sum,
+    /// and if they are all zero the whole weight goes to the first bone.
+    pub fn normalized_weights(&self) -> [(BoneIndex, f32); 4] {
+        const NONE: (BoneIndex, f32) = (-1, 0.0);
+        match *self {
+            Skin::BDEF1 { bone_index } => [(bone_index, 1.0), NONE, NONE, NONE],
+            Skin::BDEF2 {
+                bone_index_1,
+                bone_index_2,
+                bone_weight_1,
+            }
+            | Skin::SDEF {
+                bone_index_1,
+                bone_index_2,
+                bone_weight_1,
+                ..
+            } => [
+                (bone_index_1, bone_weight_1),
+                (bone_index_2, 1.0 - bone_weight_1),
+                NONE,
+                NONE,
+            ],
+            Skin::BDEF4 {
+                bone_index_1,
+                bone_index_2,
+                bone_index_3,
+                bone_index_4,
+                bone_weight_1,
+                bone_weight_2,
+                bone_weight_3,
+                bone_weight_4,
+            }
+            | Skin::QDEF {
+                bone_index_1,
+                bone_index_2,
+                bone_index_3,
+                bone_index_4,
+                bone_weight_1,
+                bone_weight_2,
+                bone_weight_3,
+                bone_weight_4,
+            } => {
+                let sum = bone_weight_1 + bone_weight_2 + bone_weight_3 + bone_weight_4;
+                if sum == 0.0 {
+                    return [
+                        (bone_index_1, 1.0),
+                        (bone_index_2, 0.0),
+                        (bone_index_3, 0.0),
+                        (bone_index_4, 0.0),
+                    ];
+                }
+                [
+                    (bone_index_1, bone_weight_1 / sum),
+                    (bone_index_2, bone_weight_2 / sum),
+                    (bone_index_3, bone_weight_3 / sum),
+                    (bone_index_4, bone_weight_4 / sum),
+                ]
+            }
+        }
+    }
+
+    /// the number of bone slots of the skin kind, counting the ones with a zero weight
+    pub fn bone_count(&self) -> usize {
+        match self {
+            Skin::BDEF1 { .. } => 1,
+            Skin::BDEF2 { .. } | Skin::SDEF { .. } => 2,
+            Skin::BDEF4 { .. } | Skin::QDEF { .. } => 4,
+        }
+    }
+
+    /// rewrite every bone index of the skin through `f`, including the ones with a zero
+    /// weight and the `-1` sentinel of unused slots, the weights being left as they are
+    pub fn map_bone_indices(&mut self, mut f: impl FnMut(BoneIndex) -> BoneIndex) {
+        self.for_each_bone_index_mut(&mut |i| *i = f(*i));
+    }
+
+    /// every bone index of the skin, including the ones with a zero weight
+    pub(crate) fn for_each_bone_index_mut(&mut self, f: &mut impl FnMut(&mut BoneIndex)) {
+        match self {
+            Skin::BDEF1 { bone_index } => f(bone_index),
+            Skin::BDEF2 {
+                bone_index_1,
+                bone_index_2,
+                ..
+            }
+            | Skin::SDEF {
+                bone_index_1,
+                bone_index_2,
+                ..
+            } => {
+                f(bone_index_1);
+                f(bone_index_2);
+            }
+            Skin::BDEF4 {
+                bone_index_1,
+                bone_index_2,
+                bone_index_3,
+                bone_index_4,
+                ..
+            }
+            | Skin::QDEF {
+                bone_index_1,
+                bone_index_2,
+                bone_index_3,
+                bone_index_4,
+                ..
+            } => {
+                f(bone_index_1);
+                f(bone_index_2);
+                f(bone_index_3);
+                f(bone_index_4);
+            }
+        }
+    }
+
+    /// replace the NaN and infinite weights and sdef parameters with 0.0,
+    /// returning how many were replaced
+    pub(crate) fn sanitize_floats(&mut self) -> usize {
+        match self {
+            Skin::BDEF1 { .. } => 0,
+            Skin::BDEF2 { bone_weight_1, .. } => sanitize([bone_weight_1]),
+            Skin::BDEF4 {
+                bone_weight_1,
+                bone_weight_2,
+                bone_weight_3,
+                bone_weight_4,
+                ..
+            }
+            | Skin::QDEF {
+                bone_weight_1,
+                bone_weight_2,
+                bone_weight_3,
+                bone_weight_4,
+                ..
+            } => sanitize([bone_weight_1, bone_weight_2, bone_weight_3, bone_weight_4]),
+            Skin::SDEF {
+                bone_weight_1,
+                sdef_c,
+                sdef_r0,
+                sdef_r1,
+                ..
+            } => sanitize(
+                std::iter::once(bone_weight_1)
+                    .chain(sdef_c)
+                    .chain(sdef_r0)
+                    .chain(sdef_r1),
+            ),
+        }
+    }
+
+    /// skip one skin, returning its kind
+    pub(crate) fn skip<R: Read>(header: &Header, read: &mut R) -> Result<u8, PmxError> {
+        let kind = read.read_u8()?;
+        skip(read, Self::data_len(header, kind)?)?;
+        Ok(kind)
+    }
+
+    /// the size of the skin of `kind` after its kind byte
+    pub(crate) fn data_len(header: &Header, kind: u8) -> Result<u64, PmxError> {
+        let bone = header.bone_index.bytes();
+        Ok(match kind {
+            0 => bone,
+            1 => bone * 2 + 4,
+            2 => bone * 4 + 4 * 4,
+            3 => bone * 2 + 4 + 3 * 3 * 4,
+            4 if !header.supports_qdef() => return Err(PmxError::QdefVersionError),
+            4 => bone * 4 + 4 * 4,
+            _ => return Err(PmxError::SkinError),
+        })
+    }
+    /// the size [`Skin::write`] writes, its kind byte included
+    pub(crate) fn serialized_size(&self, header: &Header) -> usize {
+        let bone = header.bone_index.bytes() as usize;
+        1 + match self {
+            Skin::BDEF1 { .. } => bone,
+            Skin::BDEF2 { .. } => bone * 2 + 4,
+            Skin::BDEF4 { .. } | Skin::QDEF { .. } => bone * 4 + 4 * 4,
+            Skin::SDEF { .. } => bone * 2 + 4 + 3 * 3 * 4,
+        }
+    }
     pub fn write<W: Write>(&self, header: &Header, write: &mut W) -> Result<(), PmxError> {
         let bone_index_size = header.bone_index;
         match *self {
             Skin::BDEF1 { bone_index } => {
                 write.write_u8(0)?;
-                bone_index_size.write(write, bone_index)?;
+                bone_index_size.write_i(write, bone_index)?;
             }
             Skin::BDEF2 {
                 bone_index_1,
@@ -203,8 +731,8 @@ impl Skin {
                 bone_weight_1,
             } => {
                 write.write_u8(1)?;
-                bone_index_size.write(write, bone_index_1)?;
-                bone_index_size.write(write, bone_index_2)?;
+                bone_index_size.write_i(write, bone_index_1)?;
+                bone_index_size.write_i(write, bone_index_2)?;
                 write.write_f32::<LittleEndian>(bone_weight_1)?;
             }
             Skin::BDEF4 {
@@ -218,10 +746,10 @@ impl Skin {
                 bone_weight_4,
             } => {
                 write.write_u8(2)?;
-                bone_index_size.write(write, bone_index_1)?;
-                bone_index_size.write(write, bone_index_2)?;
-                bone_index_size.write(write, bone_index_3)?;
-                bone_index_size.write(write, bone_index_4)?;
+                bone_index_size.write_i(write, bone_index_1)?;
+                bone_index_size.write_i(write, bone_index_2)?;
+                bone_index_size.write_i(write, bone_index_3)?;
+                bone_index_size.write_i(write, bone_index_4)?;
                 write.write_f32::<LittleEndian>(bone_weight_1)?;
                 write.write_f32::<LittleEndian>(bone_weight_2)?;
                 write.write_f32::<LittleEndian>(bone_weight_3)?;
@@ -236,8 +764,8 @@ impl Skin {
                 sdef_r1,
             } => {
                 write.write_u8(3)?;
-                bone_index_size.write(write, bone_index_1)?;
-                bone_index_size.write(write, bone_index_2)?;
+                bone_index_size.write_i(write, bone_index_1)?;
+                bone_index_size.write_i(write, bone_index_2)?;
                 write.write_f32::<LittleEndian>(bone_weight_1)?;
                 write_f32x3(write, sdef_c)?;
                 write_f32x3(write, sdef_r0)?;
@@ -253,11 +781,14 @@ impl Skin {
                 bone_weight_3,
                 bone_weight_4,
             } => {
+                if !header.supports_qdef() {
+                    return Err(PmxError::QdefVersionError);
+                }
                 write.write_u8(4)?;
-                bone_index_size.write(write, bone_index_1)?;
-                bone_index_size.write(write, bone_index_2)?;
-                bone_index_size.write(write, bone_index_3)?;
-                bone_index_size.write(write, bone_index_4)?;
+                bone_index_size.write_i(write, bone_index_1)?;
+                bone_index_size.write_i(write, bone_index_2)?;
+                bone_index_size.write_i(write, bone_index_3)?;
+                bone_index_size.write_i(write, bone_index_4)?;
                 write.write_f32::<LittleEndian>(bone_weight_1)?;
                 write.write_f32::<LittleEndian>(bone_weight_2)?;
                 write.write_f32::<LittleEndian>(bone_weight_3)?;
@@ -267,3 +798,354 @@ impl Skin {
         Ok(())
     }
 }
+
+impl_approx_eq!(Vertices {
+    position3s,
+    normal3s,
+    uv2s,
+    ext_vec4s,
+    skins,
+    edges,
+});
+
+impl ApproxEq for Skin {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        match (self, other) {
+            (Self::BDEF1 { bone_index: a }, Self::BDEF1 { bone_index: b }) => a == b,
+            (
+                Self::BDEF2 {
+                    bone_index_1: a1,
+                    bone_index_2: a2,
+                    bone_weight_1: aw,
+                },
+                Self::BDEF2 {
+                    bone_index_1: b1,
+                    bone_index_2: b2,
+                    bone_weight_1: bw,
+                },
+            ) => (a1, a2) == (b1, b2) && aw.approx_eq(bw, epsilon),
+            (
+                Self::BDEF4 {
+                    bone_index_1: a1,
+                    bone_index_2: a2,
+                    bone_index_3: a3,
+                    bone_index_4: a4,
+                    bone_weight_1: aw1,
+                    bone_weight_2: aw2,
+                    bone_weight_3: aw3,
+                    bone_weight_4: aw4,
+                },
+                Self::BDEF4 {
+                    bone_index_1: b1,
+                    bone_index_2: b2,
+                    bone_index_3: b3,
+                    bone_index_4: b4,
+                    bone_weight_1: bw1,
+                    bone_weight_2: bw2,
+                    bone_weight_3: bw3,
+                    bone_weight_4: bw4,
+                },
+            )
+            | (
+                Self::QDEF {
+                    bone_index_1: a1,
+                    bone_index_2: a2,
+                    bone_index_3: a3,
+                    bone_index_4: a4,
+                    bone_weight_1: aw1,
+                    bone_weight_2: aw2,
+                    bone_weight_3: aw3,
+                    bone_weight_4: aw4,
+                },
+                Self::QDEF {
+                    bone_index_1: b1,
+                    bone_index_2: b2,
+                    bone_index_3: b3,
+                    bone_index_4: b4,
+                    bone_weight_1: bw1,
+                    bone_weight_2: bw2,
+                    bone_weight_3: bw3,
+                    bone_weight_4: bw4,
+                },
+            ) => {
+                (a1, a2, a3, a4) == (b1, b2, b3, b4)
+                    && [aw1, aw2, aw3, aw4].approx_eq(&[bw1, bw2, bw3, bw4], epsilon)
+            }
+            (
+                Self::SDEF {
+                    bone_index_1: a1,
+                    bone_index_2: a2,
+                    bone_weight_1: aw,
+                    sdef_c: ac,
+                    sdef_r0: ar0,
+                    sdef_r1: ar1,
+                },
+                Self::SDEF {
+                    bone_index_1: b1,
+                    bone_index_2: b2,
+                    bone_weight_1: bw,
+                    sdef_c: bc,
+                    sdef_r0: br0,
+                    sdef_r1: br1,
+                },
+            ) => {
+                (a1, a2) == (b1, b2)
+                    && aw.approx_eq(bw, epsilon)
+                    && [ac, ar0, ar1].approx_eq(&[bc, br0, br1], epsilon)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::IndexSize;
+
+    fn with_uvs(uvs: &[[f32; 2]]) -> Vertices {
+        Vertices {
+            uv2s: uvs.iter().flatten().copied().collect(),
+            ..Vertices::default()
+        }
+    }
+
+    #[test]
+    fn uv_equal_groups_buckets_identical_and_near_uvs() {
+        let vertices = with_uvs(&[
+            [0.5, 0.5],
+            [0.25, 0.75],
+            [0.5, 0.5],
+            [0.5001, 0.4999],
+            [0.25, 0.76],
+            [f32::NAN, 0.0],
+            [-0.0, 1.0],
+            [0.0, 1.0],
+        ]);
+        assert_eq!(
+            vertices.uv_equal_groups(0.001),
+            [vec![0, 2, 3], vec![1], vec![4], vec![5], vec![6, 7]]
+        );
+        assert_eq!(
+            vertices.uv_equal_groups(0.0),
+            [vec![0, 2], vec![1], vec![3], vec![4], vec![5], vec![6, 7]]
+        );
+        assert_eq!(
+            vertices.uv_equal_groups(0.02),
+            [vec![0, 2, 3], vec![1, 4], vec![5], vec![6, 7]]
+        );
+    }
+
+    #[test]
+    fn uv_equal_groups_with_zero_epsilon_keeps_distinct_uvs_apart() {
+        let uvs: Vec<[f32; 2]> = (0..1000).map(|i| [i as f32 / 1000.0, 0.5]).collect();
+        let groups = with_uvs(&uvs).uv_equal_groups(0.0);
+        assert_eq!(groups.len(), 1000);
+    }
+
+    #[test]
+    fn qdef_skins_fail_to_write_as_version_2_0() {
+        let skin = Skin::QDEF {
+            bone_index_1: 0,
+            bone_index_2: 1,
+            bone_index_3: 2,
+            bone_index_4: -1,
+            bone_weight_1: 0.5,
+            bone_weight_2: 0.25,
+            bone_weight_3: 0.25,
+            bone_weight_4: 0.0,
+        };
+        let header = Header::builder().version(2.0).build();
+        let mut bytes = Vec::new();
+        assert!(matches!(
+            skin.write(&header, &mut bytes),
+            Err(PmxError::QdefVersionError)
+        ));
+        assert!(bytes.is_empty());
+
+        let header = Header::builder().version(2.1).build();
+        skin.write(&header, &mut bytes).unwrap();
+        assert!(matches!(
+            Skin::read(
+                &Header::builder().version(2.0).build(),
+                &mut bytes.as_slice()
+            ),
+            Err(PmxError::QdefVersionError)
+        ));
+        assert_eq!(Skin::read(&header, &mut bytes.as_slice()).unwrap(), skin);
+    }
+
+    #[test]
+    fn none_bone_index_round_trips_at_every_width() {
+        let vertices = Vertices {
+            position3s: vec![0.0; 6],
+            normal3s: vec![0.0; 6],
+            uv2s: vec![0.0; 4],
+            ext_vec4s: Vec::new(),
+            skins: vec![
+                Skin::BDEF1 { bone_index: -1 },
+                Skin::BDEF2 {
+                    bone_index_1: 0,
+                    bone_index_2: -1,
+                    bone_weight_1: 1.0,
+                },
+            ],
+            edges: vec![1.0; 2],
+        };
+        for (size, none) in [
+            (IndexSize::Bit8, vec![0xFF]),
+            (IndexSize::Bit16, vec![0xFF; 2]),
+            (IndexSize::Bit32, vec![0xFF; 4]),
+        ] {
+            let header = Header::builder().index_size(size).build();
+            let mut bytes = Vec::new();
+            vertices.write(&header, &mut bytes).unwrap();
+            // the count, then position, normal and uv before the skin kind
+            let skin = 4 + 8 * 4 + 1;
+            assert_eq!(bytes[skin..skin + none.len()], none);
+
+            let mut read = bytes.as_slice();
+            let back = Vertices::read(&header, &mut read).unwrap();
+            assert!(read.is_empty());
+            assert!(back == vertices, "{size:?}");
+        }
+    }
+
+    #[test]
+    fn normalized_weights_sum_to_one() {
+        let skins = crate::testing::sample().vertices.skins;
+        assert_eq!(
+            skins[0].normalized_weights(),
+            [(0, 1.0), (-1, 0.0), (-1, 0.0), (-1, 0.0)]
+        );
+        assert_eq!(skins[1].normalized_weights()[..2], [(0, 0.3), (1, 0.7)]);
+        assert_eq!(skins[3].normalized_weights()[..2], [(1, 0.5), (2, 0.5)]);
+        assert_eq!(skins[4].normalized_weights().map(|i| i.1), [0.25; 4]);
+
+        let heavy = Skin::BDEF4 {
+            bone_index_1: 0,
+            bone_index_2: 1,
+            bone_index_3: 2,
+            bone_index_4: 3,
+            bone_weight_1: 1.0,
+            bone_weight_2: 1.0,
+            bone_weight_3: 2.0,
+            bone_weight_4: 0.0,
+        };
+        assert_eq!(
+            heavy.normalized_weights().map(|i| i.1),
+            [0.25, 0.25, 0.5, 0.0]
+        );
+
+        let unweighted = Skin::QDEF {
+            bone_index_1: 4,
+            bone_index_2: 5,
+            bone_index_3: 6,
+            bone_index_4: 7,
+            bone_weight_1: 0.0,
+            bone_weight_2: 0.0,
+            bone_weight_3: 0.0,
+            bone_weight_4: 0.0,
+        };
+        assert_eq!(
+            unweighted.normalized_weights(),
+            [(4, 1.0), (5, 0.0), (6, 0.0), (7, 0.0)]
+        );
+    }
+
+    #[test]
+    fn mixed_skins_report_their_influences() {
+        let mut vertices = crate::testing::sample().vertices;
+        let counts: Vec<usize> = vertices.skins.iter().map(Skin::bone_count).collect();
+        assert_eq!(counts, [1, 2, 4, 2, 4]);
+        assert_eq!(vertices.max_influences(), 4);
+        assert!(vertices.uses_sdef() && vertices.uses_qdef());
+
+        vertices.skins.truncate(2);
+        assert_eq!(vertices.max_influences(), 2);
+        assert!(!vertices.uses_sdef() && !vertices.uses_qdef());
+        assert_eq!(Vertices::default().max_influences(), 0);
+    }
+
+    #[test]
+    fn a_file_cut_mid_vertex_is_an_unexpected_eof() {
+        let pmx = crate::testing::sample();
+        let header = Header::from_best(2.1, &pmx);
+        let mut bytes = Vec::new();
+        pmx.vertices.write(&header, &mut bytes).unwrap();
+        for cut in [2, 4 + 12, bytes.len() / 2, bytes.len() - 1] {
+            let e = Vertices::read(&header, &mut &bytes[..cut]).unwrap_err();
+            assert!(matches!(e, PmxError::UnexpectedEof), "{cut}: {e:?}");
+        }
+        assert!(Vertices::read(&header, &mut bytes.as_slice()).is_ok());
+
+        let other = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(PmxError::from(other), PmxError::Io(_)));
+    }
+
+    #[test]
+    fn bone_indices_are_remapped_on_every_skin_kind() {
+        let mut vertices = crate::testing::sample().vertices;
+        let weights: Vec<_> = vertices
+            .skins
+            .iter()
+            .map(Skin::normalized_weights)
+            .collect();
+        for skin in vertices.skins_mut() {
+            skin.map_bone_indices(|i| i + 10);
+        }
+        let indices: Vec<Vec<BoneIndex>> = vertices
+            .skins
+            .iter()
+            .map(|skin| {
+                let mut indices = Vec::new();
+                skin.clone()
+                    .for_each_bone_index_mut(&mut |i| indices.push(*i));
+                indices
+            })
+            .collect();
+        assert_eq!(
+            indices,
+            [
+                vec![10],
+                vec![10, 11],
+                vec![10, 11, 12, 13],
+                vec![11, 12],
+                vec![10, 11, 12, 13],
+            ]
+        );
+        for (skin, weights) in vertices.skins.iter().zip(weights) {
+            assert_eq!(skin.normalized_weights().map(|i| i.1), weights.map(|i| i.1));
+        }
+        assert!(matches!(
+            vertices.skins[3],
+            Skin::SDEF {
+                sdef_c: [1.0, 1.0, 1.0],
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn edges_are_set_in_bulk_and_ranged() {
+        let pmx = crate::testing::sample();
+        let mut vertices = pmx.vertices.clone();
+        assert_eq!(vertices.edge_range(), Some((1.0, 3.0)));
+
+        vertices.set_all_edges(0.0);
+        assert_eq!(vertices.edges, [0.0; 5]);
+        assert_eq!(vertices.edge_range(), Some((0.0, 0.0)));
+        let header = Header::from_best(2.1, &pmx);
+        let mut bytes = Vec::new();
+        vertices.write(&header, &mut bytes).unwrap();
+        let read = Vertices::read(&header, &mut bytes.as_slice()).unwrap();
+        assert_eq!(read.edges, [0.0; 5]);
+
+        vertices.edges[2] = f32::NAN;
+        vertices.edges[4] = -1.0;
+        assert_eq!(vertices.edge_range(), Some((-1.0, 0.0)));
+        vertices.edges = vec![f32::NAN];
+        assert_eq!(vertices.edge_range(), None);
+        assert_eq!(Vertices::default().edge_range(), None);
+    }
+}
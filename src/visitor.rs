@@ -0,0 +1,81 @@
+use crate::bone::Bone;
+use crate::display_frame::DisplayFrame;
+use crate::joint::Joint;
+use crate::material::Material;
+use crate::model_info::ModelInfo;
+use crate::morph::Morph;
+use crate::rigid_body::RigidBody;
+use crate::soft_body::SoftBody;
+use crate::vertex::Vertices;
+use crate::VertexIndex;
+
+/// callbacks receiving the parts of a model as they are read, see [`crate::pmx::Pmx::read_visited`].
+///
+/// every callback does nothing by default, so a visitor only implements the ones it needs.
+/// the elements are passed by value with their index in their section
+/// and dropped after the callback unless it keeps them.
+pub trait PmxVisitor {
+    fn on_info(&mut self, _info: ModelInfo) {}
+
+    /// `vertex` is a vertex section holding only the vertex at `index`,
+    /// vertices are stored as parallel arrays
+    fn on_vertex(&mut self, _index: u32, _vertex: Vertices) {}
+
+    /// a vertex index of the triangle list, `index / 3` being the triangle
+    fn on_element(&mut self, _index: u32, _element: VertexIndex) {}
+
+    fn on_texture(&mut self, _index: u32, _texture: String) {}
+
+    fn on_material(&mut self, _index: u32, _material: Material) {}
+
+    fn on_bone(&mut self, _index: u32, _bone: Bone) {}
+
+    fn on_morph(&mut self, _index: u32, _morph: Morph) {}
+
+    fn on_display_frame(&mut self, _index: u32, _display_frame: DisplayFrame) {}
+
+    fn on_rigid_body(&mut self, _index: u32, _rigid_body: RigidBody) {}
+
+    fn on_joint(&mut self, _index: u32, _joint: Joint) {}
+
+    fn on_soft_body(&mut self, _index: u32, _soft_body: SoftBody) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Header;
+    use crate::options::ReadOptions;
+    use crate::pmx::Pmx;
+    use crate::pmx_write_to_vec;
+    use crate::testing::sample;
+
+    /// counts the bones and keeps their names in order
+    #[derive(Default)]
+    struct BoneCounter {
+        count: u32,
+        names: Vec<String>,
+    }
+
+    impl PmxVisitor for BoneCounter {
+        fn on_bone(&mut self, index: u32, bone: Bone) {
+            assert_eq!(index, self.count);
+            self.count += 1;
+            self.names.push(bone.name);
+        }
+    }
+
+    #[test]
+    fn bone_counting_visitor_sees_every_bone() {
+        let pmx = sample();
+        let bytes = pmx_write_to_vec(&pmx, 2.1).unwrap();
+        let mut read = bytes.as_slice();
+        let header = Header::read(&mut read).unwrap();
+        let mut visitor = BoneCounter::default();
+        Pmx::read_visited(&header, &ReadOptions::default(), &mut read, &mut visitor).unwrap();
+        assert!(read.is_empty());
+        assert_eq!(visitor.count, pmx.bones.count());
+        let names: Vec<&str> = pmx.bones.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(visitor.names, names);
+    }
+}